@@ -1,19 +1,22 @@
 #![allow(unused)]
 
-use num::Complex;
+use num_complex::Complex;
+
+/// Once `z` is farther than this from the origin, it is certain to escape.
+const DEFAULT_BAILOUT: f64 = 2.0;
 
 /// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
 /// iterations to decide.
 ///
 /// If `c` is not a member, return `Some(i)`, where `i` is the number of
-/// iterations it took for `c` to leave the circle of radius two centered on the
-/// origin. If `c` seems to be a member (more precisely, if we reached the
-/// iteration limit without being able to prove that `c` is not a member),
-/// return `None`.
+/// iterations it took for `c` to leave the circle of radius `DEFAULT_BAILOUT`
+/// (two) centered on the origin. If `c` seems to be a member (more precisely,
+/// if we reached the iteration limit without being able to prove that `c` is
+/// not a member), return `None`.
 fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
+        if z.norm_sqr() > DEFAULT_BAILOUT * DEFAULT_BAILOUT {
             return Some(i);
         }
         z = z * z + c;
@@ -57,6 +60,7 @@ fn test_parse_pair() {
 
 /// Parse a pair of floating-point numbers separated by a comma as a complex
 /// number.
+#[allow(clippy::manual_map)]
 fn parse_complex(s: &str) -> Option<Complex<f64>> {
     match parse_pair(s, ',') {
         Some((re, im)) => Some(Complex { re, im }),
@@ -115,11 +119,27 @@ fn render(pixels: &mut [u8],
 {
     assert!(pixels.len() == bounds.0 * bounds.1);
 
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row),
+    render_rows(pixels, bounds, 0, upper_left, lower_right);
+}
+
+/// 画像全体（大きさ `bounds`）のうち、`top` 行目から始まる帯 `band` だけを描画する
+///
+/// 点の位置は画像全体の `bounds` と角から求めるので、帯に分けて描いても
+/// `render` で 1 度に描いたものとバイト単位で一致する。
+fn render_rows(band: &mut [u8],
+               bounds: (usize, usize),
+               top: usize,
+               upper_left: Complex<f64>,
+               lower_right: Complex<f64>)
+{
+    let height = band.len() / bounds.0;
+    assert!(band.len() == bounds.0 * height && top + height <= bounds.1);
+
+    for (y, row) in band.chunks_mut(bounds.0).enumerate() {
+        for (column, pixel) in row.iter_mut().enumerate() {
+            let point = pixel_to_point(bounds, (column, top + y),
                                        upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
+            *pixel =
                 match escape_time(point, 255) {
                     None => 0,
                     Some(count) => 255 - count as u8
@@ -128,6 +148,81 @@ fn render(pixels: &mut [u8],
     }
 }
 
+/// 画像を `threads` 本の帯に分け、帯ごとにスレッドを立てて描画する
+///
+/// `threads` が 1 なら `render` と同じ結果になる（帯の数を変えても結果は変わらない）。
+fn render_parallel(pixels: &mut [u8],
+                   bounds: (usize, usize),
+                   upper_left: Complex<f64>,
+                   lower_right: Complex<f64>,
+                   threads: usize)
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(threads >= 1);
+
+    let rows_per_band = bounds.1 / threads + 1;
+    let bands: Vec<&mut [u8]> =
+        pixels.chunks_mut(rows_per_band * bounds.0).collect();
+    crossbeam::scope(|spawner| {
+        for (i, band) in bands.into_iter().enumerate() {
+            let top = rows_per_band * i;
+            spawner.spawn(move |_| {
+                render_rows(band, bounds, top, upper_left, lower_right);
+            });
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_render_parallel_matches_serial() {
+    let bounds = (97, 61);
+    let upper_left = Complex::new(-2.2, 1.2);
+    let lower_right = Complex::new(1.0, -1.2);
+    let mut serial = vec![0; bounds.0 * bounds.1];
+    render(&mut serial, bounds, upper_left, lower_right);
+
+    for threads in [1, 2, 4] {
+        let mut pixels = vec![0; bounds.0 * bounds.1];
+        render_parallel(&mut pixels, bounds, upper_left, lower_right, threads);
+        assert!(pixels == serial, "threads = {}", threads);
+    }
+}
+
+/// `--threads N` を解釈する（指定がなければ使える CPU の数）
+fn parse_threads(args: &[String]) -> Result<usize, String> {
+    let mut threads = None;
+
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--threads" => {
+                let value = rest.next().ok_or("--threads requires a value")?;
+                threads = match value.parse() {
+                    Ok(n) if n >= 1 => Some(n),
+                    _ => return Err(format!("invalid thread count: {}", value)),
+                };
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(threads.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }))
+}
+
+#[test]
+fn test_parse_threads() {
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(parse_threads(&args(&["bands", "--threads", "4"])), Ok(4));
+    assert!(parse_threads(&args(&["bands"])).unwrap() >= 1);
+    assert!(parse_threads(&args(&["bands", "--threads", "0"])).is_err());
+    assert!(parse_threads(&args(&["bands", "--threads", "x"])).is_err());
+    assert!(parse_threads(&args(&["bands", "--threads"])).is_err());
+    assert!(parse_threads(&args(&["bands", "--bogus"])).is_err());
+}
+
 /// ビルドエラーで全面変更
 use image::{ImageBuffer, Luma, ImageError};
 
@@ -162,35 +257,23 @@ fn main() {
     let lower_right = parse_complex(&args[4])
         .expect("error parsing lower right corner point");
 */
+    let args: Vec<String> = env::args().collect();
+    let threads = match parse_threads(&args) {
+        Ok(threads) => threads,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("Usage: {} [--threads N]", args[0]);
+            std::process::exit(1);
+        }
+    };
+
     let start = std::time::Instant::now(); // ★ 計測開始
     let bounds = (1200, 800);
     let upper_left = Complex::new(-2.2, 1.2);
     let lower_right = Complex::new(1.0, -1.2);
     let mut pixels = vec![0; bounds.0 * bounds.1];
 
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
-
-    {
-        let bands: Vec<&mut [u8]> =
-            pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left =
-                    pixel_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right =
-                    pixel_to_point(bounds, (bounds.0, top + height),
-                                   upper_left, lower_right);
-
-                spawner.spawn(move |_| {
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
-            }
-        }).unwrap();
-    }
+    render_parallel(&mut pixels, bounds, upper_left, lower_right, threads);
 
     //    write_image(&args[1], &pixels, bounds)
     write_image("mandelbrot.png", &pixels, bounds)
@@ -0,0 +1,79 @@
+//! 描画関数のベンチマーク（`cargo bench`、SIMD 版は `cargo bench --features simd`）
+//!
+//! 従来の 1200x800・max_iter 200 の範囲を描画し、スループットをピクセル数で報告する
+//! （criterion の `Melem/s` がそのまま「メガピクセル/秒」になる）。
+//! 発散判定だけの比較（`Complex` の反復と実部・虚部を直接扱う `escape_time_fast`）もする。
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mandelbrot_color::{
+    escape_time, escape_time_fast, render_color, render_color_bands, render_color_parallel, render_simd, Fractal,
+    InteriorColor, Palette, Region, Tile, DEFAULT_BAILOUT,
+};
+use num_complex::Complex;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+fn bench_render(c: &mut Criterion) {
+    let bounds = (1200, 800);
+    let max_iter = 200;
+
+    let mut group = c.benchmark_group("render");
+    group.throughput(Throughput::Elements((bounds.0 * bounds.1) as u64));
+    group.sample_size(10);
+
+    type Renderer = fn(&mut [u8], (usize, usize), &Region, u32);
+    let renderers: [(&str, Renderer); 4] = [
+        ("serial", render_color),
+        ("parallel", render_color_parallel),
+        // lockfree の描画と同じく、3 行ずつの帯を 8 スレッドで取り合う
+        ("bands", |pixels, bounds, region, max_iter| {
+            render_color_bands(pixels, bounds, region, &Tile::full(bounds), 3, 8, max_iter,
+                               DEFAULT_BAILOUT * DEFAULT_BAILOUT, Fractal::Mandelbrot, &Palette::Classic,
+                               InteriorColor::Black, &AtomicUsize::new(0), &AtomicBool::new(false));
+        }),
+        // simd フィーチャーが無効ならスカラー版（並列）と同じ計算になる
+        ("simd", render_simd),
+    ];
+
+    for (name, render) in renderers {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        group.bench_function(name, |b| {
+            b.iter(|| render(black_box(&mut pixels), bounds, black_box(&Region::CLASSIC), max_iter))
+        });
+    }
+    group.finish();
+}
+
+fn bench_escape_time(c: &mut Criterion) {
+    let bounds = (300, 200);
+    let max_iter = 200;
+    let region = Region::CLASSIC;
+    let points: Vec<Complex<f64>> = (0..bounds.1)
+        .flat_map(|y| (0..bounds.0).map(move |x| (x, y)))
+        .map(|pixel| region.pixel_to_point(bounds, pixel))
+        .collect();
+
+    let mut group = c.benchmark_group("escape_time");
+    group.throughput(Throughput::Elements(points.len() as u64));
+    group.sample_size(10);
+    group.bench_function("complex", |b| {
+        b.iter(|| {
+            points
+                .iter()
+                .map(|&point| escape_time(black_box(point), max_iter, DEFAULT_BAILOUT * DEFAULT_BAILOUT))
+                .sum::<u32>()
+        })
+    });
+    group.bench_function("fast", |b| {
+        b.iter(|| {
+            points
+                .iter()
+                .map(|&point| escape_time_fast(black_box(point), max_iter, DEFAULT_BAILOUT * DEFAULT_BAILOUT))
+                .sum::<u32>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_render, bench_escape_time);
+criterion_main!(benches);
@@ -0,0 +1,503 @@
+//! 倍々精度（double-double）の浮動小数点数
+//!
+//! 2 つの f64 の和 `hi + lo`（|lo| は hi の最下位桁の半分以下）で約 106 ビット（10 進で 32 桁）の
+//! 仮数を表す。f64 では倍率 1e15 あたりで隣り合うピクセルの座標が同じ値に丸められてしまうが、
+//! この型なら 1e30 程度まで拡大できる。`num_traits::Float` を実装しているので、`escape_time` などの
+//! ジェネリックな関数にそのまま渡せる。
+//!
+//! 四則演算と `sqrt` は倍々精度で計算する。指数・対数・三角関数などは描画の色付けにしか使わないので、
+//! 上位の f64 だけで計算する（結果の精度は f64 と同じ）。
+
+use num_traits::{Float, Num, One, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::num::FpCategory;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+
+/// `hi + lo` で表す倍々精度の浮動小数点数
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+/// a + b を丸め誤差まで含めて (和, 誤差) で返す
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// `two_sum` と同じだが |a| >= |b| のときだけ使える（計算が少ない）
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// a × b を丸め誤差まで含めて (積, 誤差) で返す
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl DoubleDouble {
+    /// 上位 `hi`・下位 `lo` から作る（`hi + lo` を正規化する）
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    /// 上位の f64（f64 に丸めた値）
+    pub fn hi(self) -> f64 {
+        self.hi
+    }
+
+    /// 下位の f64（`hi` との差）
+    pub fn lo(self) -> f64 {
+        self.lo
+    }
+
+    /// 上位の f64 だけで計算する関数を適用する（精度は f64 と同じ）
+    fn map_hi(self, f: impl FnOnce(f64) -> f64) -> DoubleDouble {
+        DoubleDouble::from(f(self.hi))
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(x: f64) -> DoubleDouble {
+        DoubleDouble { hi: x, lo: 0.0 }
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &DoubleDouble) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi)? {
+            Ordering::Equal => self.lo.partial_cmp(&other.lo),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, other.hi);
+        let (t, f) = two_sum(self.lo, other.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        let (hi, lo) = quick_two_sum(s, e + f);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        let (hi, lo) = quick_two_sum(p, e + (self.hi * other.lo + self.lo * other.hi));
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = DoubleDouble;
+
+    /// 上位の f64 で商を見積もり、余りから 2 回補正する
+    fn div(self, other: DoubleDouble) -> DoubleDouble {
+        let q1 = self.hi / other.hi;
+        if !q1.is_finite() {
+            return DoubleDouble::from(q1);
+        }
+        let r = self - other * DoubleDouble::from(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other * DoubleDouble::from(q2);
+        let q3 = r.hi / other.hi;
+
+        let (hi, lo) = quick_two_sum(q1, q2);
+        DoubleDouble { hi, lo } + DoubleDouble::from(q3)
+    }
+}
+
+impl Rem for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn rem(self, other: DoubleDouble) -> DoubleDouble {
+        self - other * (self / other).trunc()
+    }
+}
+
+impl Zero for DoubleDouble {
+    fn zero() -> DoubleDouble {
+        DoubleDouble::from(0.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble {
+    fn one() -> DoubleDouble {
+        DoubleDouble::from(1.0)
+    }
+}
+
+impl Num for DoubleDouble {
+    type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+
+    /// f64 として読む（10 進で 17 桁より細かい桁は丸められる）
+    fn from_str_radix(s: &str, radix: u32) -> Result<DoubleDouble, Self::FromStrRadixErr> {
+        f64::from_str_radix(s, radix).map(DoubleDouble::from)
+    }
+}
+
+impl ToPrimitive for DoubleDouble {
+    fn to_i64(&self) -> Option<i64> {
+        let t = self.trunc();
+        t.hi.to_i64()?.checked_add(t.lo.to_i64()?)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        u64::try_from(self.to_i128()?).ok()
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        let t = self.trunc();
+        t.hi.to_i128()?.checked_add(t.lo.to_i128()?)
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.hi)
+    }
+}
+
+// `NumCast::from` は `From<f64>::from` と名前がぶつかるので、トレイトは取り込まずに実装する（中では `From` を明示する）
+impl num_traits::NumCast for DoubleDouble {
+    fn from<N: ToPrimitive>(n: N) -> Option<DoubleDouble> {
+        n.to_f64().map(<DoubleDouble as From<f64>>::from)
+    }
+}
+
+impl Float for DoubleDouble {
+    fn nan() -> DoubleDouble {
+        DoubleDouble::from(f64::NAN)
+    }
+
+    fn infinity() -> DoubleDouble {
+        DoubleDouble::from(f64::INFINITY)
+    }
+
+    fn neg_infinity() -> DoubleDouble {
+        DoubleDouble::from(f64::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> DoubleDouble {
+        DoubleDouble::from(-0.0)
+    }
+
+    fn min_value() -> DoubleDouble {
+        DoubleDouble::from(f64::MIN)
+    }
+
+    fn min_positive_value() -> DoubleDouble {
+        DoubleDouble::from(f64::MIN_POSITIVE)
+    }
+
+    /// 1 と、1 より大きい最小の数との差（2⁻¹⁰⁴）
+    fn epsilon() -> DoubleDouble {
+        DoubleDouble::from(f64::EPSILON * f64::EPSILON)
+    }
+
+    fn max_value() -> DoubleDouble {
+        DoubleDouble::from(f64::MAX)
+    }
+
+    fn is_nan(self) -> bool {
+        self.hi.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.hi.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.hi.is_finite()
+    }
+
+    fn is_normal(self) -> bool {
+        self.hi.is_normal()
+    }
+
+    fn classify(self) -> FpCategory {
+        self.hi.classify()
+    }
+
+    fn floor(self) -> DoubleDouble {
+        let hi = self.hi.floor();
+        if hi == self.hi {
+            DoubleDouble::new(hi, self.lo.floor())
+        } else {
+            DoubleDouble::from(hi)
+        }
+    }
+
+    fn ceil(self) -> DoubleDouble {
+        let hi = self.hi.ceil();
+        if hi == self.hi {
+            DoubleDouble::new(hi, self.lo.ceil())
+        } else {
+            DoubleDouble::from(hi)
+        }
+    }
+
+    /// 0.5 ちょうどは 0 から遠い方へ丸める（f64 の `round` と同じ）
+    fn round(self) -> DoubleDouble {
+        let half = DoubleDouble::from(0.5);
+        if self.is_sign_negative() {
+            (self - half).ceil()
+        } else {
+            (self + half).floor()
+        }
+    }
+
+    fn trunc(self) -> DoubleDouble {
+        if self.is_sign_negative() {
+            self.ceil()
+        } else {
+            self.floor()
+        }
+    }
+
+    fn fract(self) -> DoubleDouble {
+        self - self.trunc()
+    }
+
+    fn abs(self) -> DoubleDouble {
+        if self.is_sign_negative() {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> DoubleDouble {
+        DoubleDouble::from(self.hi.signum())
+    }
+
+    fn is_sign_positive(self) -> bool {
+        !self.is_sign_negative()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.hi.is_sign_negative() || (self.hi == 0.0 && self.lo.is_sign_negative())
+    }
+
+    fn mul_add(self, a: DoubleDouble, b: DoubleDouble) -> DoubleDouble {
+        self * a + b
+    }
+
+    fn recip(self) -> DoubleDouble {
+        DoubleDouble::one() / self
+    }
+
+    fn powi(self, n: i32) -> DoubleDouble {
+        // 2 乗を繰り返す（倍々精度のまま）
+        let mut base = self;
+        let mut result = DoubleDouble::one();
+        let mut e = n.unsigned_abs();
+        while e > 0 {
+            if e & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            e >>= 1;
+        }
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+
+    fn powf(self, n: DoubleDouble) -> DoubleDouble {
+        self.map_hi(|x| x.powf(n.hi))
+    }
+
+    /// 上位の f64 の平方根を、余りから 1 回ニュートン法で補正する
+    fn sqrt(self) -> DoubleDouble {
+        if self.hi <= 0.0 || !self.hi.is_finite() {
+            return self.map_hi(f64::sqrt);
+        }
+        let q = self.hi.sqrt();
+        let (p, e) = two_prod(q, q);
+        let r = self - DoubleDouble { hi: p, lo: e };
+        DoubleDouble::new(q, r.hi / (2.0 * q))
+    }
+
+    fn exp(self) -> DoubleDouble {
+        self.map_hi(f64::exp)
+    }
+
+    fn exp2(self) -> DoubleDouble {
+        self.map_hi(f64::exp2)
+    }
+
+    fn ln(self) -> DoubleDouble {
+        self.map_hi(f64::ln)
+    }
+
+    fn log(self, base: DoubleDouble) -> DoubleDouble {
+        self.map_hi(|x| x.log(base.hi))
+    }
+
+    fn log2(self) -> DoubleDouble {
+        self.map_hi(f64::log2)
+    }
+
+    fn log10(self) -> DoubleDouble {
+        self.map_hi(f64::log10)
+    }
+
+    fn max(self, other: DoubleDouble) -> DoubleDouble {
+        if self.is_nan() || self < other {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn min(self, other: DoubleDouble) -> DoubleDouble {
+        if self.is_nan() || self > other {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn abs_sub(self, other: DoubleDouble) -> DoubleDouble {
+        if self > other {
+            self - other
+        } else {
+            DoubleDouble::zero()
+        }
+    }
+
+    fn cbrt(self) -> DoubleDouble {
+        self.map_hi(f64::cbrt)
+    }
+
+    fn hypot(self, other: DoubleDouble) -> DoubleDouble {
+        (self * self + other * other).sqrt()
+    }
+
+    fn sin(self) -> DoubleDouble {
+        self.map_hi(f64::sin)
+    }
+
+    fn cos(self) -> DoubleDouble {
+        self.map_hi(f64::cos)
+    }
+
+    fn tan(self) -> DoubleDouble {
+        self.map_hi(f64::tan)
+    }
+
+    fn asin(self) -> DoubleDouble {
+        self.map_hi(f64::asin)
+    }
+
+    fn acos(self) -> DoubleDouble {
+        self.map_hi(f64::acos)
+    }
+
+    fn atan(self) -> DoubleDouble {
+        self.map_hi(f64::atan)
+    }
+
+    fn atan2(self, other: DoubleDouble) -> DoubleDouble {
+        self.map_hi(|y| y.atan2(other.hi))
+    }
+
+    fn sin_cos(self) -> (DoubleDouble, DoubleDouble) {
+        (self.sin(), self.cos())
+    }
+
+    fn exp_m1(self) -> DoubleDouble {
+        self.map_hi(f64::exp_m1)
+    }
+
+    fn ln_1p(self) -> DoubleDouble {
+        self.map_hi(f64::ln_1p)
+    }
+
+    fn sinh(self) -> DoubleDouble {
+        self.map_hi(f64::sinh)
+    }
+
+    fn cosh(self) -> DoubleDouble {
+        self.map_hi(f64::cosh)
+    }
+
+    fn tanh(self) -> DoubleDouble {
+        self.map_hi(f64::tanh)
+    }
+
+    fn asinh(self) -> DoubleDouble {
+        self.map_hi(f64::asinh)
+    }
+
+    fn acosh(self) -> DoubleDouble {
+        self.map_hi(f64::acosh)
+    }
+
+    fn atanh(self) -> DoubleDouble {
+        self.map_hi(f64::atanh)
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8) {
+        Float::integer_decode(self.hi)
+    }
+}
+
+#[test]
+fn test_double_double_arithmetic() {
+    let dd = |x: f64| DoubleDouble::from(x);
+    let third = dd(1.0) / dd(3.0);
+
+    // 1/3 × 3 は f64 の丸め誤差よりずっと 1 に近い
+    assert!((third * dd(3.0) - dd(1.0)).abs() < dd(1e-30));
+    assert!(third.lo() != 0.0);
+
+    // 1 + 2⁻⁷⁰ は f64 では 1 に丸められるが、倍々精度では区別できる
+    let tiny = dd(2f64.powi(-70));
+    let sum = dd(1.0) + tiny;
+    assert!(sum > dd(1.0));
+    assert_eq!(sum - dd(1.0), tiny);
+    assert_eq!((sum * sum - dd(1.0)).hi(), 2.0 * tiny.hi());
+
+    assert!((dd(2.0).sqrt() * dd(2.0).sqrt() - dd(2.0)).abs() < dd(1e-30));
+    assert_eq!(dd(-2.5).floor(), dd(-3.0));
+    assert_eq!(dd(-2.5).trunc(), dd(-2.0));
+    assert_eq!(dd(2.5).round(), dd(3.0));
+    assert_eq!(dd(3.0).powi(-2), dd(1.0) / dd(9.0));
+    assert_eq!(dd(7.0) % dd(3.0), dd(1.0));
+    assert_eq!(<DoubleDouble as num_traits::NumCast>::from(200u32), Some(dd(200.0)));
+    assert_eq!(sum.to_f64(), Some(1.0));
+    assert_eq!(dd(-7.9).to_i64(), Some(-7));
+}
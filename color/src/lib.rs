@@ -0,0 +1,4562 @@
+//! マンデルブロ集合・ジュリア集合の描画
+//!
+//! 複素平面上の範囲（`Region`）から反復回数を求め、RGB のピクセルバッファに色を付ける。
+//! コマンドラインツール（main.rs）とベンチマーク（benches/render.rs）から使う。
+
+use image::ImageError;
+use mandelbrot_lockfree::AtomicChunksMut;
+use num_complex::Complex;
+use num_traits::Float;
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "high-precision")]
+pub mod double_double;
+
+// ファイルの読み書きは WebAssembly 向けのビルドでは使えない
+#[cfg(not(target_arch = "wasm32"))]
+mod output;
+#[cfg(not(target_arch = "wasm32"))]
+pub use output::*;
+
+/// 描画するフラクタル
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    /// z₀ = 0, c = 各ピクセルの点
+    Mandelbrot,
+    /// z₀ = 各ピクセルの点, c = 固定の定数
+    Julia(Complex<f64>),
+    /// z₀ = 0, c = 各ピクセルの点で、z を 2 乗する前に実部・虚部の絶対値をとる
+    BurningShip,
+    /// z₀ = 0, c = 各ピクセルの点で、z ← z^d + c（d >= 2。d = 2 ならマンデルブロ集合と同じ）
+    ///
+    /// d < 2 を弾くには `Fractal::multibrot` で作る。
+    Multibrot(u32),
+}
+
+/// 1 点の反復の結果（`Fractal::escape_time_full`）
+///
+/// 色付けの方式（回数・ポテンシャル・滑らかな回数・内部の |z| の濃淡）はどれもこれから求める。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscapeResult<T = f64> {
+    /// 発散した回数（`max_iter` 回までに発散しなかった内部の点なら `None`）
+    pub count: Option<u32>,
+    /// 反復を終えたときの z（発散した点なら発散半径を超えた後の値）
+    pub final_z: Complex<T>,
+    /// 繰り返した回数（発散した点なら `count` と同じ、内部の点なら `max_iter`）
+    pub iterations: u32,
+}
+
+impl Fractal {
+    /// 次数 `d` のマルチブロ集合（d < 2 はエラー。d = 1 では z が c ずつ増えるだけで、ポテンシャルや
+    /// 滑らかな回数の log d が 0 以下になる）
+    pub fn multibrot(d: u32) -> Result<Fractal, String> {
+        if d < 2 {
+            return Err(format!("multibrot power must be at least 2: {}", d));
+        }
+        Ok(Fractal::Multibrot(d))
+    }
+
+    /// ピクセルの点 `point` の反復回数（`bailout_sqr` は発散半径の 2 乗）
+    pub fn escape_time<T: Float>(self, point: Complex<T>, max_iter: u32, bailout_sqr: f64) -> u32 {
+        self.escape_time_full(point, max_iter, bailout_sqr).iterations
+    }
+
+    /// ピクセルの点 `point` の反復の結果（反復のループはすべてここを通る）
+    pub fn escape_time_full<T: Float>(self, point: Complex<T>, max_iter: u32, bailout_sqr: f64)
+        -> EscapeResult<T>
+    {
+        let bailout_sqr = T::from(bailout_sqr).unwrap();
+        let (iterations, final_z) = match self {
+            Fractal::Mandelbrot => escape_time_formula_final(&Mandelbrot, point, max_iter, bailout_sqr),
+            Fractal::Julia(c) => escape_time_formula_final(&Julia(c), point, max_iter, bailout_sqr),
+            Fractal::BurningShip => escape_time_formula_final(&BurningShip, point, max_iter, bailout_sqr),
+            Fractal::Multibrot(d) => escape_time_formula_final(&Multibrot(d), point, max_iter, bailout_sqr),
+        };
+        EscapeResult { count: (iterations < max_iter).then_some(iterations), final_z, iterations }
+    }
+
+    /// 実軸に対して対称に描かれるか（c と z₀ を複素共役にすると軌道も複素共役になるか）
+    ///
+    /// 反復式の係数がすべて実数なら対称。ジュリア集合は定数が実数のときだけ、
+    /// バーニングシップは絶対値で折り返すので対称にならない。
+    pub fn is_symmetric(self) -> bool {
+        match self {
+            Fractal::Mandelbrot | Fractal::Multibrot(_) => true,
+            Fractal::Julia(c) => c.im == 0.0,
+            Fractal::BurningShip => false,
+        }
+    }
+
+    /// ピクセルの点 `point` の反復回数と、反復を終えたときの z
+    pub fn escape_time_final<T: Float>(self, point: Complex<T>, max_iter: u32, bailout_sqr: f64)
+        -> (u32, Complex<T>)
+    {
+        let result = self.escape_time_full(point, max_iter, bailout_sqr);
+        (result.iterations, result.final_z)
+    }
+
+    /// ピクセルの点 `point` の反復回数と、軌道がトラップ（原点）に最も近づいた距離
+    pub fn escape_time_trap(self, point: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> (u32, f64) {
+        match self {
+            Fractal::Mandelbrot => escape_time_trap_formula(&Mandelbrot, point, max_iter, bailout_sqr),
+            Fractal::Julia(c) => escape_time_trap_formula(&Julia(c), point, max_iter, bailout_sqr),
+            Fractal::BurningShip => escape_time_trap_formula(&BurningShip, point, max_iter, bailout_sqr),
+            Fractal::Multibrot(d) => escape_time_trap_formula(&Multibrot(d), point, max_iter, bailout_sqr),
+        }
+    }
+
+    /// ピクセルの点 `point` の連続ポテンシャル ln|z|² / dⁿ（集合の内部なら `None`）
+    ///
+    /// n は発散するまでの反復回数、d は z の次数（マルチブロ集合以外は 2）。n が 1000 を超えるあたりから 0 に
+    /// アンダーフローするので、色を付けるときは `escape_time_log_potential` を使う。
+    pub fn escape_time_potential(self, point: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+        self.escape_time_log_potential(point, max_iter, bailout_sqr).map(f64::exp)
+    }
+
+    /// `escape_time_potential` の自然対数 ln(ln|z|²) - n·ln d（集合の内部なら `None`）
+    ///
+    /// 対数のまま計算するので、反復回数が多くてもアンダーフローしない。
+    pub fn escape_time_log_potential(self, point: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+        let result = self.escape_time_full(point, max_iter, bailout_sqr);
+        let (iter, z) = (result.count?, result.final_z);
+        let degree = match self {
+            Fractal::Multibrot(d) => d as f64,
+            _ => 2.0,
+        };
+        Some(z.norm_sqr().ln().ln() - iter as f64 * degree.ln())
+    }
+
+    /// ピクセルの点 `point` の滑らかな反復回数 n - log_d(ln|z| / ln R)（集合の内部なら `None`）
+    ///
+    /// n は発散するまでの反復回数、z は発散半径 R を超えた後の値、d は z の次数。|z| がちょうど R なら n で、
+    /// 1 回早く発散する点では |z| がおよそ R^d になって補正がちょうど 1 になるので、回数の帯の境目でつながる。
+    /// 超える前の z を使うとこの補正がずれ、境目に細い暗い輪が出る。R が大きいほど c の分のずれが小さい。
+    pub fn escape_time_smooth(self, point: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+        let result = self.escape_time_full(point, max_iter, bailout_sqr);
+        let (iter, z) = (result.count?, result.final_z);
+        let degree = match self {
+            Fractal::Multibrot(d) => d as f64,
+            _ => 2.0,
+        };
+        Some(iter as f64 - (z.norm_sqr().ln() / bailout_sqr.ln()).ln() / degree.ln())
+    }
+
+    /// ピクセルの点 `point` から集合の境界までの推定距離（集合の内部なら `None`）
+    pub fn escape_time_distance(self, point: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+        match self {
+            Fractal::Mandelbrot => escape_time_distance_mandelbrot(point, max_iter, bailout_sqr),
+            Fractal::Julia(c) => escape_time_distance_julia(point, c, max_iter, bailout_sqr),
+            Fractal::BurningShip => escape_time_distance_burning_ship(point, max_iter, bailout_sqr),
+            Fractal::Multibrot(d) => escape_time_distance_multibrot(point, d, max_iter, bailout_sqr),
+        }
+    }
+}
+
+/// 発散半径の既定値（反復回数を数えるだけならこれで足りる）
+///
+/// |z| が 2 を超えた点は必ず発散するので、これより小さくはできない。
+pub const DEFAULT_BAILOUT: f64 = 2.0;
+
+/// 距離推定で使う発散半径の既定値
+///
+/// |z|·ln|z| / |dz| は |z| が大きいほど正確になるので、`DEFAULT_BAILOUT` より大きくとる。
+pub const DISTANCE_BAILOUT: f64 = 4.0;
+
+/// 連続ポテンシャルで使う発散半径の既定値
+///
+/// ln|z|² / 2ⁿ は |z| が十分大きいときの近似なので、ずっと大きくとる。
+pub const POTENTIAL_BAILOUT: f64 = 1000.0;
+
+/// `Complex<f64>` を `Complex<T>` に変換する
+pub fn complex_as<T: Float>(z: Complex<f64>) -> Complex<T> {
+    Complex::new(T::from(z.re).unwrap(), T::from(z.im).unwrap())
+}
+
+/// `"400x600"` や `"1.0,0.5"` のような文字列を区切り文字 `separator` で分けてペアとして解釈する
+///
+/// 左右それぞれの前後の空白は無視するので、`"-1.20, 0.35"` のように書いてもよい。
+///
+/// 浮動小数点数のペアには、数値の書き方に現れない `x` `,` `:` `/` などを区切り文字に使うのが安全で、
+/// `"1.5e-9x1e-9"` のような指数表記もそのまま読める。`-` `+` `e` など数値の中にも現れる文字を
+/// 区切り文字にした場合は、その文字の位置を前から順に試し、左右とも解釈できた最初の位置で分ける。
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+    s.match_indices(separator).find_map(|(index, sep)| {
+        match (T::from_str(s[..index].trim()), T::from_str(s[index + sep.len()..].trim())) {
+            (Ok(l), Ok(r)) => Some((l, r)),
+            _ => None
+        }
+    })
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("",        ','), None);
+    assert_eq!(parse_pair::<i32>("10,",     ','), None);
+    assert_eq!(parse_pair::<i32>(",10",     ','), None);
+    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
+    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+
+    // 各要素の前後の空白は無視する
+    assert_eq!(parse_pair::<i32>("10, 20",     ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>(" 10 , 20 ",  ','), Some((10, 20)));
+    assert_eq!(parse_pair::<f64>("-1.20, 0.35", ','), Some((-1.20, 0.35)));
+    assert_eq!(parse_pair::<i32>("1 0,20",     ','), None);
+    assert_eq!(parse_pair::<i32>("10, ",       ','), None);
+
+    // 指数表記（区切り文字と衝突しない x を使う）
+    assert_eq!(parse_pair::<f64>("1.5e-9x1e-9",   'x'), Some((1.5e-9, 1e-9)));
+    assert_eq!(parse_pair::<f64>("1.5E+3x2.5e3",  'x'), Some((1500.0, 2500.0)));
+    assert_eq!(parse_pair::<f64>("-7.5e-10,3e-11", ','), Some((-7.5e-10, 3e-11)));
+    assert_eq!(parse_pair::<f64>("1.5e-9x",       'x'), None);
+
+    // 数値の中にも現れる区切り文字でも、両側が読める位置で分ける
+    assert_eq!(parse_pair::<f64>("1e-9-2e-9", '-'), Some((1e-9, 2e-9)));
+    assert_eq!(parse_pair::<f64>("2e3e1",     'e'), Some((2.0, 3e1))); // 最初に読める位置
+    assert_eq!(parse_pair::<f64>("1e-9-",     '-'), None);
+
+    // 複数バイトの区切り文字
+    assert_eq!(parse_pair::<usize>("1200×800", '×'), Some((1200, 800)));
+}
+
+/// `"RE,IM"` 形式の文字列を複素数として解釈する
+pub fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    parse_pair(s, ',').map(|(re, im)| Complex::new(re, im))
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("1.25,-0.0625"), Some(Complex::new(1.25, -0.0625)));
+    assert_eq!(parse_complex(",-0.0625"), None);
+}
+
+/// 引数の解析・画像の書き出しで起きるエラー
+pub enum RenderError {
+    /// 画像の大きさが `WIDTHxHEIGHT` の形になっていない
+    ParseBounds(String),
+    /// 複素数が `RE,IM` の形になっていない（`what` は何を表す値か）
+    ParseComplex { what: &'static str, text: String },
+    /// ピクセルバッファの長さが画像の大きさと合わない（単位はバイト）
+    BufferMismatch { expected: usize, actual: usize },
+    /// 画像ファイルの読み書きに失敗した
+    Io(ImageError),
+    /// その他のコマンドライン引数の誤り
+    InvalidArgument(String),
+    /// 画像に埋め込んだ描画条件が読めない
+    Metadata(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::ParseBounds(text) => write!(f, "error parsing image dimensions: {}", text),
+            RenderError::ParseComplex { what, text } => write!(f, "error parsing {}: {}", what, text),
+            RenderError::BufferMismatch { expected, actual } => {
+                write!(f, "buffer size mismatch: expected {} bytes, got {}", expected, actual)
+            }
+            RenderError::Io(err) => write!(f, "error writing image: {}", err),
+            RenderError::InvalidArgument(message) => write!(f, "{}", message),
+            RenderError::Metadata(message) => write!(f, "error reading image metadata: {}", message),
+        }
+    }
+}
+
+// `main` が Err を返すと Debug で表示されるので、Display と同じ文面にする
+impl fmt::Debug for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for RenderError {
+    fn from(err: ImageError) -> Self {
+        RenderError::Io(err)
+    }
+}
+
+impl From<std::io::Error> for RenderError {
+    fn from(err: std::io::Error) -> Self {
+        RenderError::Io(ImageError::IoError(err))
+    }
+}
+
+impl From<png::EncodingError> for RenderError {
+    fn from(err: png::EncodingError) -> Self {
+        match err {
+            png::EncodingError::IoError(err) => err.into(),
+            err => std::io::Error::other(err).into(),
+        }
+    }
+}
+
+impl From<exr::error::Error> for RenderError {
+    fn from(err: exr::error::Error) -> Self {
+        match err {
+            exr::error::Error::Io(err) => err.into(),
+            err => std::io::Error::other(err).into(),
+        }
+    }
+}
+
+impl From<String> for RenderError {
+    fn from(message: String) -> Self {
+        RenderError::InvalidArgument(message)
+    }
+}
+
+impl From<&str> for RenderError {
+    fn from(message: &str) -> Self {
+        RenderError::InvalidArgument(message.to_string())
+    }
+}
+
+#[test]
+fn test_render_error_display() {
+    assert_eq!(RenderError::ParseBounds("1200by800".to_string()).to_string(),
+               "error parsing image dimensions: 1200by800");
+    assert_eq!(RenderError::ParseComplex { what: "center point", text: "-0.75".to_string() }.to_string(),
+               "error parsing center point: -0.75");
+    let mismatch = || RenderError::BufferMismatch { expected: 36, actual: 35 };
+    assert_eq!(mismatch().to_string(), "buffer size mismatch: expected 36 bytes, got 35");
+    let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+    assert_eq!(RenderError::from(io).to_string(), "error writing image: no such file");
+    assert_eq!(RenderError::from("unknown argument: --bogus").to_string(), "unknown argument: --bogus");
+    assert_eq!(RenderError::Metadata("out.png: missing bounds".to_string()).to_string(),
+               "error reading image metadata: out.png: missing bounds");
+
+    // Debug（main から返したときの表示）も同じ文面
+    assert_eq!(format!("{:?}", mismatch()), mismatch().to_string());
+}
+
+/// 複素平面上の表示範囲（画像の左上・右下の角に対応する 2 点）
+///
+/// 2 点をばらばらに渡すと取り違えやすいので、描画関数にはこの型で渡す。
+/// 計算は型 `T` の座標で行うので、f32 で描くときは `cast` で変換する。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region<T = f64> {
+    pub upper_left: Complex<T>,
+    pub lower_right: Complex<T>,
+}
+
+impl<T: Float> Region<T> {
+    /// 実部方向の幅
+    pub fn width(&self) -> T {
+        self.lower_right.re - self.upper_left.re
+    }
+
+    /// 虚部方向の高さ
+    pub fn height(&self) -> T {
+        self.upper_left.im - self.lower_right.im
+    }
+
+    /// ピクセル座標 → 複素平面上の点（`pixel_to_point` と同じ）
+    pub fn pixel_to_point(&self, bounds: (usize, usize), pixel: (usize, usize)) -> Complex<T> {
+        pixel_to_point(bounds, pixel, self.upper_left, self.lower_right)
+    }
+
+    /// `Region::from_center_zoom` と同じだが、角の座標を型 `T` で計算する
+    ///
+    /// f64 の範囲を `cast` すると、深く拡大したときに角の座標が丸められて幅がつぶれてしまう。
+    /// 中心と幅を先に `T` に変換してから足し引きすれば、`T` の精度まで範囲を保てる。
+    pub fn from_center_zoom_as(bounds: (usize, usize), center: Complex<f64>, zoom: f64) -> Region<T> {
+        // 原点を中心にすれば、幅と高さは f64 でも丸められない
+        let size = Region::<f64>::from_center_zoom(bounds, Complex::new(0.0, 0.0), zoom);
+        let half = |length: f64| T::from(length / 2.0).unwrap();
+        let (half_width, half_height) = (half(size.width()), half(size.height()));
+        let center: Complex<T> = complex_as(center);
+        Region {
+            upper_left: Complex::new(center.re - half_width, center.im + half_height),
+            lower_right: Complex::new(center.re + half_width, center.im - half_height),
+        }
+    }
+
+    /// 実軸に対して対称な範囲か（`upper_left.im == -lower_right.im`）
+    pub fn is_symmetric(&self) -> bool {
+        self.upper_left.im == -self.lower_right.im
+    }
+
+    /// 大きさ `bounds` の画像のピクセル → 点の対応（描画のループで使う）
+    fn grid(&self, bounds: (usize, usize)) -> PixelGrid<T> {
+        let as_t = |n: usize| T::from(n).unwrap();
+        PixelGrid {
+            upper_left: self.upper_left,
+            step: (self.width() / as_t(bounds.0), self.height() / as_t(bounds.1)),
+            mirror_rows: self.is_symmetric().then_some(bounds.1),
+        }
+    }
+}
+
+impl Region {
+    /// 従来の表示範囲（zoom 1.0 でこの範囲全体が収まる）
+    pub const CLASSIC: Region = Region {
+        upper_left: Complex::new(-2.2, 1.2),
+        lower_right: Complex::new(1.0, -1.2),
+    };
+
+    /// バーニングシップの「船」が見える範囲（中心 -1.8-0.08i、縦横比は `CLASSIC` と同じ 3:2）
+    ///
+    /// 虚部が上向きなので、船は上下逆さまに写る。
+    pub const BURNING_SHIP: Region = Region {
+        upper_left: Complex::new(-1.92, 0.0),
+        lower_right: Complex::new(-1.68, -0.16),
+    };
+
+    /// 左上・右下の 2 点から作る
+    ///
+    /// `upper_left` が `lower_right` より右（`re >=`）または下（`im <=`）にあるときはエラー。
+    pub fn from_corners(upper_left: Complex<f64>, lower_right: Complex<f64>) -> Result<Region, String> {
+        if upper_left.re >= lower_right.re || upper_left.im <= lower_right.im {
+            return Err(format!(
+                "upper left corner {},{} must be above and to the left of lower right corner {},{}",
+                upper_left.re, upper_left.im, lower_right.re, lower_right.im));
+        }
+        Ok(Region { upper_left, lower_right })
+    }
+
+    /// 中心点と倍率から作る
+    ///
+    /// zoom 1.0 では従来の範囲（-2.2..1.0 × -1.2..1.2）全体が収まり、zoom を 2 倍にすると
+    /// 幅が半分になる。縦横の比は `bounds` に合わせるので、ピクセルは正方形になる。
+    pub fn from_center_zoom(bounds: (usize, usize), center: Complex<f64>, zoom: f64) -> Region {
+        let aspect = bounds.0 as f64 / bounds.1 as f64;
+        let width = Region::CLASSIC.width().max(Region::CLASSIC.height() * aspect) / zoom;
+
+        Region::around(center, width, width / aspect)
+    }
+
+    /// 中心が `center`、幅と高さが `width` × `height` の範囲
+    fn around(center: Complex<f64>, width: f64, height: f64) -> Region {
+        Region {
+            upper_left: Complex::new(center.re - width / 2.0, center.im + height / 2.0),
+            lower_right: Complex::new(center.re + width / 2.0, center.im - height / 2.0),
+        }
+    }
+
+    /// 範囲の中心
+    pub fn center(&self) -> Complex<f64> {
+        (self.upper_left + self.lower_right) / 2.0
+    }
+
+    /// 複素平面上の点 → ピクセル座標（`point_to_pixel` と同じ）
+    pub fn point_to_pixel(&self, bounds: (usize, usize), point: Complex<f64>) -> (f64, f64) {
+        point_to_pixel(bounds, point, self)
+    }
+
+    /// 縦横比を画像の縦横比 `bounds.0 / bounds.1` に合わせた範囲
+    ///
+    /// 実部か虚部のどちらか足りない方の幅を広げるだけなので、元の範囲は必ず収まる。
+    /// 範囲の中心は変わらない。
+    pub fn aspect_correct(&self, bounds: (usize, usize)) -> Region {
+        let (mut width, mut height) = (self.width(), self.height());
+        let aspect = bounds.0 as f64 / bounds.1 as f64;
+
+        if width / height < aspect {
+            width = height * aspect;
+        } else {
+            height = width / aspect;
+        }
+
+        Region::around(self.center(), width, height)
+    }
+
+    /// 大きさ `bounds` の画像を縦 `rows` × 横 `cols` のタイルに分け、(タイル, タイルが受け持つ範囲) を行優先で並べる
+    ///
+    /// 割り切れないときは、上・左のタイルから 1 ピクセルずつ大きくする。タイルは隙間も重なりもなく
+    /// 画像全体を覆い、範囲は `Tile::region` と同じなので、タイルごとに描いて並べれば 1 度に描いたものと
+    /// 同じ点になる。`rows`・`cols` が 0 なら 1、画像より大きければ 1 ピクセルずつに分ける。
+    pub fn split(&self, bounds: (usize, usize), rows: usize, cols: usize) -> Vec<(Tile, Region)> {
+        // 長さ `length` を `parts` 個に分けたときの (始まり, 長さ)
+        let spans = |length: usize, parts: usize| {
+            let parts = parts.clamp(1, length.max(1));
+            let (base, extra) = (length / parts, length % parts);
+            (0..parts).map(|i| (i * base + i.min(extra), base + usize::from(i < extra))).collect::<Vec<_>>()
+        };
+
+        let (columns, rows) = (spans(bounds.0, cols), spans(bounds.1, rows));
+        rows.iter()
+            .flat_map(|&(top, height)| columns.iter().map(move |&(left, width)| Tile {
+                size: (width, height),
+                offset: (left, top),
+            }))
+            .map(|tile| (tile, tile.region(bounds, self)))
+            .collect()
+    }
+
+    /// 座標を型 `T` に変換する
+    pub fn cast<T: Float>(&self) -> Region<T> {
+        Region {
+            upper_left: complex_as(self.upper_left),
+            lower_right: complex_as(self.lower_right),
+        }
+    }
+}
+
+#[test]
+fn test_region_from_corners() {
+    let region = Region::from_corners(Complex::new(-2.2, 1.2), Complex::new(1.0, -1.2)).unwrap();
+    assert_eq!(region, Region::CLASSIC);
+    assert!((region.width() - 3.2).abs() < 1e-12);
+    assert!((region.height() - 2.4).abs() < 1e-12);
+
+    // 左右・上下の取り違えや、幅か高さが 0 の範囲は受け付けない
+    assert!(Region::from_corners(Complex::new(1.0, -1.2), Complex::new(-2.2, 1.2)).is_err());
+    assert!(Region::from_corners(Complex::new(1.0, 1.2), Complex::new(-2.2, -1.2)).is_err());
+    assert!(Region::from_corners(Complex::new(-2.2, -1.2), Complex::new(1.0, 1.2)).is_err());
+    assert!(Region::from_corners(Complex::new(0.0, 1.0), Complex::new(0.0, -1.0)).is_err());
+    assert!(Region::from_corners(Complex::new(-1.0, 0.5), Complex::new(1.0, 0.5)).is_err());
+}
+
+#[test]
+fn test_region_pixel_to_point() {
+    let bounds = (100, 200);
+    let region = Region::from_corners(Complex::new(-1.0, 1.0), Complex::new(1.0, -1.0)).unwrap();
+    assert_eq!(region.pixel_to_point(bounds, (25, 175)), Complex::new(-0.5, -0.75));
+
+    for &pixel in &[(0, 0), (99, 199), (50, 100), (13, 77)] {
+        assert_eq!(region.pixel_to_point(bounds, pixel),
+                   pixel_to_point(bounds, pixel, region.upper_left, region.lower_right));
+        assert_eq!(region.cast::<f32>().pixel_to_point(bounds, pixel),
+                   pixel_to_point::<f32>(bounds, pixel, complex_as(region.upper_left),
+                                         complex_as(region.lower_right)));
+    }
+}
+
+#[test]
+fn test_aspect_correct() {
+    let bounds = (1200, 800);
+
+    // 3.2 × 2.4 の範囲は 1200x800 には縦長すぎるので、実部が広がる
+    let region = Region::CLASSIC.aspect_correct(bounds);
+    assert!((region.width() / region.height() - 1.5).abs() < 1e-12);
+    assert!((region.height() - 2.4).abs() < 1e-12);
+    assert!((region.center() - Complex::new(-0.6, 0.0)).norm() < 1e-12);
+
+    // 横長すぎる範囲なら虚部が広がる
+    let region = Region::from_corners(Complex::new(-2.0, 0.5), Complex::new(2.0, -0.5))
+        .unwrap()
+        .aspect_correct(bounds);
+    assert!((region.width() / region.height() - 1.5).abs() < 1e-12);
+    assert!((region.width() - 4.0).abs() < 1e-12);
+    assert!(region.center().norm() < 1e-12);
+}
+
+#[test]
+fn test_region_from_center_zoom() {
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+
+    // 4:3 の画像なら zoom 1.0 で従来の範囲そのものになる
+    let region = Region::from_center_zoom((1000, 750), Complex::new(-0.6, 0.0), 1.0);
+    assert!(close(region.upper_left, Region::CLASSIC.upper_left));
+    assert!(close(region.lower_right, Region::CLASSIC.lower_right));
+
+    // 倍率を 2 倍にすると幅が半分になる
+    let region = Region::from_center_zoom((1000, 750), Complex::new(-0.5, 0.25), 2.0);
+    assert!(close(region.upper_left, Complex::new(-1.3, 0.85)));
+    assert!(close(region.lower_right, Complex::new(0.3, -0.35)));
+
+    // 横長の画像では高さに合わせて幅が広がる（ピクセルは正方形のまま）
+    let region = Region::from_center_zoom((1200, 800), Complex::new(-0.6, 0.0), 1.0);
+    assert!(close(region.upper_left, Complex::new(-2.4, 1.2)));
+    assert!(close(region.lower_right, Complex::new(1.2, -1.2)));
+}
+/// ピクセル座標 → 複素平面上の点
+pub fn pixel_to_point<T: Float>(
+    bounds: (usize, usize),
+    pixel: (usize, usize),
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+) -> Complex<T> {
+    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    let as_t = |n: usize| T::from(n).unwrap();
+
+    Complex::new(
+        upper_left.re + as_t(pixel.0) * width / as_t(bounds.0),
+        upper_left.im - as_t(pixel.1) * height / as_t(bounds.1),
+    )
+}
+
+/// 複素平面上の点 → ピクセル座標（`pixel_to_point` の逆。小数のまま返す）
+///
+/// クリックした点を拡大の中心にするときなどに使う。範囲の外の点は `0..bounds` の外の値になる。
+pub fn point_to_pixel(bounds: (usize, usize), point: Complex<f64>, region: &Region) -> (f64, f64) {
+    (
+        (point.re - region.upper_left.re) / region.width() * bounds.0 as f64,
+        (region.upper_left.im - point.im) / region.height() * bounds.1 as f64,
+    )
+}
+
+#[test]
+fn test_point_to_pixel() {
+    let bounds = (1200, 800);
+    let region = Region::from_center_zoom(bounds, Complex::new(-0.75, 0.1), 40.0);
+    for pixel in [(0, 0), (1, 0), (599, 400), (1199, 799), (37, 654)] {
+        let (x, y) = point_to_pixel(bounds, region.pixel_to_point(bounds, pixel), &region);
+        assert!((x - pixel.0 as f64).abs() < 1e-6 && (y - pixel.1 as f64).abs() < 1e-6);
+    }
+
+    // 範囲の外の点
+    let (x, y) = point_to_pixel(bounds, region.upper_left - Complex::new(region.width(), -region.height()), &region);
+    assert!((x + 1200.0).abs() < 1e-6 && (y + 800.0).abs() < 1e-6);
+    let (x, _) = point_to_pixel(bounds, region.lower_right + Complex::new(region.width() / 2.0, 0.0), &region);
+    assert!((x - 1800.0).abs() < 1e-6);
+}
+
+/// 1 ピクセルあたりの幅・高さを前もって割っておいた `pixel_to_point`
+///
+/// 描画のループでピクセルごとに割り算をしないためのもの。丸め方が違うので
+/// `pixel_to_point` とは最後の桁がずれることがある。
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PixelGrid<T> {
+    upper_left: Complex<T>,
+    /// 1 ピクセルあたりの (幅, 高さ)
+    step: (T, T),
+    /// 範囲が実軸に対して対称なら画像の高さ（下半分の行 y を行 高さ - y の点の複素共役にする）
+    ///
+    /// そのまま計算すると丸め誤差で y と 高さ - y の虚部がぴったり逆符号にならず、
+    /// 上半分を写しただけの画像（`render_symmetric_counted`）と一致しなくなる。
+    mirror_rows: Option<usize>,
+}
+
+impl<T: Float> PixelGrid<T> {
+    fn point(&self, pixel: (usize, usize)) -> Complex<T> {
+        let as_t = |n: usize| T::from(n).unwrap();
+        let im = match self.mirror_rows {
+            Some(rows) if pixel.1 * 2 > rows => -(self.upper_left.im - as_t(rows - pixel.1) * self.step.1),
+            _ => self.upper_left.im - as_t(pixel.1) * self.step.1,
+        };
+        Complex::new(self.upper_left.re + as_t(pixel.0) * self.step.0, im)
+    }
+}
+
+#[test]
+fn test_pixel_grid() {
+    let bounds = (1200, 800);
+    let region = Region::from_center_zoom(bounds, Complex::new(-0.743643887, 0.131825904), 1e5);
+    let (width, height) = (bounds.0 - 1, bounds.1 - 1);
+    let pixels = [(0, 0), (width, 0), (0, height), (width, height), (bounds.0 / 2, bounds.1 / 2),
+                  bounds];
+
+    // 4 隅と中心（と右下の角の外側）で、丸め誤差を除いて pixel_to_point と一致する
+    let grid = region.grid(bounds);
+    for pixel in pixels {
+        let (fast, exact) = (grid.point(pixel), region.pixel_to_point(bounds, pixel));
+        assert!((fast - exact).norm() <= 4.0 * f64::EPSILON * exact.norm(), "{:?}: {} {}", pixel, fast, exact);
+    }
+    assert_eq!(grid.point((0, 0)), region.upper_left);
+
+    let region = region.cast::<f32>();
+    let grid = region.grid(bounds);
+    for pixel in pixels {
+        let (fast, exact) = (grid.point(pixel), region.pixel_to_point(bounds, pixel));
+        assert!((fast - exact).norm() <= 4.0 * f32::EPSILON * exact.norm(), "{:?}: {} {}", pixel, fast, exact);
+    }
+
+    // 実軸に対して対称な範囲では、行 y と行 高さ - y の点がぴったり複素共役になる
+    for bounds in [(1200, 800), (90, 61)] {
+        let grid = Region::CLASSIC.grid(bounds);
+        for y in 1..bounds.1 {
+            assert_eq!(grid.point((7, bounds.1 - y)), grid.point((7, y)).conj(), "{:?} row {}", bounds, y);
+        }
+        assert_eq!(grid.point((0, 0)), Region::CLASSIC.upper_left);
+        assert_eq!(grid.point(bounds).im, Region::CLASSIC.lower_right.im);
+        let exact = Region::CLASSIC.pixel_to_point(bounds, (5, bounds.1 - 3));
+        assert!((grid.point((5, bounds.1 - 3)) - exact).norm() <= 4.0 * f64::EPSILON * exact.norm());
+    }
+}
+
+/// フラクタルの反復式 z ← f(z, c) と、ピクセルの点から z₀ と c を決める規則
+///
+/// 発散判定のループ（`escape_time_formula` など）はこのトレイトに対して 1 度だけ書いてあるので、
+/// 新しいフラクタルはこれを実装して `Fractal` に列挙子を 1 つ足せば描ける。
+/// （`Fractal` は `--fractal` で選ぶ列挙型の名前なので、トレイトは `Formula` と呼ぶ）
+pub trait Formula {
+    /// 1 回分の反復
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T>;
+
+    /// ピクセルの点 `point` → (z₀, c)
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>);
+}
+
+/// マンデルブロ集合（z ← z² + c、z₀ = 0、c = ピクセルの点）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mandelbrot;
+
+/// ジュリア集合（z ← z² + c、z₀ = ピクセルの点、c = 固定の定数）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Julia(pub Complex<f64>);
+
+/// バーニングシップ（z ← (|Re z| + i|Im z|)² + c、z₀ = 0、c = ピクセルの点）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BurningShip;
+
+/// マルチブロ集合（z ← z^d + c、z₀ = 0、c = ピクセルの点。d >= 1）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Multibrot(pub u32);
+
+impl Formula for Mandelbrot {
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        z * z + c
+    }
+
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (Complex::new(T::zero(), T::zero()), point)
+    }
+}
+
+impl Formula for Julia {
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        z * z + c
+    }
+
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (point, complex_as(self.0))
+    }
+}
+
+impl Formula for BurningShip {
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        let w = fold(z);
+        w * w + c
+    }
+
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (Complex::new(T::zero(), T::zero()), point)
+    }
+}
+
+impl Formula for Multibrot {
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        power(z, self.0) + c
+    }
+
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (Complex::new(T::zero(), T::zero()), point)
+    }
+}
+
+/// `--fractal` で選んだ列挙子の反復式（1 点の軌道をたどるときなど、反復のループを外で書くとき用）
+impl Formula for Fractal {
+    fn step<T: Float>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.step(z, c),
+            Fractal::Julia(constant) => Julia(constant).step(z, c),
+            Fractal::BurningShip => BurningShip.step(z, c),
+            Fractal::Multibrot(d) => Multibrot(d).step(z, c),
+        }
+    }
+
+    fn initial<T: Float>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.initial(point),
+            Fractal::Julia(constant) => Julia(constant).initial(point),
+            Fractal::BurningShip => BurningShip.initial(point),
+            Fractal::Multibrot(d) => Multibrot(d).initial(point),
+        }
+    }
+}
+
+/// `formula` のピクセルの点 `point` の発散判定（発散半径は `DEFAULT_BAILOUT`）
+pub fn escape_time_formula<T: Float, F: Formula>(formula: &F, point: Complex<T>, max_iter: u32) -> u32 {
+    escape_time_formula_final(formula, point, max_iter, default_bailout_sqr()).0
+}
+
+/// `escape_time_formula` と同じだが、発散半径の 2 乗を `bailout_sqr` で指定し、反復を終えたときの z も返す
+fn escape_time_formula_final<T: Float, F: Formula>(
+    formula: &F,
+    point: Complex<T>,
+    max_iter: u32,
+    bailout_sqr: T,
+) -> (u32, Complex<T>)
+{
+    let (z, c) = formula.initial(point);
+    escape_time_orbit_final(formula, z, c, max_iter, bailout_sqr)
+}
+
+/// z₀ = `z`、定数 `c` から `formula` の反復を繰り返す発散判定（すべてのフラクタルで共通のループ）
+///
+/// |z|² が `bailout_sqr` を超えたら発散とみなす。|z|² が無限大や NaN になったとき（極端な c や
+/// 発散半径で z があふれたとき）も、その回で発散とみなす。NaN はどの比較でも偽になるので、
+/// そのままでは上限まで回って内部の色になってしまう。
+fn escape_time_orbit_final<T: Float, F: Formula>(
+    formula: &F,
+    z: Complex<T>,
+    c: Complex<T>,
+    max_iter: u32,
+    bailout_sqr: T,
+) -> (u32, Complex<T>) {
+    let mut z = z;
+
+    for i in 0..max_iter {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr || !norm_sqr.is_finite() {
+            return (i, z);
+        }
+        z = formula.step(z, c);
+    }
+    (max_iter, z)
+}
+
+#[test]
+fn test_escape_time_non_finite() {
+    // 発散半径が無限大だと |z₁|² = (1e200)² があふれても超えたことにならず、
+    // 次の z₂ は inf - inf = NaN を含む。どちらもあふれた回で発散とみなす
+    let huge = Complex::new(1e200, 1e200);
+    assert_eq!(Fractal::Mandelbrot.escape_time(huge, 100, f64::INFINITY), 1);
+    assert_eq!(Fractal::Mandelbrot.escape_time(Complex::new(1e200, 0.0), 100, f64::INFINITY), 1);
+    let (iter, z) = Fractal::Mandelbrot.escape_time_final(Complex::new(1e200, 0.0), 100, f64::INFINITY);
+    assert_eq!((iter, z), (1, Complex::new(1e200, 0.0)));
+
+    // NaN を含む点はその場で発散とみなす（内部の色にならない）
+    assert_eq!(escape_time(Complex::new(f64::NAN, 0.0), 100, default_bailout_sqr()), 1);
+    assert_eq!(escape_time_julia(Complex::new(0.0, f64::NAN), Complex::new(-0.8, 0.156), 100), 0);
+    assert_eq!(escape_time(Complex::new(f64::INFINITY, -f64::INFINITY), 100, default_bailout_sqr()), 1);
+    assert_eq!(escape_time_burning_ship(Complex::new(f64::NAN, f64::NAN), 100), 1);
+
+    // ふつうの点は変わらない
+    assert_eq!(escape_time(Complex::new(1.0, 0.0), 100, default_bailout_sqr()), 3);
+    assert_eq!(escape_time(Complex::new(-1.0, 0.0), 100, default_bailout_sqr()), 100);
+}
+
+#[test]
+fn test_escape_time_full() {
+    let fractals = [Fractal::Mandelbrot, Fractal::Julia(Complex::new(-0.8, 0.156)), Fractal::BurningShip,
+                    Fractal::Multibrot(3)];
+    let bounds = (60, 40);
+    for fractal in fractals {
+        for y in 0..bounds.1 {
+            for x in 0..bounds.0 {
+                let point = Region::CLASSIC.pixel_to_point(bounds, (x, y));
+                let result = fractal.escape_time_full(point, 200, default_bailout_sqr());
+                let iter = fractal.escape_time(point, 200, default_bailout_sqr());
+                assert_eq!(result.count.unwrap_or(200), iter, "{:?} {}", fractal, point);
+                assert_eq!(result.iterations, iter);
+                assert_eq!(result.count.is_none(), iter == 200);
+                assert_eq!((result.iterations, result.final_z),
+                           fractal.escape_time_final(point, 200, default_bailout_sqr()));
+                if fractal == Fractal::Mandelbrot {
+                    assert_eq!(result.count.unwrap_or(200), escape_time(point, 200, default_bailout_sqr()));
+                }
+            }
+        }
+    }
+
+    // 発散した点の z は発散半径を超えていて、内部の点は超えていない
+    let escaped = Fractal::Mandelbrot.escape_time_full(Complex::new(1.0, 0.0), 100, 4.0);
+    assert_eq!(escaped, EscapeResult { count: Some(3), final_z: Complex::new(5.0, 0.0), iterations: 3 });
+    let interior = Fractal::Mandelbrot.escape_time_full(Complex::new(-1.0, 0.0), 100, 4.0);
+    assert_eq!((interior.count, interior.iterations), (None, 100));
+    assert!(interior.final_z.norm_sqr() <= 4.0);
+
+    // f32 でも使える
+    assert_eq!(Fractal::Mandelbrot.escape_time_full(Complex::new(1.0f32, 0.0), 100, 4.0).count, Some(3));
+}
+
+#[test]
+fn test_escape_time_formula() {
+    // 書き換える前の z ← z² + c のループ
+    let original = |c: Complex<f64>, max_iter: u32| {
+        let mut z = Complex::new(0.0, 0.0);
+        for i in 0..max_iter {
+            if z.norm_sqr() > 4.0 {
+                return i;
+            }
+            z = z * z + c;
+        }
+        max_iter
+    };
+
+    let bounds = (90, 60);
+    let julia = Complex::new(-0.8, 0.156);
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let point = Region::CLASSIC.pixel_to_point(bounds, (x, y));
+            assert_eq!(escape_time_formula(&Mandelbrot, point, 200), original(point, 200), "{}", point);
+            let iter = escape_time(point, 200, default_bailout_sqr());
+            assert_eq!(escape_time_formula(&Mandelbrot, point, 200), iter, "{}", point);
+            assert_eq!(escape_time_formula(&Multibrot(2), point, 200), iter, "{}", point);
+
+            // 列挙型の `Fractal` は同じ実装に振り分けるだけ
+            let bailout_sqr = default_bailout_sqr();
+            assert_eq!(Fractal::Julia(julia).escape_time(point, 200, bailout_sqr),
+                       escape_time_formula(&Julia(julia), point, 200));
+            assert_eq!(Fractal::Julia(julia).escape_time(point, 200, bailout_sqr),
+                       escape_time_julia(point, julia, 200));
+            assert_eq!(Fractal::BurningShip.escape_time(point, 200, bailout_sqr),
+                       escape_time_formula(&BurningShip, point, 200));
+            assert_eq!(Fractal::Multibrot(3).escape_time(point, 200, bailout_sqr),
+                       escape_time_formula(&Multibrot(3), point, 200));
+        }
+    }
+
+    // f32 でも使える
+    assert_eq!(escape_time_formula(&Mandelbrot, Complex::new(1.0f32, 0.0), 100), 3);
+}
+
+/// マンデルブロ集合の発散判定（`bailout_sqr` は発散半径の 2 乗。回数を数えるだけなら `DEFAULT_BAILOUT` の 2 乗）
+pub fn escape_time<T: Float>(c: Complex<T>, max_iter: u32, bailout_sqr: f64) -> u32 {
+    Fractal::Mandelbrot.escape_time(c, max_iter, bailout_sqr)
+}
+
+/// `escape_time` と同じ回数を、`Complex` を使わずに実部・虚部の f64 で直接計算する（f64 のマンデルブロ集合専用）
+///
+/// zr²・zi² を次の z の計算と |z|² の判定で使い回す。z² の虚部 2·zr·zi は `Complex` の
+/// zr·zi + zi·zr と丸めまで同じになるので、発散半径 `bailout_sqr` が同じなら回数はいつも一致する。
+pub fn escape_time_fast(c: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> u32 {
+    let (mut zr, mut zi) = (0.0f64, 0.0f64);
+    let (mut zr2, mut zi2) = (0.0f64, 0.0f64);
+
+    for i in 0..max_iter {
+        let norm_sqr = zr2 + zi2;
+        if norm_sqr > bailout_sqr || !norm_sqr.is_finite() {
+            return i;
+        }
+        zi = 2.0 * zr * zi + c.im;
+        zr = zr2 - zi2 + c.re;
+        zr2 = zr * zr;
+        zi2 = zi * zi;
+    }
+    max_iter
+}
+
+#[test]
+fn test_escape_time_fast() {
+    // 従来の範囲を細かく走査して、`Complex` の反復と回数が一致する
+    let bounds = (240, 160);
+    let grid = Region::CLASSIC.grid(bounds);
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let point = grid.point((x, y));
+            assert_eq!(escape_time_fast(point, 500, default_bailout_sqr()),
+                       escape_time(point, 500, default_bailout_sqr()), "{}", point);
+            assert_eq!(escape_time_fast(point, 200, POTENTIAL_BAILOUT * POTENTIAL_BAILOUT),
+                       Fractal::Mandelbrot.escape_time(point, 200, POTENTIAL_BAILOUT * POTENTIAL_BAILOUT));
+        }
+    }
+
+    // 符号付きのゼロ・あふれ・NaN も同じ
+    for point in [Complex::new(-0.0, -0.0), Complex::new(0.0, -0.0), Complex::new(-2.0, 0.0),
+                  Complex::new(1e200, 1e200), Complex::new(f64::NAN, 0.0),
+                  Complex::new(0.25, f64::INFINITY)] {
+        for bailout_sqr in [default_bailout_sqr(), f64::INFINITY] {
+            assert_eq!(escape_time_fast(point, 100, bailout_sqr),
+                       Fractal::Mandelbrot.escape_time(point, 100, bailout_sqr), "{}", point);
+        }
+    }
+}
+
+/// ジュリア集合の発散判定（z₀ = `z`、定数 `c`、発散半径は `DEFAULT_BAILOUT`）
+pub fn escape_time_julia<T: Float>(z: Complex<T>, c: Complex<T>, max_iter: u32) -> u32 {
+    // c が `T` で与えられるので、反復式だけ借りて z₀ と c を直接渡す（z ← z² + c はマンデルブロ集合と同じ）
+    escape_time_orbit_final(&Mandelbrot, z, c, max_iter, default_bailout_sqr()).0
+}
+
+/// マルチブロ集合の発散判定（z ← z^`d` + c、z₀ = 0、発散半径は `DEFAULT_BAILOUT`）
+pub fn escape_time_multibrot<T: Float>(c: Complex<T>, d: u32, max_iter: u32) -> u32 {
+    escape_time_formula(&Multibrot(d), c, max_iter)
+}
+
+/// `DEFAULT_BAILOUT` の 2 乗を型 `T` で
+fn default_bailout_sqr<T: Float>() -> T {
+    T::from(DEFAULT_BAILOUT * DEFAULT_BAILOUT).unwrap()
+}
+
+/// z^`d` を掛け算の繰り返しで求める（z⁰ = 1。d = 2 なら `z * z` と同じ結果）
+fn power<T: Float>(z: Complex<T>, d: u32) -> Complex<T> {
+    if d == 0 {
+        return Complex::new(T::one(), T::zero());
+    }
+    (1..d).fold(z, |w, _| w * z)
+}
+
+#[test]
+fn test_escape_time_multibrot() {
+    let bounds = (60, 40);
+    let region = Region::CLASSIC;
+    let mut differs = false;
+
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let c = region.pixel_to_point(bounds, (x, y));
+            assert_eq!(escape_time_multibrot(c, 2, 200), escape_time(c, 200, default_bailout_sqr()), "c = {:?}", c);
+            differs |= escape_time_multibrot(c, 3, 200) != escape_time(c, 200, default_bailout_sqr());
+        }
+    }
+    assert!(differs);
+
+    // c = 1: z³ + c は 0, 1, 2, 9（|z₃|² > 4）
+    assert_eq!(escape_time_multibrot(Complex::new(1.0, 0.0), 3, 100), 3);
+    // c = -1.5 は d = 2 では内部、d = 3 では 0, -1.5, -4.875 と発散する
+    assert_eq!(escape_time_multibrot(Complex::new(-1.5, 0.0), 2, 100), 100);
+    assert_eq!(escape_time_multibrot(Complex::new(-1.5, 0.0), 3, 100), 2);
+
+    // d = 0, 1 でもパニックしない（z⁰ = 1、z¹ = z）
+    let z = Complex::new(0.5, -2.0);
+    assert_eq!(power(z, 0), Complex::new(1.0, 0.0));
+    assert_eq!(power(z, 1), z);
+    // d = 0: z = 0, 1 + c。d = 1: z = 0, c, 2c, 3c（|3c|² = 9 > 4）
+    assert_eq!(escape_time_multibrot(Complex::new(1.5, 0.0), 0, 100), 1);
+    assert_eq!(escape_time_multibrot(Complex::new(1.0, 0.0), 1, 100), 3);
+    assert!(escape_time_distance_multibrot(Complex::new(1.5, 0.0), 0, 100, 4.0).is_some());
+    assert!(escape_time_distance_multibrot(Complex::new(1.0, 0.0), 1, 100, 4.0).is_some());
+
+    assert_eq!(Fractal::multibrot(3), Ok(Fractal::Multibrot(3)));
+    assert!(Fractal::multibrot(1).is_err());
+    assert!(Fractal::multibrot(0).is_err());
+}
+
+/// 実部・虚部の絶対値をとる（バーニングシップで 2 乗する前の折り返し）
+fn fold<T: Float>(z: Complex<T>) -> Complex<T> {
+    Complex::new(z.re.abs(), z.im.abs())
+}
+
+/// バーニングシップの発散判定（z ← (|Re z| + i|Im z|)² + c、発散半径は `DEFAULT_BAILOUT`）
+pub fn escape_time_burning_ship<T: Float>(c: Complex<T>, max_iter: u32) -> u32 {
+    escape_time_formula(&BurningShip, c, max_iter)
+}
+
+#[test]
+fn test_escape_time_burning_ship() {
+    // c = -1+i: z₁ = -1+i、|z₁| を 2 乗して (1+i)² - 1 + i = -1+3i（|z₂|² = 10 > 4）
+    assert_eq!(escape_time_burning_ship(Complex::new(-1.0, 1.0), 100), 2);
+    // c = 0.5-0.5i: z₁ = 0.5-0.5i、z₂ = (0.5+0.5i)² + c = 0.5, z₃ = 0.75-0.5i,
+    // z₄ = (0.75+0.5i)² + c = 0.8125+0.25i, z₅ = 1.097...-0.09375i, z₆ = 1.696...-0.294...i,
+    // z₇ = 3.290...+0.497...i（|z₇|² > 4）
+    assert_eq!(escape_time_burning_ship(Complex::new(0.5, -0.5), 100), 7);
+    // 実軸上の負の点では折り返しが効かないので、マンデルブロ集合と同じ
+    for re in [-1.9, -1.5, -0.5] {
+        let c = Complex::new(re, 0.0);
+        assert_eq!(escape_time_burning_ship(c, 200), escape_time(c, 200, default_bailout_sqr()), "c = {}", re);
+    }
+    // 内部の点
+    assert_eq!(escape_time_burning_ship(Complex::new(-0.2, 0.0), 100), 100);
+}
+
+/// マンデルブロ集合の発散判定と、軌道 z₁, z₂, ... が原点に最も近づいた距離
+///
+/// z₀ = 0 は常にトラップ上にあるので数えない。
+pub fn escape_time_trap(c: Complex<f64>, max_iter: u32) -> (u32, f64) {
+    escape_time_trap_formula(&Mandelbrot, c, max_iter, default_bailout_sqr())
+}
+
+/// `escape_time_trap` を `formula` のピクセルの点 `point`、発散半径の 2 乗 `bailout_sqr` に一般化したもの
+fn escape_time_trap_formula<F: Formula>(formula: &F, point: Complex<f64>, max_iter: u32, bailout_sqr: f64)
+    -> (u32, f64)
+{
+    let (mut z, c) = formula.initial(point);
+    let mut min_distance = f64::INFINITY;
+
+    // あふれた z は `escape_time_orbit_final` と同じく発散とみなす
+    for i in 0..max_iter {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr || !norm_sqr.is_finite() {
+            return (i, min_distance);
+        }
+        z = formula.step(z, c);
+        min_distance = min_distance.min(z.norm());
+    }
+    (max_iter, min_distance)
+}
+
+#[test]
+fn test_escape_time_trap() {
+    // c = -1 の軌道は 0, -1, 0, -1, ... と原点を通る
+    let c = Complex::new(-1.0, 0.0);
+    let (_, after_one) = escape_time_trap(c, 1);
+    let (_, after_two) = escape_time_trap(c, 2);
+    assert_eq!(after_one, 1.0);
+    assert_eq!(after_two, 0.0);
+    assert!(after_two < after_one);
+
+    // 反復回数は escape_time と同じで、距離は負にならない
+    for &(re, im) in &[(0.3, 0.5), (-0.75, 0.1), (1.0, 1.0), (-2.0, 0.0), (0.25, 0.0)] {
+        let c = Complex::new(re, im);
+        let (iter, distance) = escape_time_trap(c, 200);
+        assert_eq!(iter, escape_time(c, 200, default_bailout_sqr()));
+        assert!(distance >= 0.0);
+    }
+}
+
+/// トラップまでの距離 → RGB（近いほど明るく光る）。内部は黒。
+pub fn trap_color(iter: u32, distance: f64, max_iter: u32) -> [u8; 3] {
+    if iter >= max_iter {
+        return [0, 0, 0];
+    }
+
+    palette(0.8 * (1.0 - distance.min(1.0)) as f32)
+}
+
+/// 距離推定のための 1 ステップ: dz ← 2·z·dz + 1、z ← z² + c
+fn distance_step(z: Complex<f64>, dz: Complex<f64>, c: Complex<f64>)
+    -> (Complex<f64>, Complex<f64>)
+{
+    (z * z + c, z * dz * 2.0 + 1.0)
+}
+
+/// マンデルブロ集合の境界までの推定距離 `|z|·ln|z| / |dz|`
+///
+/// z と一緒にその c による微分 dz を反復し、発散した時点の値から距離を見積もる。
+/// 反復回数の上限に達した（集合の内部とみなす）場合は `None`。発散半径は `DEFAULT_BAILOUT`。
+pub fn escape_time_distance(c: Complex<f64>, max_iter: u32) -> Option<f64> {
+    escape_time_distance_mandelbrot(c, max_iter, default_bailout_sqr())
+}
+
+/// `escape_time_distance` の発散半径の 2 乗を `bailout_sqr` で指定するもの
+fn escape_time_distance_mandelbrot(c: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut dz = Complex::new(0.0, 0.0);
+
+    for _ in 0..max_iter {
+        if z.norm_sqr() > bailout_sqr {
+            let r = z.norm();
+            return Some(r * r.ln() / dz.norm());
+        }
+        (z, dz) = distance_step(z, dz, c);
+    }
+    None
+}
+
+/// `escape_time_distance` のジュリア集合版（z₀ = `z`、定数 `c`）
+///
+/// 微分は z₀ に関するものなので、dz₀ = 1、dz ← 2·z·dz になる。
+fn escape_time_distance_julia(z: Complex<f64>, c: Complex<f64>, max_iter: u32, bailout_sqr: f64)
+    -> Option<f64>
+{
+    let mut z = z;
+    let mut dz = Complex::new(1.0, 0.0);
+
+    for _ in 0..max_iter {
+        if z.norm_sqr() > bailout_sqr {
+            let r = z.norm();
+            return Some(r * r.ln() / dz.norm());
+        }
+        dz = z * dz * 2.0;
+        z = z * z + c;
+    }
+    None
+}
+
+/// `escape_time_distance` のバーニングシップ版
+///
+/// 折り返しは実部・虚部の符号を反転するだけなので、dz も同じ成分を反転してから 2·w·dz + 1 とする。
+fn escape_time_distance_burning_ship(c: Complex<f64>, max_iter: u32, bailout_sqr: f64) -> Option<f64> {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut dz = Complex::new(0.0, 0.0);
+
+    for _ in 0..max_iter {
+        if z.norm_sqr() > bailout_sqr {
+            let r = z.norm();
+            return Some(r * r.ln() / dz.norm());
+        }
+        let w = fold(z);
+        let dw = Complex::new(dz.re * z.re.signum(), dz.im * z.im.signum());
+        (z, dz) = distance_step(w, dw, c);
+    }
+    None
+}
+
+/// `escape_time_distance` のマルチブロ集合版（dz ← d·z^(d-1)·dz + 1、z ← z^d + c）
+fn escape_time_distance_multibrot(c: Complex<f64>, d: u32, max_iter: u32, bailout_sqr: f64)
+    -> Option<f64>
+{
+    let mut z = Complex::new(0.0, 0.0);
+    let mut dz = Complex::new(0.0, 0.0);
+
+    for _ in 0..max_iter {
+        if z.norm_sqr() > bailout_sqr {
+            let r = z.norm();
+            return Some(r * r.ln() / dz.norm());
+        }
+        dz = power(z, d.saturating_sub(1)) * dz * d as f64 + 1.0;
+        z = power(z, d) + c;
+    }
+    None
+}
+
+#[test]
+fn test_escape_time_distance() {
+    // c = 1: z = 0, 1, 2, 5, ...   dz = 0, 1, 3, 13, ...
+    let c = Complex::new(1.0, 0.0);
+    let (z, dz) = distance_step(Complex::new(0.0, 0.0), Complex::new(0.0, 0.0), c);
+    assert_eq!((z, dz), (Complex::new(1.0, 0.0), Complex::new(1.0, 0.0)));
+    let (z, dz) = distance_step(z, dz, c);
+    assert_eq!((z, dz), (Complex::new(2.0, 0.0), Complex::new(3.0, 0.0)));
+    let (z, dz) = distance_step(z, dz, c);
+    assert_eq!((z, dz), (Complex::new(5.0, 0.0), Complex::new(13.0, 0.0)));
+
+    // |z₂|² = 4 はまだ発散とみなさないので、z₃ = 5 で打ち切る
+    assert_eq!(escape_time_distance(c, 100), Some(5.0 * 5.0f64.ln() / 13.0));
+
+    // 複素数の c でも微分は 2·z·dz + 1
+    let c = Complex::new(0.5, 0.5);
+    let (z1, dz1) = distance_step(Complex::new(0.0, 0.0), Complex::new(0.0, 0.0), c);
+    let (_, dz2) = distance_step(z1, dz1, c);
+    assert_eq!(dz2, Complex::new(2.0, 1.0));
+
+    // 集合の内部
+    assert_eq!(escape_time_distance(Complex::new(-0.5, 0.0), 100), None);
+}
+
+/// 推定距離 → RGB（境界に近いほど暗い）。内部は黒。
+///
+/// `pixel_size` は 1 ピクセルの幅で、これより離れた点は白になる。
+pub fn distance_color(estimate: Option<f64>, pixel_size: f64) -> [u8; 3] {
+    match estimate {
+        None => [0, 0, 0],
+        Some(distance) => {
+            let shade = ((distance / pixel_size).clamp(0.0, 1.0).sqrt() * 255.0) as u8;
+            [shade, shade, shade]
+        }
+    }
+}
+
+/// マンデルブロ集合の連続ポテンシャル ln|z|² / 2ⁿ（発散半径は `POTENTIAL_BAILOUT`）
+///
+/// 集合を帯電した導体とみなしたときの電位で、境界に近づくほど 0 に近づき、遠くでは ln|c|² / 2 に近づく。
+/// 集合の内部とみなした場合は `None`。
+pub fn escape_time_potential(c: Complex<f64>, limit: u32) -> Option<f64> {
+    Fractal::Mandelbrot.escape_time_potential(c, limit, POTENTIAL_BAILOUT * POTENTIAL_BAILOUT)
+}
+
+/// マンデルブロ集合の滑らかな反復回数（発散半径は `POTENTIAL_BAILOUT`。集合の内部なら `None`）
+///
+/// `escape_time` の整数の回数と違い、c を動かしたときに回数の帯の境目でも連続に変わる。
+pub fn escape_time_smooth(c: Complex<f64>, max_iter: u32) -> Option<f64> {
+    Fractal::Mandelbrot.escape_time_smooth(c, max_iter, POTENTIAL_BAILOUT * POTENTIAL_BAILOUT)
+}
+
+/// 滑らかな反復回数 → RGB（回数 / `max_iter` をパレットに渡す。内部は黒）
+pub fn smooth_color(smooth: Option<f64>, max_iter: u32, palette: &Palette) -> [u8; 3] {
+    match smooth {
+        None => [0, 0, 0],
+        Some(smooth) => palette.color_at((smooth / max_iter as f64).clamp(0.0, 1.0) as f32),
+    }
+}
+
+/// `smooth_color` の丸める前の値（各チャンネル 0.0..=255.0）
+pub fn smooth_color_exact(smooth: Option<f64>, max_iter: u32, palette: &Palette) -> [f32; 3] {
+    match smooth {
+        None => [0.0; 3],
+        Some(smooth) => palette.color_at_exact((smooth / max_iter as f64).clamp(0.0, 1.0) as f32),
+    }
+}
+
+/// `smooth_color` と同じだが、ピクセル `pixel` の位置で `dither` をかける
+pub fn smooth_color_dithered(smooth: Option<f64>, max_iter: u32, palette: &Palette, pixel: (usize, usize))
+    -> [u8; 3]
+{
+    dither(smooth_color_exact(smooth, max_iter, palette), pixel)
+}
+
+#[test]
+fn test_escape_time_smooth() {
+    // 実軸上の 0.3..2.0 と、タツノオトシゴの谷から真上に伸ばした線分（どちらも集合の外）を細かく走査する
+    for (start, end) in [(Complex::new(0.3, 0.0), Complex::new(2.0, 0.0)),
+                         (Complex::new(-0.75, 0.2), Complex::new(-0.75, 1.5))] {
+        let steps = 10_000;
+        let points = (0..=steps).map(|k| start + (end - start) * (k as f64 / steps as f64));
+        let (smooth, counts): (Vec<f64>, Vec<u32>) = points
+            .map(|c| (escape_time_smooth(c, 1000).unwrap(), escape_time(c, 1000, default_bailout_sqr())))
+            .unzip();
+
+        // 整数の回数は何度も変わる（発散半径の境目を何度もまたぐ）が、滑らかな回数は隣どうしでほとんど変わらない
+        assert!(counts.windows(2).filter(|w| w[0] != w[1]).count() >= 10);
+        let largest = smooth.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f64::max);
+        assert!(largest < 0.05, "{} → {}: {}", start, end, largest);
+    }
+
+    // 回数の帯の中では整数の回数から 1 以上離れない
+    for c in [Complex::new(0.5, 0.0), Complex::new(-0.75, 0.1), Complex::new(2.0, 2.0)] {
+        let (iter, _) = Fractal::Mandelbrot.escape_time_final(c, 1000, POTENTIAL_BAILOUT * POTENTIAL_BAILOUT);
+        let smooth = escape_time_smooth(c, 1000).unwrap();
+        assert!(smooth <= iter as f64 && smooth > iter as f64 - 1.0, "{}: {} {}", c, iter, smooth);
+    }
+    assert_eq!(escape_time_smooth(Complex::new(-1.0, 0.0), 1000), None);
+}
+
+/// ポテンシャルの自然対数 `log_potential` → 等電位線の中の位置（0.0..1.0）
+///
+/// ポテンシャルが 1/16 になるごとに 1 周する。無限大や NaN（z があふれた点）は帯の境目の 0.0 にする。
+fn potential_band(log_potential: f64) -> f32 {
+    let band = (-log_potential / (4.0 * std::f64::consts::LN_2)).rem_euclid(1.0);
+    if band.is_finite() { band as f32 } else { 0.0 }
+}
+
+/// 連続ポテンシャルの自然対数（`escape_time_log_potential`）→ RGB（ポテンシャルが 1/16 になるごとに `palette` を
+/// 1 周する等電位線）。内部は黒。
+pub fn potential_color(log_potential: Option<f64>, palette: &Palette) -> [u8; 3] {
+    match log_potential {
+        None => [0, 0, 0],
+        Some(log_potential) => palette.color_at(potential_band(log_potential)),
+    }
+}
+
+/// `potential_color` の丸める前の値（各チャンネル 0.0..=255.0）
+pub fn potential_color_exact(log_potential: Option<f64>, palette: &Palette) -> [f32; 3] {
+    match log_potential {
+        None => [0.0; 3],
+        Some(log_potential) => palette.color_at_exact(potential_band(log_potential)),
+    }
+}
+
+/// `potential_color` と同じだが、ピクセル `pixel` の位置で `dither` をかける
+pub fn potential_color_dithered(log_potential: Option<f64>, palette: &Palette, pixel: (usize, usize)) -> [u8; 3] {
+    dither(potential_color_exact(log_potential, palette), pixel)
+}
+
+#[test]
+fn test_escape_time_potential() {
+    // 発散する点ではいつも正
+    for &(re, im) in &[(0.5, 0.5), (1.0, 1.0), (-2.0, 0.5), (0.26, 0.0), (-0.75, 0.2)] {
+        let potential = escape_time_potential(Complex::new(re, im), 1000).unwrap();
+        assert!(potential > 0.0, "c = {}+{}i: {}", re, im, potential);
+    }
+
+    // 遠くの点では ln|c|（1 回目で発散半径を超える）
+    let potential = escape_time_potential(Complex::new(1e4, 0.0), 100).unwrap();
+    assert!((potential - 1e4f64.ln()).abs() < 1e-9);
+
+    // 実軸に沿って境界 c = 1/4 に近づくと、単調に 0 へ向かう
+    let potentials: Vec<f64> = (1..=4)
+        .map(|k| escape_time_potential(Complex::new(0.25 + 10f64.powi(-k), 0.0), 10_000).unwrap())
+        .collect();
+    assert!(potentials.windows(2).all(|w| 0.0 < w[1] && w[1] < w[0]), "{:?}", potentials);
+    assert!(potentials[0] < 0.01 && potentials[3] < 1e-90, "{:?}", potentials);
+
+    // 内部の点
+    assert_eq!(escape_time_potential(Complex::new(-0.5, 0.0), 1000), None);
+    assert_eq!(potential_color(None, &Palette::Classic), [0, 0, 0]);
+
+    // 反復回数が 1000 を超えてもアンダーフローせず、色が NaN にならない
+    let bailout_sqr = POTENTIAL_BAILOUT * POTENTIAL_BAILOUT;
+    let c = Complex::new(0.250001, 0.0);
+    let (iter, _) = Fractal::Mandelbrot.escape_time_final(c, 20_000, bailout_sqr);
+    assert!(iter > 1100 && iter < 20_000, "{}", iter);
+    assert_eq!(Fractal::Mandelbrot.escape_time_potential(c, 20_000, bailout_sqr), Some(0.0));
+    let log_potential = Fractal::Mandelbrot.escape_time_log_potential(c, 20_000, bailout_sqr).unwrap();
+    assert!(log_potential.is_finite() && log_potential < -700.0, "{}", log_potential);
+    let gradient = Gradient::new(vec![(0.0, [0, 0, 255]), (1.0, [255, 255, 0])]);
+    for palette in [Palette::Classic, Palette::Gradient(gradient)] {
+        assert!(potential_color_exact(Some(log_potential), &palette).iter().all(|c| c.is_finite()));
+    }
+    // z があふれた点は帯の境目
+    assert_eq!(potential_color(Some(f64::NEG_INFINITY), &Palette::Classic), Palette::Classic.color_at(0.0));
+}
+
+#[test]
+fn test_precision_f32_matches_f64() {
+    let bounds = (1200, 800);
+    let region = Region::CLASSIC;
+
+    for &pixel in &[(0, 0), (1199, 799), (600, 400), (123, 456), (1000, 100), (300, 700)] {
+        let p64 = region.pixel_to_point(bounds, pixel);
+        let p32 = region.cast::<f32>().pixel_to_point(bounds, pixel);
+        assert!((p64.re - p32.re as f64).abs() <= f32::EPSILON as f64 * p64.re.abs().max(1.0));
+        assert!((p64.im - p32.im as f64).abs() <= f32::EPSILON as f64 * p64.im.abs().max(1.0));
+    }
+
+    // 境界から離れた点では反復回数も一致する
+    for &(re, im) in &[(0.0, 0.0), (-1.0, 0.0), (0.25, 0.5), (1.0, 1.0), (-2.1, 0.0), (0.5, -0.5)] {
+        let c = Complex::new(re, im);
+        assert_eq!(escape_time::<f32>(complex_as(c), 200, default_bailout_sqr()),
+                   escape_time(c, 200, default_bailout_sqr()), "c = {:?}", c);
+    }
+}
+
+#[test]
+fn test_escape_time_julia() {
+    // c = 0 のジュリア集合は単位円板
+    assert_eq!(escape_time_julia(Complex::new(0.5, 0.5), Complex::new(0.0, 0.0), 100), 100);
+    assert_eq!(escape_time_julia(Complex::new(1.5, 0.0), Complex::new(0.0, 0.0), 100), 1);
+    // z₀ = 0 ならマンデルブロ集合と同じ
+    let c = Complex::new(-0.75, 0.1);
+    assert_eq!(escape_time_julia(Complex::new(0.0, 0.0), c, 200), escape_time(c, 200, default_bailout_sqr()));
+}
+
+#[test]
+fn test_larger_bailout_escapes_later() {
+    let bounds = (60, 40);
+    let region = Region::CLASSIC;
+    let fractals = [Fractal::Mandelbrot, Fractal::BurningShip, Fractal::Multibrot(3),
+                    Fractal::Julia(Complex::new(-0.8, 0.156))];
+
+    // 発散半径を広げると、軌道がその外に出るまでの反復回数は減らない
+    for fractal in fractals {
+        for y in 0..bounds.1 {
+            for x in 0..bounds.0 {
+                let point = region.pixel_to_point(bounds, (x, y));
+                let plain = fractal.escape_time(point, 200, 4.0);
+                let larger = fractal.escape_time(point, 200, 16.0);
+                assert!(larger >= plain, "{:?} at {:?}: {} < {}", fractal, point, larger, plain);
+                let trap = |bailout_sqr| fractal.escape_time_trap(point, 200, bailout_sqr).0;
+                assert!(trap(16.0) >= trap(4.0), "{:?} at {:?}", fractal, point);
+            }
+        }
+    }
+
+    // c = 1: z = 0, 1, 2, 5, 26 なので、半径 2 では z₃ で、半径 10 では z₄ で打ち切る
+    let c = Complex::new(1.0, 0.0);
+    assert_eq!(Fractal::Mandelbrot.escape_time(c, 100, 4.0), 3);
+    assert_eq!(Fractal::Mandelbrot.escape_time(c, 100, 100.0), 4);
+
+    // `escape_time` も発散半径を受け取る。既定の発散半径は従来の |z|² > 4 と同じ
+    assert_eq!(escape_time(c, 100, DEFAULT_BAILOUT * DEFAULT_BAILOUT), 3);
+    assert_eq!(escape_time(c, 100, 100.0), 4);
+}
+
+/// 4 点 `c_re[k] + c_im[k] i` の反復回数をまとめて求める（SIMD 版）
+///
+/// 4 レーンの z をそろって反復し、発散したレーンはマスクして回数を記録する。
+/// 演算の順序は `escape_time` と同じなので、結果も一致する。
+#[cfg(feature = "simd")]
+pub fn escape_time_x4(c_re: [f64; 4], c_im: [f64; 4], max_iter: u32, bailout_sqr: f64) -> [u32; 4] {
+    use wide::{f64x4, CmpLe};
+
+    let (c_re, c_im) = (f64x4::new(c_re), f64x4::new(c_im));
+    let (mut z_re, mut z_im) = (f64x4::ZERO, f64x4::ZERO);
+    let bailout = f64x4::splat(bailout_sqr);
+    let mut counts = [max_iter; 4];
+    let mut active = 0b1111;
+
+    for i in 0..max_iter {
+        let norm_sqr = z_re * z_re + z_im * z_im;
+        // `escape_time` と同じく、|z|² が NaN になったレーンも発散とみなす（`<=` が偽になる）
+        let escaped = !norm_sqr.cmp_le(bailout).move_mask() & active;
+        if escaped != 0 {
+            for (lane, count) in counts.iter_mut().enumerate() {
+                if escaped & (1 << lane) != 0 {
+                    *count = i;
+                }
+            }
+            active &= !escaped;
+            if active == 0 {
+                break;
+            }
+        }
+        // z * z + c（Complex の掛け算と同じ順序で計算する）
+        let re = z_re * z_re - z_im * z_im + c_re;
+        z_im = z_re * z_im + z_im * z_re + c_im;
+        z_re = re;
+    }
+    counts
+}
+
+/// `escape_time_x4` のスカラー版（`simd` フィーチャーが無効のとき）
+#[cfg(not(feature = "simd"))]
+pub fn escape_time_x4(c_re: [f64; 4], c_im: [f64; 4], max_iter: u32, bailout_sqr: f64) -> [u32; 4] {
+    std::array::from_fn(|lane| {
+        Fractal::Mandelbrot.escape_time(Complex::new(c_re[lane], c_im[lane]), max_iter, bailout_sqr)
+    })
+}
+
+#[test]
+fn test_escape_time_x4_matches_scalar() {
+    let bounds = (64, 48);
+    for y in 0..bounds.1 {
+        for x in (0..bounds.0).step_by(4) {
+            let points: Vec<_> = (0..4)
+                .map(|k| Region::CLASSIC.pixel_to_point(bounds, (x + k, y)))
+                .collect();
+            let counts = escape_time_x4(std::array::from_fn(|k| points[k].re),
+                                        std::array::from_fn(|k| points[k].im), 200,
+                                        default_bailout_sqr());
+            for k in 0..4 {
+                assert_eq!(counts[k], escape_time(points[k], 200, default_bailout_sqr()), "pixel ({}, {})", x + k, y);
+            }
+        }
+    }
+}
+
+/// RGB バッファを行ごとに並列に、1 行の中は 4 ピクセルずつ `escape_time_x4` で描画する
+///
+/// マンデルブロ集合・f64 のみ。結果は `render_color_parallel` と一致する。
+pub fn render_simd(pixels: &mut [u8], bounds: (usize, usize), region: &Region, max_iter: u32) {
+    render_simd_counted(pixels, bounds, region, max_iter, default_bailout_sqr(), &Palette::Classic,
+                        &AtomicUsize::new(0));
+}
+
+/// `render_simd` と同じだが、発散半径の 2 乗とパレットを選べて、1 行終わるごとに `rows_done` を 1 増やす
+pub fn render_simd_counted(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    palette: &Palette,
+    rows_done: &AtomicUsize,
+) {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let colors = ColorTable::new(palette, max_iter);
+    let grid = region.grid(bounds);
+
+    pixels
+        .par_chunks_mut(bounds.0 * 3)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (i, rgb4) in row.chunks_mut(4 * 3).enumerate() {
+                let x = i * 4;
+                let lanes = rgb4.len() / 3;
+                // 行末で 4 ピクセルに満たない分は、最後の点を繰り返して埋める
+                let points: [Complex<f64>; 4] = std::array::from_fn(|k| {
+                    grid.point((x + k.min(lanes - 1), y))
+                });
+                let counts = escape_time_x4(points.map(|p| p.re), points.map(|p| p.im), max_iter,
+                                            bailout_sqr);
+                for (rgb, &count) in rgb4.chunks_mut(3).zip(&counts) {
+                    rgb.copy_from_slice(&colors.color(count));
+                }
+            }
+            rows_done.fetch_add(1, Ordering::Relaxed);
+        });
+}
+
+#[test]
+fn test_render_simd_matches_scalar() {
+    // 幅が 4 の倍数でない場合も確かめる
+    let bounds = (203, 50);
+    let mut scalar = vec![0u8; bounds.0 * bounds.1 * 3];
+    let mut simd = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color_parallel(&mut scalar, bounds, &Region::CLASSIC, 200);
+    render_simd(&mut simd, bounds, &Region::CLASSIC, 200);
+    assert!(scalar == simd);
+}
+
+/// 全ピクセルの反復回数（行優先）
+///
+/// 重い反復計算と軽い色付けを分けておけば、パレットを変えて塗り直すときに計算し直さずに済む。
+/// 回数は f64 で持つので、整数の回数のほかに小数（滑らかにした回数）も入れられる。
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterBuffer {
+    pub data: Vec<f64>,
+    pub bounds: (usize, usize),
+    /// 内部の点の回数（反復の上限）
+    pub max_iter: u32,
+}
+
+impl IterBuffer {
+    /// `palette` で色を付けた RGB バッファ（内部は黒）
+    pub fn colorize(&self, palette: &Palette) -> Vec<u8> {
+        let _span = tracing::info_span!("colorize", mode = "linear").entered();
+        let colors = ColorTable::new(palette, self.max_iter);
+        let mut pixels = Vec::with_capacity(self.data.len() * 3);
+        for &iter in &self.data {
+            pixels.extend_from_slice(&colors.color(iter as u32));
+        }
+        pixels
+    }
+
+    /// `colorize` と同じだが、RGBA で内部を透明にする（外側のアルファは 255）
+    ///
+    /// ほかの画像に重ねるときに、集合の形に穴が空いたように見える。
+    pub fn colorize_rgba(&self, palette: &Palette) -> Vec<u8> {
+        let _span = tracing::info_span!("colorize", mode = "rgba").entered();
+        let colors = ColorTable::new(palette, self.max_iter);
+        let mut pixels = Vec::with_capacity(self.data.len() * 4);
+        for &iter in &self.data {
+            let [r, g, b] = colors.color(iter as u32);
+            let alpha = if iter >= self.max_iter as f64 { 0 } else { 255 };
+            pixels.extend_from_slice(&[r, g, b, alpha]);
+        }
+        pixels
+    }
+
+    /// 16 ビットのグレースケール（回数 0..=`max_iter` を 0..=65535 に線形に写す。内部は 65535）
+    ///
+    /// `max_iter` が 65535 以下なら、違う回数が同じ値に丸められることはない。
+    pub fn to_gray16(&self) -> Vec<u16> {
+        let scale = u16::MAX as f64 / self.max_iter as f64;
+        self.data
+            .iter()
+            .map(|&iter| (iter.min(self.max_iter as f64) * scale).round() as u16)
+            .collect()
+    }
+
+    /// 反復回数の統計（ヒストグラムは 0..`max_iter` を `bins` 等分する）
+    pub fn stats(&self, bins: usize) -> IterStats {
+        let max_iter = self.max_iter as f64;
+        let mut histogram = vec![0; bins];
+        let (mut min, mut max, mut sum, mut escaped) = (f64::INFINITY, 0.0f64, 0.0, 0);
+        for &iter in self.data.iter().filter(|&&iter| iter < max_iter) {
+            min = min.min(iter);
+            max = max.max(iter);
+            sum += iter;
+            escaped += 1;
+            if bins > 0 {
+                histogram[((iter / max_iter * bins as f64) as usize).min(bins - 1)] += 1;
+            }
+        }
+
+        let interior = self.data.len() - escaped;
+        IterStats {
+            min: if escaped > 0 { min } else { 0.0 },
+            max,
+            mean: if escaped > 0 { sum / escaped as f64 } else { 0.0 },
+            interior_fraction: if self.data.is_empty() { 0.0 } else { interior as f64 / self.data.len() as f64 },
+            histogram,
+        }
+    }
+
+    /// 反復回数の分散がいちばん大きい `tile`×`tile` ピクセルのタイルの中心（ピクセル座標）
+    ///
+    /// 右端・下端の半端なタイルも 1 枚として数える。分散が同じなら上・左にあるタイルを選ぶ。
+    /// 画像が空なら `None`。
+    pub fn busiest_tile(&self, tile: usize) -> Option<(f64, f64)> {
+        let (width, height) = self.bounds;
+        let tile = tile.max(1);
+        let mut busiest: Option<(f64, (f64, f64))> = None;
+        for top in (0..height).step_by(tile) {
+            for left in (0..width).step_by(tile) {
+                let (tile_width, tile_height) = (tile.min(width - left), tile.min(height - top));
+                let values = (top..top + tile_height)
+                    .flat_map(|y| &self.data[y * width + left..y * width + left + tile_width]);
+                let count = (tile_width * tile_height) as f64;
+                let mean = values.clone().sum::<f64>() / count;
+                let variance = values.map(|&iter| (iter - mean).powi(2)).sum::<f64>() / count;
+
+                if busiest.is_none_or(|(max, _)| variance > max) {
+                    let center = |start: usize, size: usize| start as f64 + (size - 1) as f64 / 2.0;
+                    busiest = Some((variance, (center(left, tile_width), center(top, tile_height))));
+                }
+            }
+        }
+        busiest.map(|(_, center)| center)
+    }
+
+    /// ヒストグラム平坦化で色を付けた RGB バッファ
+    ///
+    /// 反復回数の代わりに累積順位をパレットに渡すので、ピクセルが密集している
+    /// 反復回数の帯ほど多くの色が割り当てられる。内部は黒のまま。
+    /// 数える・塗るのはどちらも並列だが、結果はスレッド数によらずバイト単位で同じ（`build_histogram`）。
+    pub fn colorize_histogram(&self, palette: &Palette) -> Vec<u8> {
+        self.colorize_histogram_with(|t, _| palette.color_at(t))
+    }
+
+    /// `colorize_histogram` と同じだが、色を u8 に丸めるときに `dither` をかける
+    pub fn colorize_histogram_dithered(&self, palette: &Palette) -> Vec<u8> {
+        self.colorize_histogram_with(|t, pixel| dither(palette.color_at_exact(t), pixel))
+    }
+
+    /// ヒストグラム平坦化の累積順位 t とピクセルの位置から `color(t, pixel)` で色を付ける（内部は黒）
+    fn colorize_histogram_with(&self, color: impl Fn(f32, (usize, usize)) -> [u8; 3] + Sync) -> Vec<u8> {
+        let _span = tracing::info_span!("colorize", mode = "histogram").entered();
+        let cdf = cumulative_distribution(&build_histogram(self));
+
+        // 各ピクセルの色はそのピクセルの回数と位置だけで決まる
+        let mut pixels = vec![0u8; self.data.len() * 3];
+        pixels.par_chunks_mut(3).zip(self.data.par_iter()).enumerate().for_each(|(i, (rgb, &iter))| {
+            if iter < self.max_iter as f64 {
+                rgb.copy_from_slice(&color(cdf[iter as usize], (i % self.bounds.0, i / self.bounds.0)));
+            }
+        });
+        pixels
+    }
+
+    /// 反復回数を高さとみなした面を `light` で照らしたときの明るさ（行優先、0.0..=1.0）
+    ///
+    /// 法線は隣り合うピクセルの回数の差（画像の端では片側の差）から求め、光源の向きとの内積を取る
+    /// （ランバート反射）。内部のピクセルの高さは `max_iter`。
+    pub fn shading(&self, light: Light) -> Vec<f64> {
+        let (width, height) = self.bounds;
+        let light = light.direction();
+        let at = |x: usize, y: usize| self.data[y * width + x];
+        // 両隣があれば中心差分、端では片側の差分（1 ピクセルしかなければ傾きなし）
+        let slope = |before: f64, after: f64, span: usize| {
+            if span > 0 { (after - before) / span as f64 } else { 0.0 }
+        };
+
+        let mut shades = Vec::with_capacity(self.data.len());
+        for y in 0..height {
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            for x in 0..width {
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let dx = slope(at(left, y), at(right, y), right - left);
+                let dy = slope(at(x, up), at(x, down), down - up);
+                let length = (dx * dx + dy * dy + 1.0).sqrt();
+                let dot = (-dx * light[0] - dy * light[1] + light[2]) / length;
+                shades.push(dot.max(0.0));
+            }
+        }
+        shades
+    }
+
+    /// `palette` の色を `shading` の明るさで暗くした RGB バッファ（内部は黒）
+    ///
+    /// 光の当たらない面も真っ暗にはならないよう、`SHADE_AMBIENT` だけは明るさを残す。
+    pub fn colorize_shaded(&self, palette: &Palette, light: Light) -> Vec<u8> {
+        let _span = tracing::info_span!("colorize", mode = "shaded").entered();
+        let colors = ColorTable::new(palette, self.max_iter);
+        let mut pixels = Vec::with_capacity(self.data.len() * 3);
+        for (&iter, shade) in self.data.iter().zip(self.shading(light)) {
+            let brightness = SHADE_AMBIENT + (1.0 - SHADE_AMBIENT) * shade;
+            let color = colors.color(iter as u32).map(|channel| (channel as f64 * brightness).round() as u8);
+            pixels.extend_from_slice(&color);
+        }
+        pixels
+    }
+}
+
+/// `colorize_shaded` で光の当たらない面にも残す明るさ
+pub const SHADE_AMBIENT: f64 = 0.25;
+
+/// 陰影を付けるときの光源の向き（`--light AZ,ELEV`。角度は度）
+///
+/// 方位角 `azimuth` は画像の右を 0° として反時計回り（90° で上から）、仰角 `elevation` は画面からの高さ。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+impl Light {
+    /// 左上から 45° の高さで照らす
+    pub const DEFAULT: Light = Light { azimuth: 135.0, elevation: 45.0 };
+
+    /// 光源へ向かう単位ベクトル（x は右、y は下、z は画面の手前）
+    pub fn direction(self) -> [f64; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [elevation.cos() * azimuth.cos(), -elevation.cos() * azimuth.sin(), elevation.sin()]
+    }
+}
+
+#[test]
+fn test_shading() {
+    // x 方向に 1 ずつ高くなる坂は左を向いているので、左から照らすと最も明るい
+    let bounds = (8, 5);
+    let ramp = IterBuffer {
+        data: (0..bounds.0 * bounds.1).map(|i| (i % bounds.0) as f64).collect(),
+        bounds,
+        max_iter: 100,
+    };
+    let brightness = |azimuth: f64| ramp.shading(Light { azimuth, elevation: 45.0 });
+    let from_left = brightness(180.0);
+    for shade in &from_left {
+        assert!((shade - 1.0).abs() < 1e-12);
+    }
+    for azimuth in [0.0, 45.0, 90.0, 135.0, 225.0, 270.0] {
+        assert!(brightness(azimuth).iter().zip(&from_left).all(|(shade, best)| shade < best), "{}", azimuth);
+    }
+    // 右から照らすと光が面と平行になり、当たらない
+    assert!(brightness(0.0).iter().all(|&shade| shade.abs() < 1e-12));
+
+    // 山の形なら、光の来る側の斜面だけが明るい
+    let peak = IterBuffer { data: vec![0.0, 1.0, 2.0, 3.0, 2.0, 1.0, 0.0], bounds: (7, 1), max_iter: 100 };
+    let shades = peak.shading(Light { azimuth: 180.0, elevation: 30.0 });
+    assert!(shades[0] > shades[5] && shades[1] > shades[6]);
+    assert_eq!(shades[0], shades[1]);
+
+    // 平らなら真上からの光で最も明るく、色は陰影なしの色と同じ
+    let flat = IterBuffer { data: vec![10.0; 12], bounds: (4, 3), max_iter: 100 };
+    let overhead = Light { azimuth: 0.0, elevation: 90.0 };
+    assert!(flat.shading(overhead).iter().all(|&shade| (shade - 1.0).abs() < 1e-12));
+    let palette = Palette::Classic;
+    assert_eq!(flat.colorize_shaded(&palette, overhead), flat.colorize(&palette));
+}
+
+#[test]
+fn test_iter_buffer_colorize() {
+    let bounds = (60, 40);
+    let max_iter = 200;
+    let iters = compute_iterations(bounds, &Region::CLASSIC, max_iter);
+    assert_eq!(iters.data.len(), bounds.0 * bounds.1);
+
+    // 1 回計算した反復回数を 2 つのパレットで塗り分ける
+    let before = iters.clone();
+    let classic = iters.colorize(&Palette::Classic);
+    let hsv = iters.colorize(&Palette::Hsv { cycles: 3.0 });
+    assert_eq!(iters, before);
+    assert!(classic != hsv);
+
+    // どちらもそのパレットで直接描画したものと一致する
+    for (palette, pixels) in [(Palette::Classic, &classic), (Palette::Hsv { cycles: 3.0 }, &hsv)] {
+        let mut expected = vec![0u8; bounds.0 * bounds.1 * 3];
+        render_color_parallel_counted::<f64>(&mut expected, bounds, &Region::CLASSIC, max_iter,
+                                             default_bailout_sqr(), Fractal::Mandelbrot, &palette,
+                                             InteriorColor::Black, &AtomicUsize::new(0), &AtomicBool::new(false));
+        assert!(*pixels == expected, "{:?}", palette);
+    }
+}
+
+#[test]
+fn test_iter_buffer_colorize_rgba() {
+    let bounds = (60, 40);
+    let max_iter = 200;
+    let iters = compute_iterations(bounds, &Region::CLASSIC, max_iter);
+    let rgba = iters.colorize_rgba(&Palette::Classic);
+    assert_eq!(rgba.len(), bounds.0 * bounds.1 * 4);
+
+    // 内部は透明、外側は不透明で、色は colorize と同じ
+    let rgb = iters.colorize(&Palette::Classic);
+    for ((pixel, color), &iter) in rgba.chunks(4).zip(rgb.chunks(3)).zip(&iters.data) {
+        assert_eq!(pixel[3], if iter >= max_iter as f64 { 0 } else { 255 });
+        assert_eq!(&pixel[..3], color);
+    }
+    assert!(rgba.chunks(4).any(|pixel| pixel[3] == 0));
+    assert!(rgba.chunks(4).any(|pixel| pixel[3] == 255));
+}
+
+/// 集合の境界のピクセル (x, y) を行優先で返す
+///
+/// 内部（`max_iter` に達した）ピクセルのうち、上下左右に発散したピクセルがあるものを境界とする。
+/// 画像の外は隣として数えないので、画像の端まで内部が続いていてもそこは境界にならない。
+pub fn extract_boundary(iters: &IterBuffer) -> Vec<(usize, usize)> {
+    let (width, height) = iters.bounds;
+    let interior = |x: usize, y: usize| iters.data[y * width + x] >= iters.max_iter as f64;
+
+    let mut boundary = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            if !interior(x, y) {
+                continue;
+            }
+            let neighbors = [
+                (x.checked_sub(1), Some(y)),
+                (Some(x + 1).filter(|&x| x < width), Some(y)),
+                (Some(x), y.checked_sub(1)),
+                (Some(x), Some(y + 1).filter(|&y| y < height)),
+            ];
+            let escaped = neighbors.iter().any(|&neighbor| match neighbor {
+                (Some(nx), Some(ny)) => !interior(nx, ny),
+                _ => false,
+            });
+            if escaped {
+                boundary.push((x, y));
+            }
+        }
+    }
+    boundary
+}
+
+#[test]
+fn test_extract_boundary() {
+    // 9 が内部（max_iter = 9）。右下の角まで内部が続く
+    let data = vec![
+        1.0, 2.0, 2.0, 2.0, 1.0,
+        2.0, 9.0, 9.0, 9.0, 2.0,
+        2.0, 9.0, 9.0, 9.0, 9.0,
+        2.0, 9.0, 9.0, 9.0, 9.0,
+    ];
+    let iters = IterBuffer { data, bounds: (5, 4), max_iter: 9 };
+    assert_eq!(extract_boundary(&iters), [
+        (1, 1), (2, 1), (3, 1),
+        (1, 2), (4, 2),
+        (1, 3),
+    ]);
+
+    // すべて内部・すべて発散なら境界はない
+    assert!(extract_boundary(&IterBuffer { data: vec![9.0; 6], bounds: (3, 2), max_iter: 9 }).is_empty());
+    assert!(extract_boundary(&IterBuffer { data: vec![1.0; 6], bounds: (3, 2), max_iter: 9 }).is_empty());
+}
+
+/// 反復回数の統計（`IterBuffer::stats`）
+///
+/// 内部のピクセルが多すぎたり、最大値が上限のすぐ手前に集まっていたりすれば、`max_iter` が足りない。
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterStats {
+    /// 発散したピクセルの反復回数の最小値（発散したピクセルがなければ 0）
+    pub min: f64,
+    /// 発散したピクセルの反復回数の最大値（同上）
+    pub max: f64,
+    /// 発散したピクセルの反復回数の平均（同上）
+    pub mean: f64,
+    /// 内部（`max_iter` まで発散しなかった）ピクセルの割合
+    pub interior_fraction: f64,
+    /// 発散したピクセルの反復回数のヒストグラム（0..`max_iter` を等分した区間ごとの個数）
+    pub histogram: Vec<usize>,
+}
+
+#[test]
+fn test_iter_buffer_stats() {
+    let iters = IterBuffer {
+        data: vec![1.0, 3.0, 5.0, 11.0, 20.0, 20.0, 2.0, 20.0],
+        bounds: (4, 2),
+        max_iter: 20,
+    };
+    let stats = iters.stats(4);
+    assert_eq!((stats.min, stats.max, stats.mean), (1.0, 11.0, 4.4));
+    assert_eq!(stats.interior_fraction, 3.0 / 8.0);
+    // 0..5, 5..10, 10..15, 15..20
+    assert_eq!(stats.histogram, [3, 1, 1, 0]);
+
+    // すべて内部
+    let stats = IterBuffer { data: vec![20.0; 4], bounds: (2, 2), max_iter: 20 }.stats(4);
+    assert_eq!((stats.min, stats.max, stats.mean, stats.interior_fraction), (0.0, 0.0, 0.0, 1.0));
+    assert_eq!(stats.histogram, [0; 4]);
+}
+
+#[test]
+fn test_busiest_tile() {
+    // 8x6 のうち、右下の 4x2 のタイルだけ反復回数がばらつく
+    let mut data = vec![5.0; 8 * 6];
+    for (i, &iter) in [1.0, 40.0, 3.0, 90.0, 100.0, 2.0, 60.0, 7.0].iter().enumerate() {
+        data[(4 + i / 4) * 8 + 4 + i % 4] = iter;
+    }
+    let iters = IterBuffer { data, bounds: (8, 6), max_iter: 100 };
+    assert_eq!(iters.busiest_tile(4), Some((5.5, 4.5)));
+    let region = Region::from_corners(Complex::new(0.0, 6.0), Complex::new(8.0, 0.0)).unwrap();
+    assert_eq!(find_detail(&iters, &region, 4), Some(Complex::new(5.5, 1.5)));
+
+    // どこも同じなら左上のタイル
+    let flat = IterBuffer { data: vec![3.0; 8 * 6], bounds: (8, 6), max_iter: 100 };
+    assert_eq!(flat.busiest_tile(4), Some((1.5, 1.5)));
+    assert_eq!(IterBuffer { data: Vec::new(), bounds: (0, 0), max_iter: 100 }.busiest_tile(4), None);
+}
+
+/// `find_detail` で分散を比べるタイルの大きさ（粗く描いた画像のピクセル）
+pub const DETAIL_TILE: usize = 8;
+
+/// 粗く描いた反復回数 `iters` から、いちばん入り組んだ（反復回数の分散が大きい）タイルの中心の点を探す
+///
+/// `region` は `iters` を描いた範囲。拡大する先の候補（`--find-detail`）として使う。
+pub fn find_detail(iters: &IterBuffer, region: &Region, tile: usize) -> Option<Complex<f64>> {
+    let (x, y) = iters.busiest_tile(tile)?;
+    Some(Complex::new(
+        region.upper_left.re + x / iters.bounds.0 as f64 * region.width(),
+        region.upper_left.im - y / iters.bounds.1 as f64 * region.height(),
+    ))
+}
+
+/// 全ピクセルの反復回数を行優先で計算する
+pub fn compute_iterations(bounds: (usize, usize), region: &Region, max_iter: u32) -> IterBuffer {
+    compute_iterations_counted::<f64>(bounds, region, max_iter, default_bailout_sqr(),
+                                      Fractal::Mandelbrot, &AtomicUsize::new(0))
+}
+
+/// `compute_iterations` と同じだが、型 `T` で計算し、1 行終わるごとに `rows_done` を 1 増やす
+///
+/// 発散半径の 2 乗は `bailout_sqr` で指定する。
+pub fn compute_iterations_counted<T: Float + Send + Sync>(
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    rows_done: &AtomicUsize,
+) -> IterBuffer {
+    compute_iterations_counted_as(bounds, &region.cast::<T>(), max_iter, bailout_sqr, fractal, rows_done)
+}
+
+/// `compute_iterations_counted` と同じだが、範囲を型 `T` の座標で渡す（`Region::from_center_zoom_as`）
+pub fn compute_iterations_counted_as<T: Float + Send + Sync>(
+    bounds: (usize, usize),
+    region: &Region<T>,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    rows_done: &AtomicUsize,
+) -> IterBuffer {
+    let _span = tracing::info_span!("compute_iterations", width = bounds.0, height = bounds.1, max_iter).entered();
+    let grid = region.grid(bounds);
+    let mut data = vec![0.0; bounds.0 * bounds.1];
+
+    data
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, iter) in row.iter_mut().enumerate() {
+                let point = grid.point((x, y));
+                *iter = fractal.escape_time(point, max_iter, bailout_sqr) as f64;
+            }
+            rows_done.fetch_add(1, Ordering::Relaxed);
+        });
+    IterBuffer { data, bounds, max_iter }
+}
+
+/// `render_progressive` の最初のパスで計算する点の間隔（ピクセル）
+pub const PROGRESSIVE_START_STEP: usize = 8;
+
+/// 粗い解像度から順に解像度を 2 倍ずつ上げて反復回数を求め、パスが終わるごとに `callback` を呼ぶ
+///
+/// 最初のパスは `PROGRESSIVE_START_STEP` ピクセルおきの点だけを計算し、間のピクセルは左上の点の値で
+/// 埋める。次のパスからは前のパスで計算済みの点は計算し直さず、新しく格子に加わった点だけを計算する。
+/// `callback` には毎回 `bounds` の大きさのバッファを渡すので、そのまま表示できる。
+/// 最後のパスの結果は `compute_iterations` と同じになり、それを返す。
+pub fn render_progressive<F>(
+    region: &Region,
+    bounds: (usize, usize),
+    max_iter: u32,
+    mut callback: F,
+) -> IterBuffer
+where
+    F: FnMut(&IterBuffer),
+{
+    let _span = tracing::info_span!("render_progressive", width = bounds.0, height = bounds.1).entered();
+    let grid = region.grid(bounds);
+    let width = bounds.0;
+    let mut buffer = IterBuffer { data: vec![0.0; bounds.0 * bounds.1], bounds, max_iter };
+    if buffer.data.is_empty() {
+        callback(&buffer);
+        return buffer;
+    }
+
+    let mut step = PROGRESSIVE_START_STEP;
+    loop {
+        // step 行ずつの帯に分け、帯の先頭の行の格子点を計算して帯全体を埋める
+        buffer.data.par_chunks_mut(step * width).enumerate().for_each(|(band, rows)| {
+            let y = band * step;
+            let (row, rest) = rows.split_at_mut(width);
+            for x in (0..width).step_by(step) {
+                let computed =
+                    step < PROGRESSIVE_START_STEP && x.is_multiple_of(2 * step) && y.is_multiple_of(2 * step);
+                if !computed {
+                    let point = grid.point((x, y));
+                    row[x] = Fractal::Mandelbrot.escape_time(point, max_iter, default_bailout_sqr()) as f64;
+                }
+                let value = row[x];
+                row[x + 1..(x + step).min(width)].fill(value);
+            }
+            for next in rest.chunks_mut(width) {
+                next.copy_from_slice(row);
+            }
+        });
+        callback(&buffer);
+
+        if step == 1 {
+            return buffer;
+        }
+        step /= 2;
+    }
+}
+
+#[test]
+fn test_render_progressive() {
+    let bounds = (37, 21);
+    let region = Region::CLASSIC;
+    let direct = compute_iterations(bounds, &region, 200);
+
+    let mut passes = Vec::new();
+    let progressive = render_progressive(&region, bounds, 200, |buffer| passes.push(buffer.clone()));
+    assert_eq!(progressive, direct);
+
+    // 8 → 4 → 2 → 1 ピクセルおきの 4 パス
+    assert_eq!(passes.len(), 4);
+    assert_eq!(passes.last(), Some(&direct));
+    for (pass, buffer) in passes.iter().enumerate() {
+        let step = PROGRESSIVE_START_STEP >> pass;
+        assert_eq!(buffer.bounds, bounds);
+        for y in 0..bounds.1 {
+            for x in 0..bounds.0 {
+                // 各ピクセルはそのパスの格子で左上にある点の値
+                let corner = (y - y % step) * bounds.0 + (x - x % step);
+                assert_eq!(buffer.data[y * bounds.0 + x], direct.data[corner], "pass {}", pass);
+            }
+        }
+    }
+
+    // 空の画像でも 1 回は呼ぶ
+    let mut calls = 0;
+    let empty = render_progressive(&region, (0, 5), 200, |_| calls += 1);
+    assert!(empty.data.is_empty());
+    assert_eq!(calls, 1);
+}
+
+/// 粗く計算した反復回数 `src` を双線形補間で `new_bounds` の大きさに引き伸ばす
+///
+/// 計算し直さずにプレビューを大きく滑らかに見せるためのもの（`colorize` などで色を付けて使う）。
+/// ピクセルの中心どうしを対応させ、`src` の外にはみ出す位置は端のピクセルの値で止める。
+/// 補間した値は近似なので、内部（`max_iter`）との境目では中間の回数になる。
+pub fn upsample_iterbuffer(src: &IterBuffer, new_bounds: (usize, usize)) -> IterBuffer {
+    let (src_width, src_height) = src.bounds;
+    let mut data = vec![0.0; new_bounds.0 * new_bounds.1];
+    if src.data.is_empty() || data.is_empty() {
+        return IterBuffer { data, bounds: new_bounds, max_iter: src.max_iter };
+    }
+
+    // 新しいピクセル → src の 2 つの隣り合うピクセルと、後ろのピクセルの重み
+    let neighbors = |index: usize, new_size: usize, src_size: usize| {
+        let position = ((index as f64 + 0.5) * src_size as f64 / new_size as f64 - 0.5)
+            .clamp(0.0, (src_size - 1) as f64);
+        let low = position.floor() as usize;
+        (low, (low + 1).min(src_size - 1), position - low as f64)
+    };
+    let at = |x: usize, y: usize| src.data[y * src_width + x];
+
+    data.par_chunks_mut(new_bounds.0).enumerate().for_each(|(y, row)| {
+        let (y0, y1, wy) = neighbors(y, new_bounds.1, src_height);
+        for (x, iter) in row.iter_mut().enumerate() {
+            let (x0, x1, wx) = neighbors(x, new_bounds.0, src_width);
+            let top = at(x0, y0) * (1.0 - wx) + at(x1, y0) * wx;
+            let bottom = at(x0, y1) * (1.0 - wx) + at(x1, y1) * wx;
+            *iter = top * (1.0 - wy) + bottom * wy;
+        }
+    });
+    IterBuffer { data, bounds: new_bounds, max_iter: src.max_iter }
+}
+
+#[test]
+fn test_upsample_iterbuffer() {
+    // 2x2 → 3x3 なら、真ん中は 4 つの平均、辺の中点は 2 つの平均、角はそのまま
+    let src = IterBuffer { data: vec![0.0, 10.0, 20.0, 50.0], bounds: (2, 2), max_iter: 100 };
+    let upsampled = upsample_iterbuffer(&src, (3, 3));
+    assert_eq!(upsampled.bounds, (3, 3));
+    assert_eq!(upsampled.max_iter, 100);
+    assert_eq!(upsampled.data, [
+        0.0, 5.0, 10.0,
+        10.0, 20.0, 30.0,
+        20.0, 35.0, 50.0,
+    ]);
+
+    // 同じ大きさならそのまま、端の外側は端の値で止まる
+    assert_eq!(upsample_iterbuffer(&src, (2, 2)), src);
+    let wide = upsample_iterbuffer(&src, (4, 1));
+    assert_eq!(wide.data, [10.0, 15.0, 25.0, 30.0]);
+
+    // 引き伸ばした回数にもそのまま色を付けられる
+    let region = Region::CLASSIC;
+    let coarse = compute_iterations((30, 20), &region, 100);
+    let preview = upsample_iterbuffer(&coarse, (90, 60));
+    assert_eq!(preview.colorize(&Palette::Classic).len(), 90 * 60 * 3);
+    assert!(upsample_iterbuffer(&coarse, (0, 60)).data.is_empty());
+}
+
+#[cfg(feature = "high-precision")]
+#[test]
+fn test_high_precision_resolves_deep_zoom() {
+    use double_double::DoubleDouble;
+
+    // i の近くを 1e17 倍に拡大すると、f64 ではピクセルの座標がすべて同じ値に丸められて一色になる
+    let bounds = (8, 8);
+    let (center, zoom) = (Complex::new(0.0, 1.0), 1e17);
+    let distinct = |iters: IterBuffer| {
+        let mut counts: Vec<u32> = iters.data.iter().map(|&iter| iter as u32).collect();
+        counts.sort();
+        counts.dedup();
+        counts.len()
+    };
+
+    let region = Region::from_center_zoom(bounds, center, zoom);
+    let flat = compute_iterations_counted::<f64>(bounds, &region, 2000, default_bailout_sqr(),
+                                                 Fractal::Mandelbrot, &AtomicUsize::new(0));
+    assert_eq!(distinct(flat), 1);
+
+    // 倍々精度なら境界の細部が見え、反復回数がばらける
+    let region = Region::<DoubleDouble>::from_center_zoom_as(bounds, center, zoom);
+    let resolved = compute_iterations_counted_as(bounds, &region, 2000, default_bailout_sqr(), Fractal::Mandelbrot,
+                                                 &AtomicUsize::new(0));
+    assert!(distinct(resolved) >= 5);
+
+    // 浅い倍率では f64 と同じ範囲になる
+    let shallow = Region::<DoubleDouble>::from_center_zoom_as(bounds, center, 4.0);
+    assert_eq!(shallow.upper_left.re.hi(), Region::from_center_zoom(bounds, center, 4.0).upper_left.re);
+}
+
+/// 摂動論で深い拡大を描くときのグリッチの判定の閾値
+///
+/// 参照軌道との差を足した z が参照軌道の点 Z よりずっと小さくなると、差 δz の相対誤差が大きくなり
+/// 周りのピクセルと同じ回数が並ぶ（グリッチ）。|z|² < `GLITCH_TOLERANCE`·|Z|² のピクセルは直接計算し直す。
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+/// マンデルブロ集合の全ピクセルの反復回数を摂動論で計算する（発散半径は `DEFAULT_BAILOUT`）
+///
+/// 範囲の中心の軌道 Zₙ だけを型 `T`（`DoubleDouble` など）で計算し、各ピクセルは中心との差
+/// δzₙ₊₁ = 2Zₙδzₙ + δzₙ² + δc を f64 で計算する。ピクセルごとに `T` で反復するよりずっと速い。
+/// グリッチになったピクセルと、参照軌道が先に発散してしまったピクセルは `T` で直接計算する。
+pub fn render_perturbation<T: Float + Send + Sync>(
+    bounds: (usize, usize),
+    region: &Region<T>,
+    max_iter: u32,
+) -> IterBuffer {
+    let bailout_sqr = default_bailout_sqr::<f64>();
+    let to_f64 = |z: Complex<T>| Complex::new(z.re.to_f64().unwrap(), z.im.to_f64().unwrap());
+
+    // 参照軌道（Z₀ = 0 から、発散するか上限に達するまで）
+    let center = (region.upper_left + region.lower_right) / T::from(2.0).unwrap();
+    let mut reference = vec![Complex::new(0.0, 0.0)];
+    let mut z = Complex::new(T::zero(), T::zero());
+    for _ in 0..max_iter {
+        if z.norm_sqr() > T::from(bailout_sqr).unwrap() {
+            break;
+        }
+        z = z * z + center;
+        reference.push(to_f64(z));
+    }
+
+    let grid = region.grid(bounds);
+    let mut data = vec![0.0; bounds.0 * bounds.1];
+
+    data
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, iter) in row.iter_mut().enumerate() {
+                let point = grid.point((x, y));
+                let delta_c = to_f64(point - center);
+                let mut delta_z = Complex::new(0.0, 0.0);
+                let mut count = max_iter;
+                for (n, &z_ref) in reference.iter().enumerate().take(max_iter as usize) {
+                    let z = z_ref + delta_z;
+                    if z.norm_sqr() > bailout_sqr {
+                        count = n as u32;
+                        break;
+                    }
+                    if z.norm_sqr() < GLITCH_TOLERANCE * z_ref.norm_sqr() || n + 1 >= reference.len() {
+                        count = Fractal::Mandelbrot.escape_time(point, max_iter, bailout_sqr);
+                        break;
+                    }
+                    delta_z = z_ref * delta_z * 2.0 + delta_z * delta_z + delta_c;
+                }
+                *iter = count as f64;
+            }
+        });
+    IterBuffer { data, bounds, max_iter }
+}
+
+#[test]
+fn test_render_perturbation_matches_direct() {
+    // f64 で直接計算しても精度が足りる倍率なら、ほぼ同じ回数になる（境界の近くで 1 回ずれる程度）
+    let bounds = (32, 32);
+    let region = Region::from_center_zoom(bounds, Complex::new(-0.743643887037151, 0.131825904205330), 1e5);
+    let direct = compute_iterations(bounds, &region, 2000);
+    let perturbed = render_perturbation(bounds, &region, 2000);
+    let mismatched = direct.data.iter().zip(&perturbed.data).filter(|(a, b)| (*a - *b).abs() > 1.0).count();
+    assert!(mismatched * 100 <= direct.data.len());
+}
+
+#[cfg(feature = "high-precision")]
+#[test]
+fn test_render_perturbation_deep_zoom() {
+    use double_double::DoubleDouble;
+
+    // f64 では潰れる倍率で、倍々精度で全ピクセルを直接計算した結果と比べる
+    let bounds = (32, 32);
+    for (center, zoom, max_iter) in [(Complex::new(0.0, 1.0), 1e25, 3000),
+                                     (Complex::new(-0.743643887037151, 0.131825904205330), 1e12, 5000)] {
+        let region = Region::<DoubleDouble>::from_center_zoom_as(bounds, center, zoom);
+        let direct = compute_iterations_counted_as(bounds, &region, max_iter, default_bailout_sqr(),
+                                                   Fractal::Mandelbrot, &AtomicUsize::new(0));
+        let perturbed = render_perturbation(bounds, &region, max_iter);
+        let mismatched = direct.data.iter().zip(&perturbed.data).filter(|(a, b)| (*a - *b).abs() > 1.0).count();
+        assert!(mismatched * 100 <= direct.data.len());
+    }
+}
+
+/// 反復回数 → RGB 色変換（滑らかなグラデーション）
+pub fn color_map(iter: u32, max_iter: u32) -> [u8; 3] {
+    if iter >= max_iter {
+        return [0, 0, 0]; // 内部は緑
+    }
+
+    palette(iter as f32 / max_iter as f32)
+}
+
+/// 反復回数 → RGB 色変換（色相が `cycles` 周する虹色）
+///
+/// 反復回数の帯ごとに色相がはっきり変わるので、深くズームしたときの縞模様が見やすい。
+/// 内部は黒。
+pub fn color_map_hsv(iter: u32, max_iter: u32, cycles: f32) -> [u8; 3] {
+    if iter >= max_iter {
+        return [0, 0, 0];
+    }
+
+    rainbow(iter as f32 / max_iter as f32, cycles)
+}
+
+/// 0.0..=1.0 の値 → 色相が 0° から `cycles` 周する虹色（彩度・明度は固定）
+fn rainbow(t: f32, cycles: f32) -> [u8; 3] {
+    rainbow_exact(t, cycles).map(|channel| channel.round() as u8)
+}
+
+/// `rainbow` の丸める前の値
+fn rainbow_exact(t: f32, cycles: f32) -> [f32; 3] {
+    hsv_to_rgb_exact((t * cycles).fract() * 360.0, 0.85, 1.0)
+}
+
+/// HSV → RGB（色相 `hue` は度、彩度 `saturation`・明度 `value` は 0.0..=1.0）
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    hsv_to_rgb_exact(hue, saturation, value).map(|channel| channel.round() as u8)
+}
+
+/// `hsv_to_rgb` の丸める前の値（各チャンネル 0.0..=255.0）
+fn hsv_to_rgb_exact(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r, g, b].map(|channel| (channel + m) * 255.0)
+}
+
+#[test]
+fn test_hsv_to_rgb() {
+    assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(hsv_to_rgb(60.0, 1.0, 1.0), [255, 255, 0]);
+    assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), [0, 255, 0]);
+    assert_eq!(hsv_to_rgb(180.0, 1.0, 1.0), [0, 255, 255]);
+    assert_eq!(hsv_to_rgb(240.0, 1.0, 1.0), [0, 0, 255]);
+    assert_eq!(hsv_to_rgb(300.0, 1.0, 1.0), [255, 0, 255]);
+    assert_eq!(hsv_to_rgb(360.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(hsv_to_rgb(-120.0, 1.0, 1.0), [0, 0, 255]);
+
+    // 彩度 0 は灰色、明度 0 は黒
+    assert_eq!(hsv_to_rgb(200.0, 0.0, 0.5), [128, 128, 128]);
+    assert_eq!(hsv_to_rgb(200.0, 1.0, 0.0), [0, 0, 0]);
+}
+
+#[test]
+fn test_color_map_hsv() {
+    // 内部は黒
+    assert_eq!(color_map_hsv(100, 100, 3.0), [0, 0, 0]);
+
+    // 1 周なら t = 0 と t = 1/3 で色相が 120° ずれる
+    assert_eq!(color_map_hsv(0, 90, 1.0), hsv_to_rgb(0.0, 0.85, 1.0));
+    assert_eq!(color_map_hsv(30, 90, 1.0), hsv_to_rgb(120.0, 0.85, 1.0));
+
+    // cycles 周すると同じ色に戻る
+    assert_eq!(color_map_hsv(25, 100, 4.0), color_map_hsv(0, 100, 4.0));
+    assert_eq!(color_map_hsv(50, 100, 4.0), color_map_hsv(0, 100, 4.0));
+}
+
+/// 反復回数に色を付けるパレット（`--palette`）
+#[derive(Clone, Debug, PartialEq)]
+pub enum Palette {
+    /// 青→紫→赤→黄のグラデーション（`palette`）
+    Classic,
+    /// 色相が `cycles` 周する虹色（`color_map_hsv`）
+    Hsv { cycles: f32 },
+    /// 任意のグラデーション（`--palette-image` で参照画像から作ったものなど）
+    Gradient(Gradient),
+    /// `palette` を 0.0..=1.0 の間で `times` 回繰り返したもの（`--repeat-palette`）
+    Repeat { palette: Box<Palette>, times: u32 },
+}
+
+impl Palette {
+    /// 反復回数 → RGB（内部は黒）
+    pub fn color(&self, iter: u32, max_iter: u32) -> [u8; 3] {
+        if iter >= max_iter {
+            return [0, 0, 0];
+        }
+
+        self.color_at(iter as f32 / max_iter as f32)
+    }
+
+    /// 0.0..=1.0 の値 → RGB（ヒストグラム平坦化の累積順位など）
+    pub fn color_at(&self, t: f32) -> [u8; 3] {
+        match self {
+            Palette::Classic => palette(t),
+            Palette::Hsv { cycles } => rainbow(t, *cycles),
+            Palette::Gradient(gradient) => gradient.color(t),
+            Palette::Repeat { palette, times } => palette.color_at(repeat_palette(t, *times)),
+        }
+    }
+
+    /// `color_at` の丸める前の値（各チャンネル 0.0..=255.0。`dither` に渡す）
+    pub fn color_at_exact(&self, t: f32) -> [f32; 3] {
+        match self {
+            Palette::Classic => palette_exact(t),
+            Palette::Hsv { cycles } => rainbow_exact(t, *cycles),
+            Palette::Gradient(gradient) => gradient.color_exact(t),
+            Palette::Repeat { palette, times } => palette.color_at_exact(repeat_palette(t, *times)),
+        }
+    }
+}
+
+/// 0.0..=1.0 の値を、パレットを `repeat` 回繰り返したときの位置 `frac(t * repeat)` に直す
+///
+/// `repeat` が 1 以下なら `t` をそのまま返す（`t = 1.0` も最後の色のまま）。
+pub fn repeat_palette(t: f32, repeat: u32) -> f32 {
+    if repeat <= 1 {
+        return t;
+    }
+
+    (t * repeat as f32).fract()
+}
+
+#[test]
+fn test_gradient_sample() {
+    // 黒と白の 2 色なら、真ん中は灰色
+    let gray = Gradient::sample(&[[0, 0, 0], [255, 255, 255]], 256);
+    assert_eq!(gray.color(0.0), [0, 0, 0]);
+    assert_eq!(gray.color(0.5), [128, 128, 128]);
+    assert_eq!(gray.color(1.0), [255, 255, 255]);
+
+    // 多すぎる色は位置に比例して間引く（両端は必ず残る）
+    let colors: Vec<[u8; 3]> = (0..=100).map(|i| [i as u8, 0, 0]).collect();
+    let sampled = Gradient::sample(&colors, 11);
+    for i in 0..=10 {
+        assert_eq!(sampled.color(i as f32 / 10.0), [i * 10, 0, 0]);
+    }
+    assert_eq!(Gradient::sample(&colors, 1000), Gradient::sample(&colors, 101));
+
+    // 1 色なら単色、0 色なら黒
+    assert_eq!(Gradient::sample(&[[10, 20, 30]], 256).color(0.7), [10, 20, 30]);
+    assert_eq!(Gradient::sample(&[], 256).color(0.7), [0, 0, 0]);
+
+    // パレットとしても使える
+    let palette = Palette::Gradient(gray);
+    assert_eq!(palette.color_at(0.5), [128, 128, 128]);
+    assert_eq!(palette.color_at_exact(0.5), [127.5; 3]);
+    assert_eq!(palette.color(100, 100), [0, 0, 0]);
+}
+
+#[test]
+fn test_repeat_palette() {
+    // 2 回繰り返すと、前半と後半の同じ位置が同じ色になる
+    for base in [Palette::Classic, Palette::Hsv { cycles: 1.0 }, Palette::Gradient(Gradient::classic())] {
+        let palette = Palette::Repeat { palette: Box::new(base), times: 2 };
+        assert_eq!(palette.color_at(0.25), palette.color_at(0.75));
+        assert_eq!(palette.color_at_exact(0.25), palette.color_at_exact(0.75));
+        assert_eq!(palette.color(50, 200), palette.color(150, 200));
+        assert_eq!(palette.color(200, 200), [0, 0, 0]);
+    }
+
+    // 1 回なら今までと同じ
+    let once = Palette::Repeat { palette: Box::new(Palette::Classic), times: 1 };
+    for t in [0.0, 0.25, 0.5, 0.99, 1.0] {
+        assert_eq!(once.color_at(t), palette(t));
+    }
+    for iter in [0, 1, 100, 199] {
+        assert_eq!(once.color(iter, 200), color_map(iter, 200));
+    }
+}
+
+/// 上限のない浮動小数点の色を 0.0..1.0 に縮めるトーンマッピング（`--tonemap`）
+///
+/// 明るい部分が真っ白に張り付かず、明るさの差が少しずつ残るように縮める。
+/// パレットの色に `hdr_brightness` の明るさを掛けるときは `quantize` を使う。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// `x / (1 + x)`
+    Reinhard,
+    /// ACES の映画向けの曲線の近似（Narkowicz）
+    Aces,
+}
+
+impl ToneMap {
+    /// 0.0 以上の値 → 0.0..=1.0
+    pub fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMap::Reinhard => x / (1.0 + x),
+            ToneMap::Aces => ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0),
+        }
+    }
+
+    /// `apply` の逆（0.0..=1.0 → 0.0 以上。1.0 は `f32::MAX`）
+    pub fn invert(self, y: f32) -> f32 {
+        let y = y.clamp(0.0, 1.0);
+        let x = match self {
+            ToneMap::Reinhard => y / (1.0 - y),
+            // apply(x) = y を x の 2 次方程式 a x² + b x + c = 0 として解く（a < 0 なので 0 以上の解は 1 つ）
+            ToneMap::Aces => {
+                let (a, b, c) = (2.43 * y - 2.51, 0.59 * y - 0.03, 0.14 * y);
+                (-b - (b * b - 4.0 * a * c).sqrt()) / (2.0 * a)
+            }
+        };
+        x.clamp(0.0, f32::MAX)
+    }
+
+    /// 0.0..=1.0 の色 `color` を `brightness` 倍（1 以上）明るくして、u8 に丸める（1.0 が 255）
+    ///
+    /// 色を `invert` で上限のない値に戻してから明るくし、`apply` で縮める。明るさが 1 ならパレットの色のまま、
+    /// 明るくするほど白に張り付かずに少しずつ白に近づく。
+    pub fn quantize(self, color: [f32; 3], brightness: f32) -> [u8; 3] {
+        // 大きすぎる値は ACES の式があふれるので抑える（どちらの曲線でも 1.0 とみなせる）
+        color.map(|channel| (self.apply((self.invert(channel) * brightness).min(1e6)) * 255.0).round() as u8)
+    }
+}
+
+/// smooth・potential の色に掛ける明るさ（`--tonemap`。1 以上で、集合に近いほど明るい）
+///
+/// 反復回数 `count`（滑らかな反復回数や `-log2(potential)`）に比例して `1 + exposure × count / max_iter` 倍になる。
+/// 上限はなく、8 ビットの 255 を超える明るさになる。集合の内部（`None`）は 1。
+pub fn hdr_brightness(count: Option<f64>, max_iter: u32, exposure: f32) -> f32 {
+    match count {
+        None => 1.0,
+        Some(count) => (1.0 + exposure as f64 * count.max(0.0) / max_iter as f64) as f32,
+    }
+}
+
+#[test]
+fn test_tone_map() {
+    let inputs: Vec<f32> = (0..=2000).map(|i| i as f32 * 0.05).collect();
+    for tone_map in [ToneMap::Reinhard, ToneMap::Aces] {
+        assert_eq!(tone_map.apply(0.0), 0.0);
+        let outputs: Vec<f32> = inputs.iter().map(|&x| tone_map.apply(x)).collect();
+        assert!(outputs.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", tone_map);
+        assert!(outputs.iter().all(|&y| (0.0..=1.0).contains(&y)), "{:?}", tone_map);
+        assert_eq!(tone_map.quantize([0.0; 3], 1.0), [0, 0, 0]);
+        assert_eq!(tone_map.quantize([0.0; 3], 100.0), [0, 0, 0]);
+        assert_eq!(tone_map.invert(0.0), 0.0);
+        assert_eq!(tone_map.apply(tone_map.invert(1.0)), 1.0);
+
+        // 範囲内の色は、明るさ 1 ならそのまま、明るくしても暗くはならない
+        for value in 0..=255u8 {
+            let color = [value as f32 / 255.0; 3];
+            assert_eq!(tone_map.quantize(color, 1.0), [value; 3], "{:?}", tone_map);
+            for brightness in [1.5, 4.0, 100.0, 1e30] {
+                assert!(tone_map.quantize(color, brightness)[0] >= value, "{:?} {}", tone_map, brightness);
+            }
+        }
+    }
+
+    // Reinhard は狭義単調増加で、大きな値でも 1 には届かずに近づいていく
+    let reinhard: Vec<f32> = inputs.iter().map(|&x| ToneMap::Reinhard.apply(x)).collect();
+    assert!(reinhard.windows(2).all(|pair| pair[0] < pair[1]));
+    for x in [10.0, 1e3, 1e6] {
+        assert!(ToneMap::Reinhard.apply(x) < 1.0);
+    }
+    assert!(ToneMap::Reinhard.apply(1e3) > 0.99);
+    assert_eq!(ToneMap::Reinhard.apply(1.0), 0.5);
+    // 明るい値どうしも同じ色に張り付かない
+    let gray = [0.5; 3];
+    assert!(ToneMap::Reinhard.quantize(gray, 2.0) != ToneMap::Reinhard.quantize(gray, 4.0));
+    assert_eq!(ToneMap::Reinhard.quantize([1.0; 3], 1e30), [255; 3]);
+}
+
+#[test]
+fn test_hdr_brightness() {
+    assert_eq!(hdr_brightness(None, 100, 4.0), 1.0);
+    assert_eq!(hdr_brightness(Some(0.0), 100, 4.0), 1.0);
+    assert_eq!(hdr_brightness(Some(50.0), 100, 4.0), 3.0);
+    // 露出を上げると 1 を大きく超える（8 ビットでは白に張り付く明るさ）
+    assert!(hdr_brightness(Some(90.0), 100, 100.0) > 50.0);
+
+    // smooth の色をトーンマッピングしても、そのまま 8 ビットにした色より暗くはならない
+    let palette = Palette::Classic;
+    let (start, end) = (Complex::new(-0.75, 0.2), Complex::new(-0.75, 1.5));
+    for k in 0..=200 {
+        let smooth = escape_time_smooth(start + (end - start) * (k as f64 / 200.0), 500);
+        let plain = smooth_color(smooth, 500, &palette);
+        let exact = smooth_color_exact(smooth, 500, &palette).map(|channel| channel / 255.0);
+        for tone_map in [ToneMap::Reinhard, ToneMap::Aces] {
+            let mapped = tone_map.quantize(exact, hdr_brightness(smooth, 500, 4.0));
+            assert!((0..3).all(|c| mapped[c] >= plain[c]), "{:?} {:?} {:?}", tone_map, mapped, plain);
+        }
+    }
+}
+
+/// 4×4 のベイヤー行列（しきい値の順番 0..16）
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// 丸める前の RGB（0.0..=255.0）を、ピクセル `pixel` の位置で決まるしきい値で u8 に丸める（組織的ディザ）
+///
+/// 値に -0.5..0.5 のずれを足してから四捨五入するので、結果は丸めた値の ±1 に収まり、
+/// 4×4 ピクセルで平均すると元の値に戻る。ずれは位置だけで決まるので、何度描いても同じ結果になる。
+/// ゆるやかなグラデーションに出る 8 ビットの段差（バンディング）を、ぼかさずに目立たなくする。
+pub fn dither(color: [f32; 3], pixel: (usize, usize)) -> [u8; 3] {
+    let offset = (BAYER_4X4[pixel.1 % 4][pixel.0 % 4] as f32 + 0.5) / 16.0 - 0.5;
+    color.map(|channel| (channel + offset).round().clamp(0.0, 255.0) as u8)
+}
+
+#[test]
+fn test_dither() {
+    // 一様な色の 4×4 ピクセルは、丸めた値の ±1 に収まり、平均すると元の値に戻る
+    for value in [0.0, 0.3, 100.25, 127.5, 200.75, 254.9, 255.0] {
+        let undithered = [value; 3].map(|channel: f32| channel.round() as u8);
+        let mut sum = [0.0; 3];
+        for y in 0..4 {
+            for x in 0..4 {
+                let color = dither([value; 3], (x, y));
+                for k in 0..3 {
+                    assert!(color[k].abs_diff(undithered[k]) <= 1, "{} ({}, {}): {:?}", value, x, y, color);
+                    sum[k] += color[k] as f32;
+                }
+            }
+        }
+        for total in sum {
+            assert!((total / 16.0 - value).abs() <= 1.0 / 16.0, "{}: {}", value, total / 16.0);
+            assert_eq!((total / 16.0).round() as u8, undithered[0], "{}", value);
+        }
+    }
+
+    // 整数の値は変わらない
+    assert_eq!(dither([0.0, 128.0, 255.0], (1, 2)), [0, 128, 255]);
+    // 位置だけで決まる
+    assert_eq!(dither([100.4; 3], (5, 6)), dither([100.4; 3], (1, 2)));
+    // 小数の値では隣り合うピクセルの一部が違う値になる（帯が崩れる）
+    let row: Vec<_> = (0..4).map(|x| dither([100.4; 3], (x, 0))).collect();
+    assert!(row.iter().any(|color| *color != row[0]));
+
+    // ディザをかけない色も同じく四捨五入する（3.92, 45.76, 181.51）
+    assert_eq!(palette(0.125), [4, 46, 182]);
+    assert_eq!(Palette::Classic.color_at(0.125), palette_exact(0.125).map(|channel| channel.round() as u8));
+}
+
+/// `ColorTable` の大きさの上限
+pub const COLOR_TABLE_SIZE: usize = 4096;
+
+/// 反復回数 → RGB の表（`Palette::color` を前もって計算しておいたもの）
+///
+/// 1 回の描画の前に 1 度だけ作れば、ピクセルごとにパレットを計算せずに済む。
+/// 反復回数 / `max_iter` を `COLOR_TABLE_SIZE` 段階に丸めて引くので、`max_iter` が
+/// `COLOR_TABLE_SIZE` 以下なら `Palette::color` と完全に一致する。パレットか `max_iter` が
+/// 変わったら作り直すこと。
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorTable {
+    colors: Vec<[u8; 3]>,
+    max_iter: u32,
+}
+
+impl ColorTable {
+    pub fn new(palette: &Palette, max_iter: u32) -> ColorTable {
+        let len = (max_iter as usize).min(COLOR_TABLE_SIZE);
+        // k 番目の色は、その段階に丸められる反復回数のうち最小のものの色
+        let colors = (0..len as u64)
+            .map(|k| palette.color((k * max_iter as u64 / len as u64) as u32, max_iter))
+            .collect();
+        ColorTable { colors, max_iter }
+    }
+
+    /// 反復回数 → RGB（内部は黒）
+    pub fn color(&self, iter: u32) -> [u8; 3] {
+        if iter >= self.max_iter {
+            return [0, 0, 0];
+        }
+        self.colors[(iter as u64 * self.colors.len() as u64 / self.max_iter as u64) as usize]
+    }
+}
+
+#[test]
+fn test_color_table() {
+    // max_iter が表より小さければ完全に一致する
+    for palette in [Palette::Classic, Palette::Hsv { cycles: 3.0 }] {
+        let table = ColorTable::new(&palette, 200);
+        for iter in 0..=200 {
+            assert_eq!(table.color(iter), palette.color(iter, 200), "{:?} {}", palette, iter);
+        }
+    }
+
+    // 表より大きければ丸めた分だけずれるが、わずかな差に収まる
+    let max_iter = 100_000;
+    let table = ColorTable::new(&Palette::Classic, max_iter);
+    for iter in (0..max_iter).step_by(7) {
+        let (lut, direct) = (table.color(iter), color_map(iter, max_iter));
+        for (a, b) in lut.iter().zip(&direct) {
+            assert!(a.abs_diff(*b) <= 2, "{} {:?} {:?}", iter, lut, direct);
+        }
+    }
+    assert_eq!(table.color(max_iter), [0, 0, 0]);
+}
+
+/// 集合の内部（`max_iter` まで発散しなかった点）の色（`--interior`）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InteriorColor {
+    /// 従来どおり真っ黒
+    #[default]
+    Black,
+    /// 反復を終えたときの |z| で濃淡を付ける（|z| = 0 で黒、発散半径 2 で白）
+    FinalNorm,
+    /// 真っ白
+    White,
+}
+
+impl InteriorColor {
+    /// 反復を終えたときの z の `norm_sqr()` → RGB
+    pub fn color(self, final_norm_sqr: f64) -> [u8; 3] {
+        match self {
+            InteriorColor::Black => [0, 0, 0],
+            InteriorColor::FinalNorm => {
+                let t = (final_norm_sqr.sqrt() / 2.0).min(1.0);
+                [(t * 255.0).round() as u8; 3]
+            }
+            InteriorColor::White => [255, 255, 255],
+        }
+    }
+}
+
+#[test]
+fn test_interior_color() {
+    let bounds = (1, 1);
+    // c = -0.2+0.1i は内部の点で、軌道は 0 でない不動点に収束する
+    let region = Region { upper_left: Complex::new(-0.2, 0.1), lower_right: Complex::new(-0.1, 0.0) };
+    let render = |interior| {
+        let mut pixels = vec![0u8; 3];
+        render_color_parallel_counted::<f64>(&mut pixels, bounds, &region, 200, default_bailout_sqr(),
+                                             Fractal::Mandelbrot, &Palette::Classic, interior,
+                                             &AtomicUsize::new(0), &AtomicBool::new(false));
+        pixels
+    };
+
+    assert_eq!(render(InteriorColor::Black), [0, 0, 0]);
+    assert_ne!(render(InteriorColor::FinalNorm), [0, 0, 0]);
+    assert_eq!(render(InteriorColor::White), [255, 255, 255]);
+
+    assert_eq!(InteriorColor::FinalNorm.color(0.0), [0, 0, 0]);
+    assert_eq!(InteriorColor::FinalNorm.color(1.0), [128, 128, 128]);
+    assert_eq!(InteriorColor::FinalNorm.color(9.0), [255, 255, 255]);
+}
+
+/// 0.0..=1.0 の値 → RGB（有名な smooth coloring: 青→紫→赤→黄）
+pub fn palette(t: f32) -> [u8; 3] {
+    palette_exact(t).map(|channel| channel.round() as u8)
+}
+
+/// `palette` の丸める前の値（各チャンネル 0.0..=255.0）
+pub fn palette_exact(t: f32) -> [f32; 3] {
+    let r = 9.0 * (1.0 - t) * t * t * t * 255.0;
+    let g = 15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0;
+    let b = 8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0;
+
+    [r, g, b]
+}
+
+/// `Gradient::classic` の色の位置（`palette` の多項式を 1/8 刻みで取ったもの）
+const CLASSIC_STOPS: [(f32, [u8; 3]); 9] = [
+    (0.0, [0, 0, 0]),
+    (0.125, [4, 46, 182]),
+    (0.25, [27, 134, 229]),
+    (0.375, [76, 210, 198]),
+    (0.5, [143, 239, 135]),
+    (0.625, [210, 210, 71]),
+    (0.75, [242, 134, 25]),
+    (0.875, [192, 46, 4]),
+    (1.0, [0, 0, 0]),
+];
+
+/// 色の位置 (position, color) を並べたグラデーション
+///
+/// 隣り合う 2 つの位置の間は RGB を線形に補間する。範囲外の `t` は両端の色になる。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f32, [u8; 3])>,
+}
+
+impl Gradient {
+    /// 色の位置から作る（順番はばらばらでもよい）
+    pub fn new(mut stops: Vec<(f32, [u8; 3])>) -> Gradient {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Gradient { stops }
+    }
+
+    /// `palette` の多項式を 1/8 刻みの色の位置で近似したグラデーション（位置の間は線形補間なので少し違う）
+    pub fn classic() -> Gradient {
+        Gradient::new(CLASSIC_STOPS.to_vec())
+    }
+
+    /// 位置 `t` の色（位置が 1 つもなければ黒、1 つだけならその色）
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        interpolate(&self.stops, t)
+    }
+
+    /// `color` の丸める前の値（各チャンネル 0.0..=255.0）
+    pub fn color_exact(&self, t: f32) -> [f32; 3] {
+        interpolate_exact(&self.stops, t)
+    }
+
+    /// 左から右へ並んだ色 `colors` から、等間隔に最大 `max_stops` 個を選んで作る（`--palette-image`）
+    ///
+    /// 色が `max_stops` 個より多ければ、端の 2 色を含めて位置に比例した色を選ぶ。
+    /// 最初の色が位置 0.0、最後の色が 1.0 になる。
+    pub fn sample(colors: &[[u8; 3]], max_stops: usize) -> Gradient {
+        let count = colors.len().min(max_stops);
+        if count <= 1 {
+            return Gradient::new(colors.iter().take(count).map(|&color| (0.0, color)).collect());
+        }
+
+        let last = (count - 1) as f64;
+        let stops = (0..count)
+            .map(|i| {
+                let source = (i as f64 / last * (colors.len() - 1) as f64).round() as usize;
+                ((i as f64 / last) as f32, colors[source])
+            })
+            .collect();
+        Gradient::new(stops)
+    }
+}
+
+/// 位置の順に並んだ `stops` の、位置 `t` における色
+fn interpolate(stops: &[(f32, [u8; 3])], t: f32) -> [u8; 3] {
+    interpolate_exact(stops, t).map(|channel| channel.round() as u8)
+}
+
+/// `interpolate` の丸める前の値
+fn interpolate_exact(stops: &[(f32, [u8; 3])], t: f32) -> [f32; 3] {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return [0.0; 3],
+    };
+    if t <= first.0 || t.is_nan() {
+        return first.1.map(f32::from);
+    }
+    if t >= last.0 {
+        return last.1.map(f32::from);
+    }
+
+    // t を挟む 2 つの位置（first.0 < t < last.0 なので必ず見つかる）
+    let i = stops.iter().position(|&(position, _)| position > t).unwrap();
+    let ((p0, c0), (p1, c1)) = (stops[i - 1], stops[i]);
+    let s = (t - p0) / (p1 - p0);
+
+    std::array::from_fn(|k| c0[k] as f32 * (1.0 - s) + c1[k] as f32 * s)
+}
+
+#[test]
+fn test_gradient() {
+    // 白黒の 2 色なら中間は灰色
+    let gray = Gradient::new(vec![(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]);
+    assert_eq!(gray.color(0.5), [128, 128, 128]);
+    assert_eq!(gray.color(0.0), [0, 0, 0]);
+    assert_eq!(gray.color(1.0), [255, 255, 255]);
+
+    // 範囲外は両端の色
+    assert_eq!(gray.color(-0.5), [0, 0, 0]);
+    assert_eq!(gray.color(1.5), [255, 255, 255]);
+    assert_eq!(gray.color(f32::NAN), [0, 0, 0]);
+
+    // 3 色なら t を含む区間の中で補間する（位置は並べ替えられる）
+    let rgb = Gradient::new(vec![(1.0, [0, 0, 255]), (0.0, [255, 0, 0]), (0.5, [0, 255, 0])]);
+    assert_eq!(rgb.color(0.25), [128, 128, 0]);
+    assert_eq!(rgb.color(0.5), [0, 255, 0]);
+    assert_eq!(rgb.color(0.75), [0, 128, 128]);
+
+    // 位置が 0 個・1 個でもパニックしない
+    assert_eq!(Gradient::new(vec![]).color(0.5), [0, 0, 0]);
+    assert_eq!(Gradient::new(vec![(0.3, [10, 20, 30])]).color(0.9), [10, 20, 30]);
+}
+
+/// RGB バッファに 1 行ずつ順番に描画する（並列版との比較用）
+pub fn render_color(pixels: &mut [u8], bounds: (usize, usize), region: &Region, max_iter: u32) {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let colors = ColorTable::new(&Palette::Classic, max_iter);
+    let grid = region.grid(bounds);
+
+    for (y, row) in pixels.chunks_mut(bounds.0 * 3).enumerate() {
+        render_row(row, (0, y), &grid, max_iter, default_bailout_sqr(), Fractal::Mandelbrot,
+                   &colors, InteriorColor::Black);
+    }
+}
+
+/// RGB バッファを行ごとに分割し、rayon で並列に描画する
+///
+/// スレッド数は rayon のグローバルプールに従うので、`RAYON_NUM_THREADS` で指定できる。
+/// 各行は独立に計算されるため、結果は `render_color` とバイト単位で一致する。
+pub fn render_color_parallel(pixels: &mut [u8], bounds: (usize, usize), region: &Region, max_iter: u32) {
+    let colors = ColorTable::new(&Palette::Classic, max_iter);
+    render_with(pixels, bounds, region, |point| {
+        let iter = Fractal::Mandelbrot.escape_time(point, max_iter, default_bailout_sqr());
+        colors.color(iter)
+    });
+}
+
+/// `render_tile_banded_as` と同じだが、rayon の代わりに `threads` 本のスレッドが `AtomicChunksMut` で
+/// `band_rows` 行ずつの帯を取り合って描く
+///
+/// チャンクの長さを 1 行のバイト数 `3 * tile.size.0` の倍数にするので、帯はいつも行の先頭から始まり、
+/// 1 ピクセルの RGB が 2 つの帯にまたがることはない。結果は帯の高さやスレッド数によらず
+/// `render_tile_banded_as` とバイト単位で一致する。1 行終わるごとに `rows_done` を 1 増やす。
+///
+/// 各スレッドは帯を取る前に `cancel` を見て、立っていればそこで止まる（描きかけの帯は最後まで描く）。
+/// 止まった場合、まだ描いていない帯は元の `pixels` のまま残る。戻り値はすべての帯を描き終えたかどうか。
+#[allow(clippy::too_many_arguments)]
+pub fn render_color_bands<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region<T>,
+    tile: &Tile,
+    band_rows: usize,
+    threads: usize,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    assert!(tile.fits(bounds));
+    assert_eq!(pixels.len(), tile.size.0 * tile.size.1 * 3);
+    // 幅が 0 なら帯の長さも 0 になって分けられないので、描くものがないまま全部の行を終えたことにする
+    if pixels.is_empty() {
+        rows_done.fetch_add(tile.size.1, Ordering::Relaxed);
+        return true;
+    }
+    let _span = tracing::info_span!("render", width = tile.size.0, height = tile.size.1, max_iter).entered();
+    let grid = region.grid(bounds);
+    let colors = ColorTable::new(palette, max_iter);
+    let row_len = tile.size.0 * 3;
+    let band_len = band_rows.max(1) * row_len;
+    let band_count = pixels.len().div_ceil(band_len);
+    let rendered = AtomicUsize::new(0);
+    let bands = AtomicChunksMut::new(pixels, band_len);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                // `cancel` を先に見るので、止まるときに帯を取ってから捨てることはない
+                while !cancel.load(Ordering::SeqCst) {
+                    let Some((i, band)) = (&bands).next() else {
+                        break;
+                    };
+                    // チャンクの先頭のバイト位置から行に戻す（最後の帯は短いことがある）
+                    let top = i * band_len / row_len;
+                    for (k, row) in band.chunks_mut(row_len).enumerate() {
+                        let start = (tile.offset.0, tile.offset.1 + top + k);
+                        render_row(row, start, &grid, max_iter, bailout_sqr, fractal, &colors, interior);
+                        rows_done.fetch_add(1, Ordering::Relaxed);
+                    }
+                    rendered.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+    });
+
+    rendered.into_inner() == band_count
+}
+
+#[test]
+fn test_render_color_bands_matches_serial() {
+    let bounds = (203, 150);
+    let mut serial = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color(&mut serial, bounds, &Region::CLASSIC, 200);
+
+    let render = |bounds, tile: &Tile, band_rows, fractal, palette: &Palette| {
+        let mut pixels = vec![0u8; tile.size.0 * tile.size.1 * 3];
+        let rows_done = AtomicUsize::new(0);
+        assert!(render_color_bands(&mut pixels, bounds, &Region::CLASSIC, tile, band_rows, 4, 200,
+                                   default_bailout_sqr(), fractal, palette, InteriorColor::Black, &rows_done,
+                                   &AtomicBool::new(false)));
+        assert_eq!(rows_done.into_inner(), tile.size.1);
+        pixels
+    };
+
+    // 帯の高さが画像の高さを割り切らなくても（最後の帯が短くても）同じになる
+    for band_rows in [0, 1, 3, 7, 150, 200] {
+        let chunked = render(bounds, &Tile::full(bounds), band_rows, Fractal::Mandelbrot, &Palette::Classic);
+        assert!(chunked == serial, "band_rows = {}", band_rows);
+    }
+
+    // ほかのフラクタル・パレットやタイルでも、rayon で描いたものと同じになる
+    let tile = Tile { size: (150, 97), offset: (20, 31) };
+    for (fractal, palette) in [(Fractal::Multibrot(3), Palette::Hsv { cycles: 3.0 }),
+                               (Fractal::Julia(Complex::new(-0.8, 0.156)), Palette::Hsv { cycles: 1.0 })] {
+        let mut expected = vec![0u8; tile.size.0 * tile.size.1 * 3];
+        render_tile_banded_as(&mut expected, bounds, &Region::CLASSIC, &tile, 1, 200, default_bailout_sqr(),
+                              fractal, &palette, InteriorColor::Black, &AtomicUsize::new(0), &AtomicBool::new(false));
+        assert!(render(bounds, &tile, 7, fractal, &palette) == expected, "{:?}", fractal);
+    }
+
+    // 幅や高さが 0 でもパニックしない
+    for bounds in [(0, 10), (10, 0)] {
+        assert!(render(bounds, &Tile::full(bounds), 3, Fractal::Mandelbrot, &Palette::Classic).is_empty());
+    }
+}
+
+#[test]
+fn test_render_color_bands_cancelled() {
+    let bounds = (120, 80);
+
+    // 始める前に止められていれば、帯を 1 本も描かずにすぐ戻る
+    let mut pixels = vec![7u8; bounds.0 * bounds.1 * 3];
+    assert!(!render_color_bands(&mut pixels, bounds, &Region::CLASSIC, &Tile::full(bounds), 3, 4, 200,
+                                default_bailout_sqr(), Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black,
+                                &AtomicUsize::new(0), &AtomicBool::new(true)));
+    assert!(pixels.iter().all(|&b| b == 7));
+}
+
+/// `render_color_parallel` と同じ絵を `img` に直接描く（大きさは `img.dimensions()`）
+///
+/// 中間の `Vec<u8>` を作らずに `put_pixel` で 1 ピクセルずつ書く。結果は `render_color_parallel` と一致する。
+pub fn render_into(img: &mut image::RgbImage, region: &Region, max_iter: u32) {
+    let (width, height) = img.dimensions();
+    let colors = ColorTable::new(&Palette::Classic, max_iter);
+    let grid = region.grid((width as usize, height as usize));
+
+    for y in 0..height {
+        for x in 0..width {
+            let point = grid.point((x as usize, y as usize));
+            let iter = Fractal::Mandelbrot.escape_time(point, max_iter, default_bailout_sqr());
+            img.put_pixel(x, y, image::Rgb(colors.color(iter)));
+        }
+    }
+}
+
+#[test]
+fn test_render_into() {
+    let bounds = (120, 80);
+    let mut img = image::RgbImage::new(bounds.0 as u32, bounds.1 as u32);
+    render_into(&mut img, &Region::CLASSIC, 200);
+
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color_parallel(&mut pixels, bounds, &Region::CLASSIC, 200);
+    assert!(img.into_raw() == pixels);
+}
+
+/// `render_color_parallel` と同じだが、型 `T` で計算し、1 行終わるごとに `rows_done` を 1 増やす
+///
+/// 発散半径の 2 乗は `bailout_sqr` で指定し、集合の内部は `interior` で色を付ける。
+/// `cancel` が立ったら新しい行を描き始めない（`render_tile_banded_as`）。戻り値はすべての行を描き終えたかどうか。
+#[allow(clippy::too_many_arguments)]
+pub fn render_color_parallel_counted<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    render_tile_counted::<T>(pixels, bounds, region, &Tile::full(bounds), max_iter, bailout_sqr, fractal,
+                             palette, interior, rows_done, cancel)
+}
+
+/// `render_color_parallel_counted` と同じだが、範囲とフラクタルが実軸に対して対称なら上半分だけ計算する
+///
+/// 行 y（y > 高さ / 2）は行 高さ - y の鏡像なので、計算せずに写す。対称なときは `PixelGrid` が
+/// 下半分の点を上半分の点の複素共役にしているので、全体を計算したものとバイト単位で一致する。
+/// 対称でなければ全体を計算する。`cancel` で止められたら下半分は写さずに `false` を返す。
+#[allow(clippy::too_many_arguments)]
+pub fn render_symmetric_counted<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    let region = region.cast::<T>();
+    if !(region.is_symmetric() && fractal.is_symmetric()) {
+        return render_tile_counted_as(pixels, bounds, &region, &Tile::full(bounds), max_iter, bailout_sqr,
+                                      fractal, palette, interior, rows_done, cancel);
+    }
+
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let half = Tile { size: (bounds.0, bounds.1 / 2 + 1), offset: (0, 0) };
+    let row_len = bounds.0 * 3;
+    if !render_tile_counted_as(&mut pixels[..half.size.1 * row_len], bounds, &region, &half, max_iter,
+                               bailout_sqr, fractal, palette, interior, rows_done, cancel) {
+        return false;
+    }
+    for y in half.size.1..bounds.1 {
+        let source = (bounds.1 - y) * row_len;
+        pixels.copy_within(source..source + row_len, y * row_len);
+    }
+    rows_done.fetch_add(bounds.1 - half.size.1, Ordering::Relaxed);
+    true
+}
+
+#[test]
+fn test_render_symmetric() {
+    let render = |bounds: (usize, usize), region: &Region, fractal, symmetric: bool| {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        let rows_done = AtomicUsize::new(0);
+        if symmetric {
+            render_symmetric_counted::<f64>(&mut pixels, bounds, region, 200, default_bailout_sqr(), fractal,
+                                            &Palette::Classic, InteriorColor::FinalNorm, &rows_done,
+                                            &AtomicBool::new(false));
+        } else {
+            render_color_parallel_counted::<f64>(&mut pixels, bounds, region, 200, default_bailout_sqr(), fractal,
+                                                 &Palette::Classic, InteriorColor::FinalNorm, &rows_done,
+                                                 &AtomicBool::new(false));
+        }
+        assert_eq!(rows_done.into_inner(), bounds.1);
+        pixels
+    };
+
+    // 対称な範囲（高さが偶数でも奇数でも）では全体を計算したものとバイト単位で一致し、下半分は上半分の鏡像
+    for bounds in [(120, 80), (91, 61), (5, 1)] {
+        for fractal in [Fractal::Mandelbrot, Fractal::Multibrot(3), Fractal::Julia(Complex::new(-0.8, 0.0))] {
+            let (full, mirrored) = (render(bounds, &Region::CLASSIC, fractal, false),
+                                    render(bounds, &Region::CLASSIC, fractal, true));
+            assert!(full == mirrored, "{:?} {:?}", bounds, fractal);
+            let row = |y: usize| &mirrored[y * bounds.0 * 3..(y + 1) * bounds.0 * 3];
+            for y in 1..bounds.1 {
+                assert_eq!(row(y), row(bounds.1 - y), "{:?} {:?} row {}", bounds, fractal, y);
+            }
+        }
+    }
+
+    // 対称でない範囲・フラクタルでは全体を計算する
+    let shifted = Region::from_corners(Complex::new(-2.2, 1.3), Complex::new(1.0, -1.1)).unwrap();
+    assert!(render((60, 40), &shifted, Fractal::Mandelbrot, false)
+        == render((60, 40), &shifted, Fractal::Mandelbrot, true));
+    for fractal in [Fractal::BurningShip, Fractal::Julia(Complex::new(-0.8, 0.156))] {
+        assert!(render((60, 40), &Region::CLASSIC, fractal, false)
+            == render((60, 40), &Region::CLASSIC, fractal, true), "{:?}", fractal);
+    }
+}
+
+/// 大きな画像の一部（左上が `offset`、大きさが `size` ピクセルの長方形）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub size: (usize, usize),
+    pub offset: (usize, usize),
+}
+
+impl Tile {
+    /// 大きさ `bounds` の画像全体
+    pub fn full(bounds: (usize, usize)) -> Tile {
+        Tile { size: bounds, offset: (0, 0) }
+    }
+
+    /// N×N のスーパーサンプリングで、サンプル単位に拡大したタイル
+    pub fn scaled(&self, n: usize) -> Tile {
+        Tile {
+            size: (self.size.0 * n, self.size.1 * n),
+            offset: (self.offset.0 * n, self.offset.1 * n),
+        }
+    }
+
+    /// 大きさ `bounds` の画像からはみ出していないか
+    pub fn fits(&self, bounds: (usize, usize)) -> bool {
+        self.offset.0 + self.size.0 <= bounds.0 && self.offset.1 + self.size.1 <= bounds.1
+    }
+
+    /// 画像全体が `region` のとき、このタイルが受け持つ範囲
+    pub fn region(&self, bounds: (usize, usize), region: &Region) -> Region {
+        let end = (self.offset.0 + self.size.0, self.offset.1 + self.size.1);
+        Region {
+            upper_left: region.pixel_to_point(bounds, self.offset),
+            lower_right: region.pixel_to_point(bounds, end),
+        }
+    }
+}
+
+#[test]
+fn test_region_split() {
+    let bounds = (103, 61);
+    let region = Region::from_center_zoom(bounds, Complex::new(-0.75, 0.1), 3.0);
+    let tiles = region.split(bounds, 4, 5);
+    assert_eq!(tiles.len(), 20);
+
+    // 幅 103 = 21+21+21+20+20、高さ 61 = 16+15+15+15
+    let widths: Vec<usize> = tiles[..5].iter().map(|(tile, _)| tile.size.0).collect();
+    let heights: Vec<usize> = tiles.iter().step_by(5).map(|(tile, _)| tile.size.1).collect();
+    assert_eq!(widths, [21, 21, 21, 20, 20]);
+    assert_eq!(heights, [16, 15, 15, 15]);
+
+    // どのピクセルもちょうど 1 枚のタイルに入り、タイルの範囲での点は画像全体での点と同じ
+    let mut covered = vec![0; bounds.0 * bounds.1];
+    for (tile, tile_region) in &tiles {
+        assert!(tile.fits(bounds));
+        assert_eq!(*tile_region, tile.region(bounds, &region));
+        for y in 0..tile.size.1 {
+            for x in 0..tile.size.0 {
+                let pixel = (tile.offset.0 + x, tile.offset.1 + y);
+                covered[pixel.1 * bounds.0 + pixel.0] += 1;
+                let expected = region.pixel_to_point(bounds, pixel);
+                let actual = tile_region.pixel_to_point(tile.size, (x, y));
+                assert!((actual - expected).norm() < region.width() * 1e-12, "{:?}", pixel);
+            }
+        }
+    }
+    assert!(covered.iter().all(|&count| count == 1));
+
+    // 分けすぎたときは 1 ピクセルずつ、0 は分けない
+    let tiny = region.split((3, 2), 10, 10);
+    assert_eq!(tiny.len(), 6);
+    assert!(tiny.iter().all(|(tile, _)| tile.size == (1, 1)));
+    let whole = region.split(bounds, 0, 0);
+    assert_eq!(whole.len(), 1);
+    assert_eq!(whole[0].0, Tile::full(bounds));
+}
+
+/// 大きさ `bounds` の画像のうち `tile` の部分だけを、`tile.size` の RGB バッファに並列に描画する
+///
+/// 点の位置は画像全体の `bounds`・`region` で決めるので、別々に描いたタイルを並べると
+/// 画像全体を 1 度に描いたものとバイト単位で一致する。1 行終わるごとに `rows_done` を 1 増やす。
+/// `cancel` の扱いと戻り値は `render_tile_banded_as` と同じ。
+#[allow(clippy::too_many_arguments)]
+pub fn render_tile_counted<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    tile: &Tile,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    render_tile_counted_as(pixels, bounds, &region.cast::<T>(), tile, max_iter, bailout_sqr, fractal, palette,
+                           interior, rows_done, cancel)
+}
+
+/// `render_tile_counted` と同じだが、範囲を型 `T` の座標で渡す（`Region::from_center_zoom_as`）
+#[allow(clippy::too_many_arguments)]
+pub fn render_tile_counted_as<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region<T>,
+    tile: &Tile,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    render_tile_banded_as(pixels, bounds, region, tile, 1, max_iter, bailout_sqr, fractal, palette, interior,
+                          rows_done, cancel)
+}
+
+/// `render_tile_counted_as` と同じだが、`band_rows` 行ずつの帯を 1 つの仕事として並列に描く
+///
+/// 帯の高さは負荷の偏りやキャッシュの効き方を変えるだけで、結果は帯の高さによらずバイト単位で同じ。
+/// 速い高さは `max_iter` やコア数で変わるので、`tune_band_rows` で測って選ぶ。
+///
+/// 帯を描き始める前に `cancel` を見て、立っていればその帯を飛ばす（描きかけの帯は最後まで描く）。
+/// 飛ばした帯は元の `pixels` のまま残り、`rows_done` にも数えない。戻り値はすべての帯を描き終えたかどうか。
+#[allow(clippy::too_many_arguments)]
+pub fn render_tile_banded_as<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region<T>,
+    tile: &Tile,
+    band_rows: usize,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> bool {
+    assert!(tile.fits(bounds));
+    assert_eq!(pixels.len(), tile.size.0 * tile.size.1 * 3);
+    // 反復回数を求めながら色を付けるので、計算と色付けは 1 つの段階になる
+    let _span = tracing::info_span!("render", width = tile.size.0, height = tile.size.1, max_iter).entered();
+    let grid = region.grid(bounds);
+    let colors = ColorTable::new(palette, max_iter);
+    let row_len = tile.size.0 * 3;
+    let completed = AtomicBool::new(true);
+
+    pixels
+        .par_chunks_mut((row_len * band_rows.max(1)).max(1))
+        .enumerate()
+        .for_each(|(band, rows)| {
+            if cancel.load(Ordering::SeqCst) {
+                completed.store(false, Ordering::Relaxed);
+                return;
+            }
+            for (i, row) in rows.chunks_mut(row_len.max(1)).enumerate() {
+                let start = (tile.offset.0, tile.offset.1 + band * band_rows.max(1) + i);
+                render_row(row, start, &grid, max_iter, bailout_sqr, fractal, &colors, interior);
+                rows_done.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    completed.into_inner()
+}
+
+/// `tune_band_rows` で試す帯の高さ
+pub const BAND_ROWS_CANDIDATES: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+/// `tune_band_rows` で試しに描く行数（画像の真ん中の帯）
+pub const TUNE_ROWS: usize = 64;
+
+/// 画像の真ん中の `TUNE_ROWS` 行を `BAND_ROWS_CANDIDATES` の高さで 1 回ずつ描き、いちばん速かった高さを返す
+///
+/// 試しに描く行数より高い候補は試さないので、返す値は必ず 1..=`bounds.1`（画像が空なら 1）。
+pub fn tune_band_rows(
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+) -> usize {
+    let _span = tracing::info_span!("tune_band_rows", width = bounds.0, height = bounds.1).entered();
+    let rows = bounds.1.min(TUNE_ROWS);
+    let trial = Tile { size: (bounds.0, rows), offset: (0, (bounds.1 - rows) / 2) };
+    let mut pixels = vec![0u8; trial.size.0 * trial.size.1 * 3];
+
+    BAND_ROWS_CANDIDATES
+        .iter()
+        .filter(|&&band_rows| band_rows == 1 || band_rows <= rows)
+        .map(|&band_rows| {
+            let start = Instant::now();
+            render_tile_banded_as(&mut pixels, bounds, region, &trial, band_rows, max_iter, bailout_sqr,
+                                  fractal, &Palette::Classic, InteriorColor::Black,
+                                  &AtomicUsize::new(0), &AtomicBool::new(false));
+            (start.elapsed(), band_rows)
+        })
+        .min()
+        .map_or(1, |(_, band_rows)| band_rows)
+}
+
+#[test]
+fn test_render_tile_banded() {
+    let bounds = (40, 27);
+    let region = Region::CLASSIC;
+    let tile = Tile { size: (30, 19), offset: (5, 4) };
+    let render = |band_rows| {
+        let mut pixels = vec![0u8; tile.size.0 * tile.size.1 * 3];
+        let rows_done = AtomicUsize::new(0);
+        render_tile_banded_as(&mut pixels, bounds, &region, &tile, band_rows, 100, default_bailout_sqr(),
+                              Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black,
+                              &rows_done, &AtomicBool::new(false));
+        assert_eq!(rows_done.into_inner(), tile.size.1);
+        pixels
+    };
+
+    // 帯の高さによらず、1 行ずつ描いたものと同じ（割り切れない高さ・タイルより高い帯も）
+    let rows = render(1);
+    for band_rows in [0, 2, 5, 8, 19, 100] {
+        assert!(render(band_rows) == rows, "band_rows {}", band_rows);
+    }
+
+    for bounds in [(40, 27), (16, 1), (8, 200)] {
+        let band_rows = tune_band_rows(bounds, &region, 50, default_bailout_sqr(), Fractal::Mandelbrot);
+        assert!((1..=bounds.1).contains(&band_rows), "{:?}: {}", bounds, band_rows);
+        assert!(BAND_ROWS_CANDIDATES.contains(&band_rows));
+    }
+    assert_eq!(tune_band_rows((0, 0), &region, 50, default_bailout_sqr(), Fractal::Mandelbrot), 1);
+}
+
+#[test]
+fn test_render_cancelled() {
+    let bounds = (40, 27);
+    let region = Region::CLASSIC;
+    let cancel = AtomicBool::new(true);
+    let rows_done = AtomicUsize::new(0);
+
+    // 始める前に止められていれば、rayon で描く入口もどの帯も描かずに戻る
+    let mut pixels = vec![7u8; bounds.0 * bounds.1 * 3];
+    assert!(!render_tile_banded_as(&mut pixels, bounds, &region, &Tile::full(bounds), 4, 200, default_bailout_sqr(),
+                                   Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black, &rows_done,
+                                   &cancel));
+    assert!(!render_color_parallel_counted::<f64>(&mut pixels, bounds, &region, 200, default_bailout_sqr(),
+                                                  Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black,
+                                                  &rows_done, &cancel));
+    assert!(!render_symmetric_counted::<f64>(&mut pixels, bounds, &region, 200, default_bailout_sqr(),
+                                             Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black,
+                                             &rows_done, &cancel));
+    assert!(pixels.iter().all(|&b| b == 7));
+    assert_eq!(rows_done.into_inner(), 0);
+
+    // 締め切りつきの描画は、締め切りを過ぎたときと同じく灰色で埋める
+    let rendered = render_budgeted_counted::<f64>(&mut pixels, bounds, &region, 200, default_bailout_sqr(),
+                                                  Fractal::Mandelbrot, &Palette::Classic, InteriorColor::Black,
+                                                  Instant::now() + Duration::from_secs(3600),
+                                                  &AtomicUsize::new(0), &cancel);
+    assert_eq!(rendered, 0);
+    assert!(pixels.chunks(3).all(|rgb| rgb == PLACEHOLDER_COLOR));
+}
+
+/// `render_budgeted_counted` で 1 度に描く行数（締め切りはこの帯ごとに確かめる）
+pub const BUDGET_BAND_ROWS: usize = 8;
+
+/// `render_budgeted_counted` が締め切りまでに描けなかった帯の色（中間の灰色）
+pub const PLACEHOLDER_COLOR: [u8; 3] = [128, 128, 128];
+
+/// `render_color_parallel_counted` と同じだが、`deadline` を過ぎたら新しい帯を描き始めない（`--max-time`）
+///
+/// 画像を `BUDGET_BAND_ROWS` 行ずつの帯に分けて並列に描き、描き始める前に締め切りを過ぎていた帯は
+/// `PLACEHOLDER_COLOR` で塗りつぶす。描きかけの帯は最後まで描くので、バッファはいつも画像として完全になる。
+/// `cancel` が立ったあとに描き始める帯も、締め切りを過ぎたときと同じく塗りつぶす。
+/// 描いた行数を返す（塗りつぶした行も `rows_done` には数える）。
+#[allow(clippy::too_many_arguments)]
+pub fn render_budgeted_counted<T: Float + Send + Sync>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: &Palette,
+    interior: InteriorColor,
+    deadline: Instant,
+    rows_done: &AtomicUsize,
+    cancel: &AtomicBool,
+) -> usize {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let _span = tracing::info_span!("render", width = bounds.0, height = bounds.1, max_iter).entered();
+    let grid = region.cast::<T>().grid(bounds);
+    let colors = ColorTable::new(palette, max_iter);
+    let row_len = bounds.0 * 3;
+    let rendered = AtomicUsize::new(0);
+
+    pixels
+        .par_chunks_mut((row_len * BUDGET_BAND_ROWS).max(1))
+        .enumerate()
+        .for_each(|(band, rows)| {
+            let rows_in_band = rows.len() / row_len.max(1);
+            if Instant::now() >= deadline || cancel.load(Ordering::SeqCst) {
+                for rgb in rows.chunks_mut(3) {
+                    rgb.copy_from_slice(&PLACEHOLDER_COLOR);
+                }
+            } else {
+                for (i, row) in rows.chunks_mut(row_len.max(1)).enumerate() {
+                    render_row(row, (0, band * BUDGET_BAND_ROWS + i), &grid, max_iter, bailout_sqr, fractal,
+                               &colors, interior);
+                }
+                rendered.fetch_add(rows_in_band, Ordering::Relaxed);
+            }
+            rows_done.fetch_add(rows_in_band, Ordering::Relaxed);
+        });
+    rendered.into_inner()
+}
+
+#[test]
+fn test_render_budgeted() {
+    let bounds = (50, 37);
+    let render = |deadline: Instant| {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        let rows_done = AtomicUsize::new(0);
+        let rendered = render_budgeted_counted::<f64>(&mut pixels, bounds, &Region::CLASSIC, 200,
+                                                      default_bailout_sqr(), Fractal::Mandelbrot,
+                                                      &Palette::Classic, InteriorColor::Black,
+                                                      deadline, &rows_done, &AtomicBool::new(false));
+        assert_eq!(rows_done.into_inner(), bounds.1);
+        (pixels, rendered)
+    };
+
+    // 締め切りに余裕があれば、全体を描いたものと一致する
+    let (pixels, rendered) = render(Instant::now() + Duration::from_secs(3600));
+    assert_eq!(rendered, bounds.1);
+    let mut expected = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color_parallel(&mut expected, bounds, &Region::CLASSIC, 200);
+    assert!(pixels == expected);
+
+    // 締め切りを過ぎていれば、どの帯も描かずに灰色で埋める
+    let (pixels, rendered) = render(Instant::now());
+    assert_eq!(rendered, 0);
+    assert!(pixels.chunks(3).all(|rgb| rgb == PLACEHOLDER_COLOR));
+}
+
+#[test]
+#[cfg(not(target_arch = "wasm32"))]
+fn test_write_budgeted_image() {
+    // 締め切りが短すぎて描けなかった帯も灰色で埋まるので、画像として書き出せる
+    let bounds = (64, 40);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    let deadline = Instant::now() + Duration::from_nanos(1);
+    let rendered = render_budgeted_counted::<f64>(&mut pixels, bounds, &Region::CLASSIC, 100_000,
+                                                  default_bailout_sqr(), Fractal::Mandelbrot,
+                                                  &Palette::Classic, InteriorColor::Black, deadline,
+                                                  &AtomicUsize::new(0), &AtomicBool::new(false));
+    assert!(rendered < bounds.1);
+    assert_eq!(rendered % BUDGET_BAND_ROWS, 0);
+
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_budget_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+    write_image(filename, &pixels, bounds).unwrap();
+    let read = image::open(filename).unwrap().to_rgb8();
+    std::fs::remove_file(filename).unwrap();
+    assert_eq!(read.as_raw(), &pixels);
+
+    // 描けなかった行は帯ごと灰色
+    let unrendered = read.rows().filter(|row| row.clone().all(|pixel| pixel.0 == PLACEHOLDER_COLOR)).count();
+    assert!(unrendered >= bounds.1 - rendered);
+    assert!(unrendered > 0);
+}
+
+/// フラクタルの代わりに描くテストパターン（`--pattern`）
+///
+/// ピクセルと点の対応（縦横比やタイルの位置）の誤りを目で見つけるためのもの。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// `CHECKER_SIZE` ピクセル四方の白黒の市松模様（左上のブロックが白）
+    Checker,
+    /// 点の実部を赤、虚部を緑に入れる（表示範囲の左端・上端が 0、右端・下端が 255 近く）
+    Coords,
+}
+
+/// `Pattern::Checker` のブロックの一辺（ピクセル）
+pub const CHECKER_SIZE: usize = 8;
+
+impl Pattern {
+    /// 大きさ `bounds`・範囲 `region` の画像のピクセル `pixel` の色
+    pub fn color(self, bounds: (usize, usize), region: &Region, pixel: (usize, usize)) -> [u8; 3] {
+        match self {
+            Pattern::Checker => {
+                let (block_x, block_y) = (pixel.0 / CHECKER_SIZE, pixel.1 / CHECKER_SIZE);
+                if (block_x + block_y) % 2 == 0 { [255, 255, 255] } else { [0, 0, 0] }
+            }
+            Pattern::Coords => {
+                let point = region.pixel_to_point(bounds, pixel);
+                let re = (point.re - region.upper_left.re) / region.width();
+                let im = (region.upper_left.im - point.im) / region.height();
+                [(re * 255.0) as u8, (im * 255.0) as u8, 0]
+            }
+        }
+    }
+}
+
+/// `render_tile_counted` と同じだが、フラクタルの代わりにテストパターン `pattern` を描く
+///
+/// 書き出しまでの流れはフラクタルと同じなので、タイルをつなぎ合わせた結果も確かめられる。
+pub fn render_pattern(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    tile: &Tile,
+    pattern: Pattern,
+) {
+    assert!(tile.fits(bounds));
+    assert_eq!(pixels.len(), tile.size.0 * tile.size.1 * 3);
+
+    pixels
+        .par_chunks_mut(tile.size.0 * 3)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, rgb) in row.chunks_mut(3).enumerate() {
+                let pixel = (tile.offset.0 + x, tile.offset.1 + y);
+                rgb.copy_from_slice(&pattern.color(bounds, region, pixel));
+            }
+        });
+}
+
+#[test]
+fn test_render_pattern() {
+    // 8 ブロック × 6 ブロック
+    let bounds = (64, 48);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_pattern(&mut pixels, bounds, &Region::CLASSIC, &Tile::full(bounds), Pattern::Checker);
+
+    let at = |x: usize, y: usize| &pixels[(y * bounds.0 + x) * 3..(y * bounds.0 + x) * 3 + 3];
+    const WHITE: [u8; 3] = [255, 255, 255];
+    const BLACK: [u8; 3] = [0, 0, 0];
+    // 4 隅（右上・左下は奇数番目のブロック、右下は偶数番目）
+    assert_eq!(at(0, 0), WHITE);
+    assert_eq!(at(63, 0), BLACK);
+    assert_eq!(at(0, 47), BLACK);
+    assert_eq!(at(63, 47), WHITE);
+    // ブロックの境目で色が変わる
+    assert_eq!(at(7, 7), WHITE);
+    assert_eq!(at(8, 7), BLACK);
+    assert_eq!(at(8, 8), WHITE);
+
+    // タイルは画像全体の位置で模様が決まる
+    let tile = Tile { size: (10, 10), offset: (8, 0) };
+    let mut part = vec![0u8; 10 * 10 * 3];
+    render_pattern(&mut part, bounds, &Region::CLASSIC, &tile, Pattern::Checker);
+    assert_eq!(part[..3], BLACK);
+
+    // 座標は左上が 0、右・下へ行くほど大きい
+    assert_eq!(Pattern::Coords.color(bounds, &Region::CLASSIC, (0, 0)), [0, 0, 0]);
+    assert_eq!(Pattern::Coords.color(bounds, &Region::CLASSIC, (32, 24)), [127, 127, 0]);
+}
+
+/// `add_legend` で画像の右に足す凡例の幅（ピクセル）
+pub const LEGEND_WIDTH: usize = 72;
+
+// 凡例の配置（ピクセル）: 余白、グラデーションの帯の幅、目盛りの線の長さ、数字の拡大率（数字が凡例の幅に
+// 収まらなければ 1 にする）
+const LEGEND_MARGIN: usize = 8;
+const LEGEND_BAR_WIDTH: usize = 12;
+const LEGEND_TICK_LENGTH: usize = 4;
+const LEGEND_DIGIT_SCALE: usize = 2;
+
+/// 凡例の数字の 3×5 ドットのビットマップ（1 行 3 ビット、上の行から）
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// 大きさ `bounds` の RGB 画像の右に、パレットと反復回数の目盛りの凡例を足した画像
+///
+/// 幅は `LEGEND_WIDTH` だけ広がり、元の画像の部分はそのまま残る。凡例の帯は上端が反復回数 0、
+/// 下端が `max_iter` の近くで、0・`max_iter / 2`・`max_iter` の位置に目盛りと数字を描く。
+/// `max_iter` が 6 桁以上なら数字を小さくして幅に収める。
+pub fn add_legend(pixels: &[u8], bounds: (usize, usize), palette: &Palette, max_iter: u32) -> Vec<u8> {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+
+    let width = bounds.0 + LEGEND_WIDTH;
+    let mut out = vec![0u8; width * bounds.1 * 3];
+    for (row, legend_row) in pixels.chunks(bounds.0 * 3).zip(out.chunks_mut(width * 3)) {
+        legend_row[..row.len()].copy_from_slice(row);
+        for pixel in legend_row[row.len()..].chunks_mut(3) {
+            pixel.copy_from_slice(&[32, 32, 32]);
+        }
+    }
+    let mut put = |x: usize, y: usize, color: [u8; 3]| {
+        if x < width && y < bounds.1 {
+            out[(y * width + x) * 3..][..3].copy_from_slice(&color);
+        }
+    };
+
+    // グラデーションの帯（画像が低すぎて余白がとれなければ上下いっぱいに描く）
+    let bar_left = bounds.0 + LEGEND_MARGIN;
+    let (top, bar_height) = match bounds.1.checked_sub(2 * LEGEND_MARGIN) {
+        Some(height) if height > 0 => (LEGEND_MARGIN, height),
+        _ => (0, bounds.1),
+    };
+    let colors = ColorTable::new(palette, max_iter);
+    for row in 0..bar_height {
+        let color = colors.color((row as u64 * max_iter as u64 / bar_height as u64) as u32);
+        for x in bar_left..bar_left + LEGEND_BAR_WIDTH {
+            put(x, top + row, color);
+        }
+    }
+
+    // 目盛りと数字（数字の縦の中央を目盛りに合わせる）
+    let tick_left = bar_left + LEGEND_BAR_WIDTH + 1;
+    let text_left = tick_left + LEGEND_TICK_LENGTH + 2;
+    // 1 桁は 3 ドットと 1 ドットの間隔。いちばん長い `max_iter` の数字が右端に収まる拡大率にする
+    let text_width = (max_iter.to_string().len() * 4 - 1) * LEGEND_DIGIT_SCALE;
+    let scale = if text_left + text_width <= width { LEGEND_DIGIT_SCALE } else { 1 };
+    let digit_height = 5 * scale;
+    for (fraction, iter) in [(0, 0), (1, max_iter / 2), (2, max_iter)] {
+        let y = top + bar_height.saturating_sub(1) * fraction / 2;
+        for x in tick_left..tick_left + LEGEND_TICK_LENGTH {
+            put(x, y, [255, 255, 255]);
+        }
+        let text_top = (y + 1).saturating_sub(digit_height / 2).min(bounds.1.saturating_sub(digit_height));
+        for (i, digit) in iter.to_string().bytes().enumerate() {
+            let (left, bitmap) = (text_left + i * 4 * scale, DIGITS[(digit - b'0') as usize]);
+            for dy in 0..digit_height {
+                for dx in 0..3 * scale {
+                    if bitmap[dy / scale] & (0b100 >> (dx / scale)) != 0 {
+                        put(left + dx, text_top + dy, [255, 255, 255]);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_add_legend() {
+    let bounds = (40, 100);
+    let max_iter = 200;
+    let pixels = compute_iterations(bounds, &Region::CLASSIC, max_iter).colorize(&Palette::Classic);
+    let with_legend = add_legend(&pixels, bounds, &Palette::Classic, max_iter);
+
+    // 幅だけが LEGEND_WIDTH 広がり、元の画像はそのまま左に残る
+    let width = bounds.0 + LEGEND_WIDTH;
+    assert_eq!(with_legend.len(), width * bounds.1 * 3);
+    for (row, legend_row) in pixels.chunks(bounds.0 * 3).zip(with_legend.chunks(width * 3)) {
+        assert_eq!(row, &legend_row[..bounds.0 * 3]);
+    }
+
+    // 帯の列は上から下へパレットのグラデーションになっている
+    let x = bounds.0 + LEGEND_MARGIN + LEGEND_BAR_WIDTH / 2;
+    let at = |y: usize| with_legend[(y * width + x) * 3..][..3].to_vec();
+    let bar_height = bounds.1 - 2 * LEGEND_MARGIN;
+    for row in [0, bar_height / 3, bar_height / 2, bar_height - 1] {
+        let iter = (row * max_iter as usize / bar_height) as u32;
+        assert_eq!(at(LEGEND_MARGIN + row), Palette::Classic.color(iter, max_iter));
+    }
+    assert_ne!(at(LEGEND_MARGIN), at(LEGEND_MARGIN + bar_height / 2));
+    assert_eq!(at(0), [32, 32, 32]);
+
+    // 6 桁以上の数字は小さくして、右端の列を残す（大きいままだと最後の数字がはみ出る）
+    for max_iter in [99_999, 100_000, u32::MAX] {
+        let with_legend = add_legend(&pixels, bounds, &Palette::Classic, max_iter);
+        let right_column = (0..bounds.1).map(|y| &with_legend[(y * width + width - 1) * 3..][..3]);
+        assert!(right_column.into_iter().all(|pixel| pixel == [32, 32, 32]), "{}", max_iter);
+        let text = (0..bounds.1 * width).filter(|i| i % width > bounds.0 + LEGEND_MARGIN + LEGEND_BAR_WIDTH)
+            .filter(|i| with_legend[i * 3..][..3] == [255, 255, 255]).count();
+        assert!(text > 0, "{}", max_iter);
+    }
+
+    // 高さ 0 でもパニックしない
+    assert!(add_legend(&[], (bounds.0, 0), &Palette::Classic, max_iter).is_empty());
+}
+
+#[test]
+fn test_render_tiles_stitch() {
+    // 割り切れない大きさで、4 つのタイルの大きさがそろわないようにする
+    let bounds = (61, 43);
+    let (split_x, split_y) = (25, 30);
+    let mut whole = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color_parallel(&mut whole, bounds, &Region::CLASSIC, 200);
+
+    let tiles = [
+        Tile { size: (split_x, split_y), offset: (0, 0) },
+        Tile { size: (bounds.0 - split_x, split_y), offset: (split_x, 0) },
+        Tile { size: (split_x, bounds.1 - split_y), offset: (0, split_y) },
+        Tile { size: (bounds.0 - split_x, bounds.1 - split_y), offset: (split_x, split_y) },
+    ];
+    let mut stitched = vec![0u8; whole.len()];
+    for tile in &tiles {
+        let mut pixels = vec![0u8; tile.size.0 * tile.size.1 * 3];
+        render_tile_counted::<f64>(&mut pixels, bounds, &Region::CLASSIC, tile, 200,
+                                   default_bailout_sqr(), Fractal::Mandelbrot, &Palette::Classic,
+                                   InteriorColor::Black, &AtomicUsize::new(0), &AtomicBool::new(false));
+
+        for (y, row) in pixels.chunks(tile.size.0 * 3).enumerate() {
+            let start = ((tile.offset.1 + y) * bounds.0 + tile.offset.0) * 3;
+            stitched[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+    assert!(stitched == whole);
+
+    assert!(!Tile { size: (10, 10), offset: (55, 0) }.fits(bounds));
+    assert_eq!(Tile { size: (3, 2), offset: (4, 5) }.scaled(2), Tile { size: (6, 4), offset: (8, 10) });
+
+    // 右下のタイルの範囲は、画像全体の右下の角で終わる
+    let corner = tiles[3].region(bounds, &Region::CLASSIC);
+    assert_eq!(corner.upper_left, Region::CLASSIC.pixel_to_point(bounds, (split_x, split_y)));
+    assert_eq!(corner.lower_right, Region::CLASSIC.lower_right);
+    assert_eq!(Tile::full(bounds).region(bounds, &Region::CLASSIC), Region::CLASSIC);
+}
+
+/// RGB バッファを行ごとに並列に、ピクセル (x, y) の色を `color(x, y)` で求めて描画する
+///
+/// チャンネルの型 `P` は u8 のほか、`write_image_exr` に渡す f32 でもよい。
+pub fn render_pixels_parallel<P: Copy + Send>(
+    pixels: &mut [P],
+    bounds: (usize, usize),
+    rows_done: &AtomicUsize,
+    color: impl Fn(usize, usize) -> [P; 3] + Sync,
+) {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let _span = tracing::info_span!("render", width = bounds.0, height = bounds.1).entered();
+
+    pixels
+        .par_chunks_mut(bounds.0 * 3)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, rgb) in row.chunks_mut(3).enumerate() {
+                rgb.copy_from_slice(&color(x, y));
+            }
+            rows_done.fetch_add(1, Ordering::Relaxed);
+        });
+}
+
+/// RGB バッファを行ごとに並列に、各ピクセルの点 `point` の色を `f(point)` で求めて描画する
+///
+/// ピクセルの位置と複素平面上の点の対応だけを受け持ち、反復や色付けは `f` に任せる。
+/// 発散判定以外の計算や、別の実装（GPU など）に差し替えるときの入口にする。
+pub fn render_with<F>(pixels: &mut [u8], bounds: (usize, usize), region: &Region, f: F)
+where
+    F: Fn(Complex<f64>) -> [u8; 3] + Sync,
+{
+    render_with_counted(pixels, bounds, region, &AtomicUsize::new(0), f);
+}
+
+/// `render_with` と同じだが、1 行終わるごとに `rows_done` を 1 増やす
+pub fn render_with_counted<F>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    rows_done: &AtomicUsize,
+    f: F,
+) where
+    F: Fn(Complex<f64>) -> [u8; 3] + Sync,
+{
+    let grid = region.grid(bounds);
+    render_pixels_parallel(pixels, bounds, rows_done, |x, y| f(grid.point((x, y))));
+}
+
+#[test]
+fn test_render_with() {
+    let bounds = (8, 4);
+    let region = Region::from_corners(Complex::new(-1.0, 1.0), Complex::new(1.0, -1.0)).unwrap();
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+
+    // 実部 -1.0..1.0 を 0..=200 に写して赤に入れ、緑・青は目印にする
+    render_with(&mut pixels, bounds, &region, |point| {
+        [((point.re + 1.0) * 100.0).round() as u8, 7, 9]
+    });
+
+    let at = |x: usize, y: usize| &pixels[(y * bounds.0 + x) * 3..(y * bounds.0 + x) * 3 + 3];
+    assert_eq!(at(0, 0), [0, 7, 9]);
+    assert_eq!(at(2, 1), [50, 7, 9]);
+    assert_eq!(at(4, 3), [100, 7, 9]);
+    assert_eq!(at(7, 2), [175, 7, 9]);
+}
+
+/// 1 ピクセルあたり `samples` 点をずらしてサンプリングし、その色を平均して描画する（ジッター AA）
+///
+/// 各点はピクセルの中のランダムな位置に置くが、位置は `seed` とピクセル・サンプルの番号だけで決まるので、
+/// 同じ `seed` なら何度描いても（スレッドの割り当てが変わっても）同じ結果になる。
+/// `f(point)` は点の色と、その点が集合の内部かどうかを返す。`samples` が 4 より多いときは
+/// 先にピクセルの 4 隅を調べ、すべて内部ならそのピクセルは内部とみなして残りのサンプルを省く。
+/// 色は `space` で平均する。
+#[allow(clippy::too_many_arguments)]
+pub fn render_jittered_counted<F>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    region: &Region,
+    samples: usize,
+    seed: u64,
+    space: GammaSpace,
+    rows_done: &AtomicUsize,
+    f: F,
+) where
+    F: Fn(Complex<f64>) -> ([u8; 3], bool) + Sync,
+{
+    assert!(samples >= 1);
+    let grid = region.grid(bounds);
+    // ピクセル (x, y) の左上の角から右へ dx、下へ dy（ピクセル単位）の点
+    let point = |x: usize, y: usize, dx: f64, dy: f64| {
+        grid.point((x, y)) + Complex::new(dx * grid.step.0, -dy * grid.step.1)
+    };
+
+    render_pixels_parallel(pixels, bounds, rows_done, |x, y| {
+        if samples > 4 {
+            let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+                .map(|(dx, dy)| f(point(x, y, dx, dy)));
+            if corners.iter().all(|&(_, inside)| inside) {
+                return average_colors(corners.iter().map(|&(color, _)| color), space);
+            }
+        }
+
+        let pixel = (y * bounds.0 + x) as u64;
+        average_colors((0..samples as u64).map(|k| {
+            let (dx, dy) = jitter(seed, pixel * samples as u64 + k);
+            f(point(x, y, dx, dy)).0
+        }), space)
+    });
+}
+
+/// スーパーサンプリング・ジッター AA でサブピクセルの色を平均する空間（`--color-gamma-space`）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GammaSpace {
+    /// sRGB の値を光の強さ（線形）に戻してから平均し、sRGB に戻す（縁の明るさが正しくなる）
+    #[default]
+    Linear,
+    /// sRGB の値のまま平均する（光としては暗すぎ、黒と白の縁が暗く見える）
+    Srgb,
+}
+
+/// sRGB の値（0..=255）→ 光の強さ（0.0..=1.0）
+pub fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// 光の強さ（0.0..=1.0）→ sRGB の値（0..=255、四捨五入）
+pub fn linear_to_srgb(linear: f64) -> u8 {
+    let v = linear.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v * 255.0).round() as u8
+}
+
+/// 色の平均（チャンネルごとに `space` で平均して四捨五入）
+fn average_colors(colors: impl Iterator<Item = [u8; 3]>, space: GammaSpace) -> [u8; 3] {
+    match space {
+        GammaSpace::Srgb => {
+            let (mut sum, mut count) = ([0u32; 3], 0u32);
+            for color in colors {
+                for c in 0..3 {
+                    sum[c] += color[c] as u32;
+                }
+                count += 1;
+            }
+            sum.map(|total| ((total + count / 2) / count) as u8)
+        }
+        GammaSpace::Linear => {
+            let (mut sum, mut count) = ([0.0; 3], 0);
+            for color in colors {
+                for c in 0..3 {
+                    sum[c] += srgb_to_linear(color[c]);
+                }
+                count += 1;
+            }
+            sum.map(|total| linear_to_srgb(total / count as f64))
+        }
+    }
+}
+
+#[test]
+fn test_average_colors_gamma_space() {
+    let (black, white) = ([0, 0, 0], [255, 255, 255]);
+
+    // sRGB のまま平均すると 128 だが、光の強さで半分にすると sRGB では 188 と明るくなる
+    let naive = average_colors([black, white].into_iter(), GammaSpace::Srgb);
+    let linear = average_colors([black, white].into_iter(), GammaSpace::Linear);
+    assert_eq!(naive, [128; 3]);
+    assert_eq!(linear, [188; 3]);
+    assert!(linear[0] > naive[0]);
+
+    // 同じ色ばかりなら、どちらの空間でも変わらない
+    for value in 0..=255 {
+        assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        let color = [value, 255 - value, value / 2];
+        assert_eq!(average_colors([color; 4].into_iter(), GammaSpace::Linear), color);
+        assert_eq!(average_colors([color; 4].into_iter(), GammaSpace::Srgb), color);
+    }
+}
+
+/// SplitMix64 の 1 ステップ（`x` をよく混ぜた 64 ビット値）
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// `seed` と通し番号 `index` で決まる、ピクセル内の位置 (0.0..1.0, 0.0..1.0)
+fn jitter(seed: u64, index: u64) -> (f64, f64) {
+    let h = splitmix64(seed ^ splitmix64(index));
+    let unit = |bits: u64| (bits >> 11) as f64 / (1u64 << 53) as f64;
+    (unit(h), unit(splitmix64(h)))
+}
+
+#[test]
+fn test_render_jittered_deterministic() {
+    let bounds = (48, 32);
+    let max_iter = 200;
+    let sample = |point: Complex<f64>| {
+        let iter = escape_time(point, max_iter, default_bailout_sqr());
+        (color_map(iter, max_iter), iter >= max_iter)
+    };
+    let render = |samples, seed| {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        render_jittered_counted(&mut pixels, bounds, &Region::CLASSIC, samples, seed, GammaSpace::Linear,
+                                &AtomicUsize::new(0), sample);
+        pixels
+    };
+
+    // 同じ seed・サンプル数なら、スレッドの割り当てによらずバイト単位で一致する
+    let first = render(8, 42);
+    assert!(first == render(8, 42));
+    assert!(first != render(8, 43));
+    assert!(first != render(1, 42));
+
+    for index in 0..1000 {
+        let (dx, dy) = jitter(42, index);
+        assert!((0.0..1.0).contains(&dx) && (0.0..1.0).contains(&dy));
+    }
+
+    // 集合の内部だけの範囲では、各ピクセルの 4 隅だけを調べて残りのサンプルを省く
+    let inside = Region::from_corners(Complex::new(-0.2, 0.1), Complex::new(-0.1, 0.0)).unwrap();
+    let calls = AtomicUsize::new(0);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_jittered_counted(&mut pixels, bounds, &inside, 16, 42, GammaSpace::Linear, &AtomicUsize::new(0),
+                            |point| {
+                                calls.fetch_add(1, Ordering::Relaxed);
+                                sample(point)
+                            });
+    assert_eq!(calls.load(Ordering::SeqCst), bounds.0 * bounds.1 * 4);
+    assert!(pixels.iter().all(|&c| c == 0));
+}
+
+/// `render(rows_done)` を実行し、`enabled` なら別スレッドで進捗率を標準エラーに表示する
+///
+/// `render` は 1 行終わるごとに `rows_done` を増やす。表示は 1 秒に数回更新し、
+/// 終了時には進捗の行を消してから戻る。
+pub fn with_progress<R>(enabled: bool, total_rows: usize, render: impl FnOnce(&AtomicUsize) -> R) -> R {
+    let rows_done = AtomicUsize::new(0);
+    if !enabled {
+        return render(&rows_done);
+    }
+
+    let finished = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                let done = rows_done.load(Ordering::Relaxed);
+                eprint!("\r描画中... {:3}%", done * 100 / total_rows.max(1));
+                std::thread::sleep(Duration::from_millis(250));
+            }
+            eprint!("\r{}\r", " ".repeat(20));
+        });
+
+        // render がパニックしても表示スレッドが止まるようにする
+        struct Finish<'a>(&'a AtomicBool);
+        impl Drop for Finish<'_> {
+            fn drop(&mut self) { self.0.store(true, Ordering::SeqCst); }
+        }
+        let _finish = Finish(&finished);
+
+        render(&rows_done)
+    })
+}
+
+#[test]
+fn test_progress_counts_every_row() {
+    let bounds = (40, 30);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+
+    let rows = with_progress(true, bounds.1, |rows_done| {
+        render_color_parallel_counted::<f64>(&mut pixels, bounds, &Region::CLASSIC, 200,
+                                             default_bailout_sqr(), Fractal::Mandelbrot, &Palette::Classic,
+                                             InteriorColor::Black, rows_done, &AtomicBool::new(false));
+        rows_done.load(Ordering::SeqCst)
+    });
+    assert_eq!(rows, bounds.1);
+
+    let rows = with_progress(false, bounds.1, |rows_done| {
+        compute_iterations_counted::<f64>(bounds, &Region::CLASSIC, 200, default_bailout_sqr(),
+                                          Fractal::Mandelbrot, rows_done);
+        rows_done.load(Ordering::SeqCst)
+    });
+    assert_eq!(rows, bounds.1);
+}
+
+/// ピクセル `start` から右へ 1 行分の RGB を `row` に書き込む
+#[allow(clippy::too_many_arguments)]
+fn render_row<T: Float>(
+    row: &mut [u8],
+    start: (usize, usize),
+    grid: &PixelGrid<T>,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    colors: &ColorTable,
+    interior: InteriorColor,
+) {
+    for (x, rgb) in row.chunks_mut(3).enumerate() {
+        let point = grid.point((start.0 + x, start.1));
+        let result = fractal.escape_time_full(point, max_iter, bailout_sqr);
+
+        let color = match result.count {
+            Some(iter) => colors.color(iter),
+            None => interior.color(result.final_z.norm_sqr().to_f64().unwrap()),
+        };
+        rgb.copy_from_slice(&color);
+    }
+}
+
+#[test]
+fn test_render_color_parallel_matches_serial() {
+    let bounds = (300, 200);
+    let region = Region::CLASSIC;
+    let max_iter = 200;
+
+    let mut serial = vec![0u8; bounds.0 * bounds.1 * 3];
+    let mut parallel = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color(&mut serial, bounds, &region, max_iter);
+    render_color_parallel(&mut parallel, bounds, &region, max_iter);
+    assert!(serial == parallel);
+
+    // 従来の 2 パス（反復回数 → 色）とも一致する
+    let iters = compute_iterations(bounds, &region, max_iter);
+    assert!(serial == iters.colorize(&Palette::Classic));
+}
+
+#[test]
+fn test_render_color_parallel_not_slower() {
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    if cores < 2 {
+        return;
+    }
+
+    let bounds = (300, 200);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+
+    // 3 回ずつ計測して最速の値で比べる（大まかなチェック。詳しい数字は cargo bench で）
+    type Renderer = fn(&mut [u8], (usize, usize), &Region, u32);
+    let mut fastest = |render: Renderer| {
+        (0..3).map(|_| {
+            let start = Instant::now();
+            render(&mut pixels, bounds, &Region::CLASSIC, 200);
+            start.elapsed()
+        }).min().unwrap()
+    };
+    let serial = fastest(render_color);
+    let parallel = fastest(render_color_parallel);
+    assert!(parallel.as_secs_f64() <= serial.as_secs_f64() * 1.5,
+            "parallel {:?} vs serial {:?}", parallel, serial);
+}
+
+/// 描画にかかる時間の見積もり（`estimate_render`）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderEstimate {
+    /// 画像全体のピクセル数
+    pub pixels: usize,
+    /// 実際に計算したピクセル数
+    pub sampled: usize,
+    /// 計算したピクセルの反復回数の平均
+    pub mean_iterations: f64,
+    /// 計算したピクセル全部にかかった時間
+    pub elapsed: Duration,
+}
+
+impl RenderEstimate {
+    /// `threads` スレッドで画像全体を描いたときの時間（計算した時間をピクセル数の比で伸ばす）
+    pub fn duration(&self, threads: usize) -> Duration {
+        let scale = self.pixels as f64 / self.sampled.max(1) as f64;
+        self.elapsed.mul_f64(scale / threads.max(1) as f64)
+    }
+}
+
+/// 縦横 `stride` ピクセルおきの点だけを 1 スレッドで計算して、描画にかかる時間を見積もる
+///
+/// 画像は書かない。反復回数の平均は間引いた点から求めるので、同じ範囲なら `bounds` に
+/// よらずほぼ同じになり、見積もりはピクセル数にほぼ比例する。
+pub fn estimate_render(
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    stride: usize,
+) -> RenderEstimate {
+    assert!(stride > 0);
+    let grid = region.grid(bounds);
+
+    let start = Instant::now();
+    let mut sampled = 0;
+    let mut iterations = 0u64;
+    for y in (0..bounds.1).step_by(stride) {
+        for x in (0..bounds.0).step_by(stride) {
+            iterations += fractal.escape_time(grid.point((x, y)), max_iter, bailout_sqr) as u64;
+            sampled += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    RenderEstimate {
+        pixels: bounds.0 * bounds.1,
+        sampled,
+        mean_iterations: iterations as f64 / sampled.max(1) as f64,
+        elapsed,
+    }
+}
+
+#[test]
+fn test_estimate_render() {
+    let bounds = (300, 200);
+    let estimate = estimate_render(bounds, &Region::CLASSIC, 200, default_bailout_sqr(), Fractal::Mandelbrot, 16);
+    assert_eq!(estimate.sampled, 19 * 13);
+    assert!(estimate.duration(1) > Duration::ZERO);
+    assert!(estimate.duration(4) < estimate.duration(1));
+
+    // 幅を 2 倍にすると、計算する点も画像のピクセル数もちょうど 2 倍になり（伸ばす比は同じ）、
+    // 1 点あたりの反復回数はほとんど変わらない（見積もりは仕事の量に比例して 2 倍くらいになる）
+    let doubled = estimate_render((600, 200), &Region::CLASSIC, 200, default_bailout_sqr(),
+                                  Fractal::Mandelbrot, 16);
+    assert_eq!(doubled.pixels, 2 * estimate.pixels);
+    assert_eq!(doubled.sampled, 2 * estimate.sampled);
+    let ratio = doubled.mean_iterations / estimate.mean_iterations;
+    assert!((0.9..1.1).contains(&ratio), "ratio = {}", ratio);
+
+    // 計算した時間をピクセル数の比で伸ばし、スレッド数で割る
+    let estimate = RenderEstimate { pixels: 1000, sampled: 10, mean_iterations: 1.0,
+                                    elapsed: Duration::from_secs(2) };
+    assert_eq!(estimate.duration(1), Duration::from_secs(200));
+    assert_eq!(estimate.duration(4), Duration::from_secs(50));
+}
+
+/// `estimate_area` で点をばらまく長方形（集合全体 -2.0..0.48 × -1.13..1.13 を囲む）
+pub const AREA_BOX: Region = Region {
+    upper_left: Complex::new(-2.0, 1.25),
+    lower_right: Complex::new(0.5, -1.25),
+};
+
+/// `estimate_area` の反復回数の上限（境界のすぐ外の点も発散したと判定できるように大きくとる）
+pub const AREA_MAX_ITER: u32 = 10_000;
+
+/// マンデルブロ集合の面積の推定値（`estimate_area`）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaEstimate {
+    /// ばらまいた点の数
+    pub samples: usize,
+    /// そのうち集合の内部と判定した点の数
+    pub inside: usize,
+    /// 面積の推定値（長方形の面積 × 内部の点の割合）
+    pub area: f64,
+    /// 推定値の標準誤差（二項分布の割合の標準誤差 × 長方形の面積）
+    pub std_error: f64,
+}
+
+impl AreaEstimate {
+    /// 95% 信頼区間（推定値 ± 1.96 × 標準誤差）
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        (self.area - 1.96 * self.std_error, self.area + 1.96 * self.std_error)
+    }
+}
+
+/// `AREA_BOX` に一様にばらまいた `samples` 個の点のうち、`max_iter` 回で発散しなかった割合から
+/// マンデルブロ集合の面積を推定する（モンテカルロ法）
+///
+/// 点の位置は `seed` と点の番号だけで決まるので、同じ `seed` なら何度実行しても（スレッドの
+/// 割り当てが変わっても）同じ結果になる。上限に達した点を内部とみなすので、境界のすぐ外の点の分だけ
+/// 本当の面積（およそ 1.5066）より少し大きくなる。
+pub fn estimate_area(samples: usize, max_iter: u32, seed: u64) -> AreaEstimate {
+    let _span = tracing::info_span!("estimate_area", samples, max_iter).entered();
+    let (width, height) = (AREA_BOX.width(), AREA_BOX.height());
+    let inside = (0..samples as u64)
+        .into_par_iter()
+        .filter(|&index| {
+            let (x, y) = jitter(seed, index);
+            let point = AREA_BOX.upper_left + Complex::new(x * width, -y * height);
+            escape_time_fast(point, max_iter, default_bailout_sqr()) >= max_iter
+        })
+        .count();
+
+    let box_area = width * height;
+    let fraction = inside as f64 / samples.max(1) as f64;
+    AreaEstimate {
+        samples,
+        inside,
+        area: box_area * fraction,
+        std_error: box_area * (fraction * (1.0 - fraction) / samples.max(1) as f64).sqrt(),
+    }
+}
+
+#[test]
+fn test_estimate_area() {
+    let estimate = estimate_area(20_000, 1000, 42);
+    assert_eq!(estimate.samples, 20_000);
+    assert!((1.3..1.7).contains(&estimate.area), "{:?}", estimate);
+    let (low, high) = estimate.confidence_interval();
+    assert!(low < estimate.area && estimate.area < high);
+    assert!(high - low < 0.2, "{:?}", estimate);
+
+    // 同じ seed なら同じ結果
+    assert_eq!(estimate_area(2000, 200, 7), estimate_area(2000, 200, 7));
+    assert_eq!(estimate_area(0, 200, 7).area, 0.0);
+}
+
+/// `build_histogram` で 1 つの局所ヒストグラムにまとめるピクセル数
+const HISTOGRAM_CHUNK: usize = 1 << 16;
+
+/// 反復回数ごとのピクセル数を数える（内部 = max_iter のピクセルは除外）
+///
+/// `HISTOGRAM_CHUNK` ピクセルずつの局所ヒストグラムを並列に数え、先頭の塊から順に足し合わせる。
+/// 塊の切り方も足す順番もスレッド数によらないので、結果はいつも同じになる。
+pub fn build_histogram(iters: &IterBuffer) -> Vec<usize> {
+    let max_iter = iters.max_iter as f64;
+    let locals: Vec<Vec<usize>> = iters
+        .data
+        .par_chunks(HISTOGRAM_CHUNK)
+        .map(|chunk| {
+            let mut local = vec![0; iters.max_iter as usize];
+            for &iter in chunk.iter().filter(|&&iter| iter < max_iter) {
+                local[iter as usize] += 1;
+            }
+            local
+        })
+        .collect();
+
+    let mut histogram = vec![0; iters.max_iter as usize];
+    for local in &locals {
+        for (total, count) in histogram.iter_mut().zip(local) {
+            *total += count;
+        }
+    }
+    histogram
+}
+
+/// ヒストグラム → 累積分布（各反復回数以下のピクセルの割合、0.0..=1.0）
+pub fn cumulative_distribution(histogram: &[usize]) -> Vec<f32> {
+    let total: usize = histogram.iter().sum();
+    let mut cdf = Vec::with_capacity(histogram.len());
+    let mut running = 0;
+
+    for &count in histogram {
+        running += count;
+        cdf.push(if total == 0 { 0.0 } else { running as f32 / total as f32 });
+    }
+    cdf
+}
+
+#[test]
+fn test_histogram_deterministic() {
+    // 局所ヒストグラムの塊をまたぐ大きさで、1 スレッドで素直に数えたものと一致する
+    let bounds = (400, 300);
+    let max_iter = 200;
+    let iters = compute_iterations(bounds, &Region::CLASSIC, max_iter);
+    assert!(iters.data.len() > HISTOGRAM_CHUNK);
+    let mut expected = vec![0; max_iter as usize];
+    for &iter in iters.data.iter().filter(|&&iter| iter < max_iter as f64) {
+        expected[iter as usize] += 1;
+    }
+    assert_eq!(build_histogram(&iters), expected);
+
+    // スレッド数を変えても、計算から色付けまでバイト単位で同じ
+    let render = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| {
+            let iters = compute_iterations(bounds, &Region::CLASSIC, max_iter);
+            (iters.colorize_histogram(&Palette::Classic),
+             iters.colorize_histogram_dithered(&Palette::Hsv { cycles: 2.0 }))
+        })
+    };
+    let single = render(1);
+    for threads in [2, 8] {
+        assert!(render(threads) == single, "{} threads", threads);
+    }
+}
+
+#[test]
+fn test_histogram_equalization() {
+    let bounds = (120, 80);
+    let max_iter = 200;
+    let iters = compute_iterations(bounds, &Region::CLASSIC, max_iter);
+
+    // 内部ピクセルはヒストグラムに含まれない
+    let histogram = build_histogram(&iters);
+    let interior = iters.data.iter().filter(|&&i| i >= max_iter as f64).count();
+    assert!(interior > 0);
+    assert_eq!(histogram.iter().sum::<usize>(), iters.data.len() - interior);
+
+    // 累積分布は単調増加で 1.0 で終わる
+    let cdf = cumulative_distribution(&histogram);
+    assert!(cdf.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(*cdf.last().unwrap(), 1.0);
+
+    // 内部は黒のまま
+    let equalized = iters.colorize_histogram(&Palette::Classic);
+
+    for (i, &iter) in iters.data.iter().enumerate() {
+        if iter >= max_iter as f64 {
+            assert_eq!(&equalized[i * 3..i * 3 + 3], &[0, 0, 0]);
+        }
+    }
+
+    // 平坦化した方がパレットの広い範囲を使う
+    let spread = |pixels: &[u8]| {
+        let sums: Vec<u32> = pixels.chunks(3)
+            .zip(&iters.data)
+            .filter(|&(_, &iter)| iter < max_iter as f64)
+            .map(|(p, _)| p.iter().map(|&c| c as u32).sum())
+            .collect();
+        sums.iter().max().unwrap() - sums.iter().min().unwrap()
+    };
+    let linear = iters.colorize(&Palette::Classic);
+    assert!(spread(&equalized) > spread(&linear));
+
+    // ディザをかけても各チャンネルは ±1 しか変わらず、内部は黒のまま
+    let dithered = iters.colorize_histogram_dithered(&Palette::Classic);
+    assert!(dithered.iter().zip(&equalized).all(|(a, b)| a.abs_diff(*b) <= 1));
+    assert!(dithered != equalized);
+}
+
+/// N×N のサブピクセルの RGB を平均して 1 ピクセルにする
+///
+/// `pixels` は `(bounds.0 * n) × (bounds.1 * n)` の RGB バッファ。
+/// 出力ピクセル (x, y) は、サブピクセル (x * n + i, y * n + j)（0 <= i, j < n）を `space` で平均したものになる。
+pub fn downsample(pixels: &[u8], bounds: (usize, usize), n: usize, space: GammaSpace) -> Vec<u8> {
+    assert_eq!(pixels.len(), bounds.0 * n * bounds.1 * n * 3);
+
+    let row_len = bounds.0 * n * 3;
+    let mut out = Vec::with_capacity(bounds.0 * bounds.1 * 3);
+
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let subpixels = (0..n).flat_map(|j| (0..n).map(move |i| (i, j))).map(|(i, j)| {
+                let offset = (y * n + j) * row_len + (x * n + i) * 3;
+                [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+            });
+            out.extend_from_slice(&average_colors(subpixels, space));
+        }
+    }
+    out
+}
+
+#[test]
+fn test_supersample_average() {
+    let bounds = (40, 30);
+    let region = Region::CLASSIC;
+    let max_iter = 200;
+    let n = 2;
+    let sample_bounds = (bounds.0 * n, bounds.1 * n);
+
+    let iters = compute_iterations(sample_bounds, &region, max_iter);
+    let sampled = iters.colorize(&Palette::Classic);
+    let pixels = downsample(&sampled, bounds, n, GammaSpace::Srgb);
+
+    // 4 つのサブサンプルの色を直接計算する
+    let subsamples = |x: usize, y: usize| {
+        let mut colors = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                let point = region.pixel_to_point(sample_bounds, (x * n + i, y * n + j));
+                colors.push(color_map(escape_time(point, max_iter, default_bailout_sqr()), max_iter));
+            }
+        }
+        colors
+    };
+
+    // サブサンプルの色がばらばらなピクセル（集合の縁）を探す
+    let (x, y) = (0..bounds.1)
+        .flat_map(|y| (0..bounds.0).map(move |x| (x, y)))
+        .find(|&(x, y)| {
+            let colors = subsamples(x, y);
+            colors.contains(&[0, 0, 0]) && colors.iter().any(|&c| c != [0, 0, 0])
+        })
+        .expect("no edge pixel found");
+
+    let colors = subsamples(x, y);
+    for c in 0..3 {
+        let sum: u32 = colors.iter().map(|color| color[c] as u32).sum();
+        let mean = (sum as f64 / colors.len() as f64).round() as u8;
+        assert_eq!(pixels[(y * bounds.0 + x) * 3 + c], mean);
+    }
+
+    // 光の強さで平均すると、黒の混じった縁は暗くならず、サブピクセルがそろったピクセルはそのままの色になる
+    let linear = downsample(&sampled, bounds, n, GammaSpace::Linear);
+    let row_len = sample_bounds.0 * 3;
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let colors: Vec<&[u8]> = (0..n * n)
+                .map(|k| (x * n + k % n) * 3 + (y * n + k / n) * row_len)
+                .map(|offset| &sampled[offset..offset + 3])
+                .collect();
+            let offset = (y * bounds.0 + x) * 3;
+            let (l, s) = (&linear[offset..offset + 3], &pixels[offset..offset + 3]);
+            if colors.iter().all(|&c| c == colors[0]) {
+                assert_eq!(l, colors[0], "({}, {})", x, y);
+                assert_eq!(s, colors[0], "({}, {})", x, y);
+            } else if colors.contains(&&[0, 0, 0][..]) {
+                assert!(l.iter().zip(s).all(|(l, s)| l >= s), "({}, {}): {:?} {:?}", x, y, l, s);
+            }
+        }
+    }
+    assert!(linear != pixels);
+}
+
+#[test]
+fn test_supersample_one_is_identity() {
+    let bounds = (40, 30);
+    let pixels = compute_iterations(bounds, &Region::CLASSIC, 200).colorize(&Palette::Classic);
+
+    assert_eq!(downsample(&pixels, bounds, 1, GammaSpace::Linear), pixels);
+    assert_eq!(downsample(&pixels, bounds, 1, GammaSpace::Srgb), pixels);
+}
+
+/// 大きさ `bounds` の RGB バッファから `area` の長方形を切り出す（描き直さずに余白を落とす）
+///
+/// `area` が画像からはみ出していたり、`pixels` の長さが `bounds` と合わなかったりすればエラーを返す。
+pub fn crop(pixels: &[u8], bounds: (usize, usize), area: &Tile) -> Result<Vec<u8>, RenderError> {
+    let expected = bounds.0 * bounds.1 * 3;
+    if pixels.len() != expected {
+        return Err(RenderError::BufferMismatch { expected, actual: pixels.len() });
+    }
+    if !area.fits(bounds) {
+        return Err(format!("crop {}x{} at {},{} does not fit in the {}x{} image",
+                           area.size.0, area.size.1, area.offset.0, area.offset.1, bounds.0, bounds.1).into());
+    }
+
+    let mut cropped = Vec::with_capacity(area.size.0 * area.size.1 * 3);
+    for row in pixels.chunks(bounds.0 * 3).skip(area.offset.1).take(area.size.1) {
+        cropped.extend_from_slice(&row[area.offset.0 * 3..(area.offset.0 + area.size.0) * 3]);
+    }
+    Ok(cropped)
+}
+
+#[test]
+fn test_crop() {
+    // ピクセル (x, y) の色が [x, y, 7] の 5×4 の画像
+    let bounds = (5, 4);
+    let pixels: Vec<u8> = (0..bounds.1)
+        .flat_map(|y| (0..bounds.0).flat_map(move |x| [x as u8, y as u8, 7]))
+        .collect();
+
+    let area = Tile { size: (2, 3), offset: (3, 1) };
+    assert_eq!(crop(&pixels, bounds, &area).unwrap(),
+               [[3, 1, 7], [4, 1, 7], [3, 2, 7], [4, 2, 7], [3, 3, 7], [4, 3, 7]].concat());
+
+    // 画像全体なら何も変わらない
+    assert_eq!(crop(&pixels, bounds, &Tile::full(bounds)).unwrap(), pixels);
+
+    // はみ出す範囲・長さの合わないバッファはエラー
+    assert!(matches!(crop(&pixels, bounds, &Tile { size: (3, 1), offset: (3, 0) }),
+                     Err(RenderError::InvalidArgument(_))));
+    assert!(matches!(crop(&pixels, bounds, &Tile { size: (1, 5), offset: (0, 0) }),
+                     Err(RenderError::InvalidArgument(_))));
+    assert!(matches!(crop(&pixels[3..], bounds, &area), Err(RenderError::BufferMismatch { .. })));
+}
+
+/// RGB の各チャンネルにガンマ補正をかける（v ← 255·(v/255)^(1/`gamma`)、0..255 に収める）
+///
+/// `color_map` などのグラデーションは明るさに比例した値なので、そのまま sRGB として表示すると中間の色が暗く見える。
+/// 一般的なディスプレイなら `gamma` は 2.2 くらいで、1.0 なら何も変わらない。
+pub fn apply_gamma(pixels: &mut [u8], gamma: f64) {
+    // 256 通りしかないので、先に表を作っておく
+    let table: Vec<u8> = (0..=255u8)
+        .map(|v| (255.0 * (v as f64 / 255.0).powf(1.0 / gamma)).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    for v in pixels {
+        *v = table[*v as usize];
+    }
+}
+
+#[test]
+fn test_apply_gamma() {
+    let pixels: Vec<u8> = (0..=255).collect();
+    let mut unchanged = pixels.clone();
+    apply_gamma(&mut unchanged, 1.0);
+    assert_eq!(unchanged, pixels);
+
+    // 中間の灰色は明るくなり、黒と白はそのまま
+    let mut gray = [0, 128, 255];
+    apply_gamma(&mut gray, 2.2);
+    assert_eq!(gray, [0, 186, 255]);
+}
+
+/// 2 枚の RGB 画像の違い（`diff_pixels`）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelDiff {
+    /// どれかのチャンネルの差が許容範囲を超えたピクセルの数
+    pub differing: usize,
+    /// 全ピクセル・全チャンネルの中でいちばん大きい差（許容範囲内の差も含む）
+    pub max_delta: u8,
+}
+
+/// 同じ大きさの RGB バッファ `actual` と `expected` をピクセルごとに比べる（`--compare`）
+///
+/// R・G・B のどれかの差が `tolerance` を超えたピクセルを違うものとして数える。
+/// 長さが違えば比べずにエラーを返す。
+pub fn diff_pixels(actual: &[u8], expected: &[u8], tolerance: u8) -> Result<PixelDiff, RenderError> {
+    if actual.len() != expected.len() {
+        return Err(RenderError::BufferMismatch { expected: expected.len(), actual: actual.len() });
+    }
+
+    let mut diff = PixelDiff { differing: 0, max_delta: 0 };
+    for (a, e) in actual.chunks_exact(3).zip(expected.chunks_exact(3)) {
+        let delta = a.iter().zip(e).map(|(&a, &e)| a.abs_diff(e)).max().unwrap_or(0);
+        diff.max_delta = diff.max_delta.max(delta);
+        if delta > tolerance {
+            diff.differing += 1;
+        }
+    }
+    Ok(diff)
+}
+
+#[test]
+fn test_diff_pixels() {
+    let pixels = compute_iterations((40, 30), &Region::CLASSIC, 200).colorize(&Palette::Classic);
+    assert_eq!(diff_pixels(&pixels, &pixels, 0).unwrap(), PixelDiff { differing: 0, max_delta: 0 });
+
+    // 1 ピクセルの 1 チャンネルだけ変える
+    let mut modified = pixels.clone();
+    modified[5 * 3 + 1] = modified[5 * 3 + 1].wrapping_add(9);
+    assert_eq!(diff_pixels(&modified, &pixels, 0).unwrap(), PixelDiff { differing: 1, max_delta: 9 });
+    // 許容範囲内なら違うものとは数えないが、差の最大は変わらない
+    assert_eq!(diff_pixels(&modified, &pixels, 9).unwrap(), PixelDiff { differing: 0, max_delta: 9 });
+
+    assert!(matches!(diff_pixels(&pixels[3..], &pixels, 0), Err(RenderError::BufferMismatch { .. })));
+}
+
+/// 範囲 `region` を `width`×`height` の RGBA バッファに描く（1 ピクセル 4 バイト、アルファは 255）
+///
+/// ファイルを使わないので、WebAssembly からも呼べる。結果は canvas の `putImageData` にそのまま渡せる。
+/// 色は `Palette::Classic`、内部は黒。
+pub fn render_to_rgba(width: usize, height: usize, region: &Region, max_iter: u32) -> Vec<u8> {
+    let rgb = compute_iterations((width, height), region, max_iter).colorize(&Palette::Classic);
+    rgb.chunks_exact(3).flat_map(|color| [color[0], color[1], color[2], 255]).collect()
+}
+
+#[test]
+fn test_render_to_rgba() {
+    let (width, height) = (40, 30);
+    let rgba = render_to_rgba(width, height, &Region::CLASSIC, 200);
+    assert_eq!(rgba.len(), width * height * 4);
+    assert!(rgba.chunks_exact(4).all(|pixel| pixel[3] == 255));
+
+    // 色は RGB で描いたものと同じ
+    let rgb = compute_iterations((width, height), &Region::CLASSIC, 200).colorize(&Palette::Classic);
+    let stripped: Vec<u8> = rgba.chunks_exact(4).flat_map(|pixel| pixel[..3].to_vec()).collect();
+    assert_eq!(stripped, rgb);
+}
@@ -1,80 +1,2980 @@
-use image::{ImageBuffer, Rgb};
+use mandelbrot_color::*;
 use num_complex::Complex;
-use std::time::Instant;
+use num_traits::Float;
+use serde::Deserialize;
+use std::sync::atomic::AtomicBool;
 
-fn main() {
-    let start = Instant::now(); // ★ 計測開始
+/// 色付けの方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorMode {
+    /// 反復回数 / max_iter をそのままパレットに渡す
+    Linear,
+    /// ヒストグラム平坦化（累積順位でパレットを割り当てる）
+    Histogram,
+    /// 軌道が原点（トラップ）に最も近づいた距離で色を付ける
+    OrbitTrap,
+    /// 集合の境界までの推定距離で濃淡を付ける
+    Distance,
+    /// 連続ポテンシャルの等電位線で色を付ける
+    Potential,
+    /// 滑らかな反復回数 / max_iter をパレットに渡す（linear の帯の境目がなくなる）
+    Smooth,
+    /// 反復回数を高さとみなして光を当て、linear の色に陰影を付ける（`--light`）
+    Shaded,
+}
+
+/// `--color-mode` の値を解釈する
+fn parse_color_mode(s: &str) -> Option<ColorMode> {
+    match s {
+        "linear" => Some(ColorMode::Linear),
+        "histogram" => Some(ColorMode::Histogram),
+        "orbit-trap" => Some(ColorMode::OrbitTrap),
+        "distance" => Some(ColorMode::Distance),
+        "potential" => Some(ColorMode::Potential),
+        "smooth" => Some(ColorMode::Smooth),
+        "shaded" => Some(ColorMode::Shaded),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_color_mode() {
+    assert_eq!(parse_color_mode("linear"), Some(ColorMode::Linear));
+    assert_eq!(parse_color_mode("histogram"), Some(ColorMode::Histogram));
+    assert_eq!(parse_color_mode("orbit-trap"), Some(ColorMode::OrbitTrap));
+    assert_eq!(parse_color_mode("distance"), Some(ColorMode::Distance));
+    assert_eq!(parse_color_mode("potential"), Some(ColorMode::Potential));
+    assert_eq!(parse_color_mode("smooth"), Some(ColorMode::Smooth));
+    assert_eq!(parse_color_mode("shaded"), Some(ColorMode::Shaded));
+    assert_eq!(parse_color_mode("rainbow"), None);
+}
+
+/// 計算に使う浮動小数点数の型
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Precision {
+    /// 速いが、ズームするとすぐに精度が足りなくなる
+    F32,
+    /// 従来どおり
+    F64,
+    /// 倍々精度（遅いが、f64 では潰れる深い拡大でも描ける）
+    #[cfg(feature = "high-precision")]
+    High,
+}
+
+/// `--precision` の値を解釈する
+fn parse_precision(s: &str) -> Option<Precision> {
+    match s {
+        "f32" => Some(Precision::F32),
+        "f64" => Some(Precision::F64),
+        #[cfg(feature = "high-precision")]
+        "high" => Some(Precision::High),
+        _ => None,
+    }
+}
+
+/// `--animate-julia START END FRAMES OUTDIR` の指定
+#[derive(Clone, Debug, PartialEq)]
+struct JuliaAnimation {
+    start: Complex<f64>,
+    end: Complex<f64>,
+    frames: usize,
+    outdir: String,
+}
+
+impl JuliaAnimation {
+    /// `index` 番目のフレームの定数 c（START から END まで実部・虚部を線形補間）
+    fn constant(&self, index: usize) -> Complex<f64> {
+        let t = if self.frames <= 1 { 0.0 } else { index as f64 / (self.frames - 1) as f64 };
+        // start + (end - start) * t だと t = 1 で END に一致しないことがある
+        self.start * (1.0 - t) + self.end * t
+    }
+}
+
+/// `index` 番目のフレームのファイル名（ffmpeg で読めるよう 4 桁にゼロ埋め）
+fn frame_filename(outdir: &str, index: usize) -> String {
+    std::path::Path::new(outdir)
+        .join(format!("frame_{:04}.png", index))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn test_julia_animation() {
+    let animation = JuliaAnimation {
+        start: Complex::new(-0.8, 0.156),
+        end: Complex::new(0.285, 0.01),
+        frames: 30,
+        outdir: "frames".to_string(),
+    };
+    assert_eq!(animation.constant(0), animation.start);
+    assert_eq!(animation.constant(29), animation.end);
+    let middle = animation.constant(1);
+    assert!(middle.re > animation.start.re && middle.re < animation.end.re);
+    assert!(middle.im < animation.start.im && middle.im > animation.end.im);
+
+    assert_eq!(frame_filename("frames", 0), format!("frames{}frame_0000.png", std::path::MAIN_SEPARATOR));
+    assert_eq!(frame_filename("frames", 7), format!("frames{}frame_0007.png", std::path::MAIN_SEPARATOR));
+    assert_eq!(frame_filename("frames", 123), format!("frames{}frame_0123.png", std::path::MAIN_SEPARATOR));
+}
+
+/// `--dive CENTER FRAMES FACTOR OUTDIR` の指定
+#[derive(Clone, Debug, PartialEq)]
+struct Dive {
+    center: Complex<f64>,
+    frames: usize,
+    /// 1 フレームごとの倍率
+    factor: f64,
+    outdir: String,
+}
+
+impl Dive {
+    /// `index` 番目のフレームの倍率（最初のフレームが `start_zoom`）
+    fn zoom(&self, start_zoom: f64, index: usize) -> f64 {
+        start_zoom * self.factor.powi(index as i32)
+    }
+}
+
+/// `--mandelbrot-movie-spec` のキーフレーム 1 つ（時刻 `time` のカメラと色）
+#[derive(Clone, Debug, PartialEq)]
+struct Keyframe {
+    time: f64,
+    center: Complex<f64>,
+    zoom: f64,
+    max_iter: u32,
+    palette: Palette,
+}
+
+/// `--mandelbrot-movie-spec SPEC FRAMES OUTDIR` の指定（`--dive` の直線の代わりにキーフレームの間を動く）
+#[derive(Clone, Debug, PartialEq)]
+struct Movie {
+    /// 時刻の順に並んだキーフレーム（1 つ以上）
+    keyframes: Vec<Keyframe>,
+    frames: usize,
+    outdir: String,
+}
+
+impl Movie {
+    /// `index` 番目のフレームのカメラと色（最初のキーフレームから最後のキーフレームまでの時刻を等分する）
+    fn frame(&self, index: usize) -> Keyframe {
+        let (first, last) = (self.keyframes[0].time, self.keyframes[self.keyframes.len() - 1].time);
+        let t = if self.frames <= 1 { 0.0 } else { index as f64 / (self.frames - 1) as f64 };
+        camera_at(&self.keyframes, first * (1.0 - t) + last * t)
+    }
+}
+
+/// 0.0..=1.0 の進み具合 `t` を、始めと終わりがゆっくりになるように曲げる（smoothstep）
+fn ease_in_out(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// 時刻 `time` のカメラと色（前後のキーフレームの間を `ease_in_out` で補間する）
+///
+/// 中心は複素平面上で、倍率は対数で補間するので、一定の速さで潜っていくように見える。
+/// 反復回数の上限は線形に補間し、補間できないパレットは前のキーフレームのものを使う。
+/// 最初のキーフレームより前・最後のキーフレームより後は、端のキーフレームのまま動かない。
+fn camera_at(keyframes: &[Keyframe], time: f64) -> Keyframe {
+    let (from, to) = match keyframes.iter().position(|keyframe| keyframe.time >= time) {
+        Some(next) if keyframes[next].time == time => return keyframes[next].clone(),
+        Some(0) => return Keyframe { time, ..keyframes[0].clone() },
+        Some(next) => (&keyframes[next - 1], &keyframes[next]),
+        None => return Keyframe { time, ..keyframes[keyframes.len() - 1].clone() },
+    };
+
+    let s = ease_in_out((time - from.time) / (to.time - from.time));
+    // from + (to - from) * s だと s = 1 で to に一致しないことがある
+    let lerp = |from: f64, to: f64| from * (1.0 - s) + to * s;
+    Keyframe {
+        time,
+        center: from.center * (1.0 - s) + to.center * s,
+        zoom: lerp(from.zoom.ln(), to.zoom.ln()).exp(),
+        max_iter: lerp(from.max_iter as f64, to.max_iter as f64).round() as u32,
+        palette: from.palette.clone(),
+    }
+}
+
+/// `--mandelbrot-movie-spec` のキーフレームの一覧を解釈する
+///
+/// 1 行に `TIME CENTER ZOOM MAX_ITER PALETTE`（例: `2.5 -0.75,0.1 40 500 hsv`）を書く。
+/// 空行と `#` で始まる行は飛ばす。時刻は行の順に増えていなければならない。
+/// 1 行でも誤りがあれば、1 枚も描かずに行番号つきのエラーにする。
+fn parse_movie_spec(text: &str) -> Result<Vec<Keyframe>, RenderError> {
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| RenderError::from(format!("line {}: {}", index + 1, message));
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let &[time, center, zoom, max_iter, palette] = fields.as_slice() else {
+            return Err(error(format!("expected TIME CENTER ZOOM MAX_ITER PALETTE: {}", line)));
+        };
+        let keyframe = Keyframe {
+            time: match time.parse::<f64>() {
+                Ok(t) if t.is_finite() => t,
+                _ => return Err(error(format!("invalid time: {}", time))),
+            },
+            center: parse_complex(center).ok_or_else(|| error(format!("invalid center point: {}", center)))?,
+            zoom: match zoom.parse::<f64>() {
+                Ok(z) if z > 0.0 && z.is_finite() => z,
+                _ => return Err(error(format!("invalid zoom: {}", zoom))),
+            },
+            max_iter: match max_iter.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(error(format!("invalid iteration count: {}", max_iter))),
+            },
+            palette: parse_palette(palette).ok_or_else(|| error(format!("unknown palette: {}", palette)))?,
+        };
+        if keyframes.last().is_some_and(|last| keyframe.time <= last.time) {
+            return Err(error(format!("keyframe times must increase: {}", time)));
+        }
+        keyframes.push(keyframe);
+    }
+
+    if keyframes.is_empty() {
+        return Err("movie spec has no keyframes".into());
+    }
+    Ok(keyframes)
+}
+
+#[test]
+fn test_movie_spec() {
+    let spec = "# 全体から拡大して戻る
+0 -0.5,0 1 100 classic
+
+4 -0.75,0.1 10000 900 hsv
+6 -0.75,0.1 100 500 classic
+";
+    let keyframes = parse_movie_spec(spec).unwrap();
+    assert_eq!(keyframes.len(), 3);
+    assert_eq!(keyframes[1], Keyframe {
+        time: 4.0,
+        center: Complex::new(-0.75, 0.1),
+        zoom: 10000.0,
+        max_iter: 900,
+        palette: Palette::Hsv { cycles: 1.0 },
+    });
+
+    // 2 つのキーフレームの真ん中では、中心は中点、倍率は相乗平均
+    let middle = camera_at(&keyframes, 2.0);
+    assert_eq!(middle.center, Complex::new(-0.625, 0.05));
+    assert!((middle.zoom - (1.0f64 * 10000.0).sqrt()).abs() < 1e-9, "{}", middle.zoom);
+    assert_eq!(middle.max_iter, 500);
+    assert_eq!(middle.palette, Palette::Classic);
+
+    // キーフレームの時刻ではそのまま、前後は端で止まる、始めと終わりはゆっくり
+    assert_eq!(camera_at(&keyframes, 4.0), keyframes[1]);
+    assert_eq!(camera_at(&keyframes, -1.0), Keyframe { time: -1.0, ..keyframes[0].clone() });
+    assert_eq!(camera_at(&keyframes, 9.0), Keyframe { time: 9.0, ..keyframes[2].clone() });
+    assert!(camera_at(&keyframes, 0.4).zoom < 10000f64.powf(0.1));
+    assert!(camera_at(&keyframes, 3.6).zoom > 10000f64.powf(0.9));
+
+    // フレームは最初から最後のキーフレームまでを等分する
+    let movie = Movie { keyframes: keyframes.clone(), frames: 7, outdir: "movie".to_string() };
+    assert_eq!(movie.frame(0), keyframes[0]);
+    assert_eq!(movie.frame(3), camera_at(&keyframes, 3.0));
+    assert_eq!(movie.frame(6), keyframes[2]);
+
+    assert!(parse_movie_spec("").is_err());
+    assert!(parse_movie_spec("0 -0.5,0 1 100").is_err());
+    assert!(parse_movie_spec("0 -0.5,0 0 100 classic").is_err());
+    assert!(parse_movie_spec("0 -0.5,0 1 100 fire").is_err());
+    let err = parse_movie_spec("0 -0.5,0 1 100 classic\n0 -0.5,0 2 100 classic").unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
+
+/// 倍率に応じて反復回数の上限を増やす（深く潜るほど細部が消えないように）
+///
+/// zoom が 2 倍になるごとに 50 回ずつ増える。zoom <= 1 では `base` のまま。
+fn iterations_for_zoom(base: u32, zoom: f64) -> u32 {
+    base + (50.0 * zoom.log2().max(0.0)) as u32
+}
+
+/// `--batch` のマニフェストの 1 行（1 枚分の出力ファイル名・大きさ・範囲）
+#[derive(Debug, PartialEq)]
+struct RenderJob {
+    output: String,
+    bounds: (usize, usize),
+    region: Region,
+}
+
+/// `--batch` のマニフェストを解釈する
+///
+/// 1 行に `OUTFILE BOUNDS UPPERLEFT LOWERRIGHT`（例: `a.png 800x600 -1.20,0.35 -1,0.20`）を書く。
+/// 空行と `#` で始まる行は飛ばす。書き方の誤った行はその行だけエラーにするので、
+/// 結果は (行番号, 解釈した結果) を並べたものになる。
+fn parse_manifest(text: &str) -> Vec<(usize, Result<RenderJob, RenderError>)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, parse_render_job(line)))
+        .collect()
+}
+
+/// マニフェストの 1 行を解釈する
+fn parse_render_job(line: &str) -> Result<RenderJob, RenderError> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let &[output, bounds, upper_left, lower_right] = fields.as_slice() else {
+        return Err(format!("expected OUTFILE BOUNDS UPPERLEFT LOWERRIGHT: {}", line).into());
+    };
+    let bounds = match parse_pair(bounds, 'x') {
+        Some((width, height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(RenderError::ParseBounds(bounds.to_string())),
+    };
+    let corner = |s: &str| {
+        parse_complex(s).ok_or_else(|| RenderError::ParseComplex { what: "corner point", text: s.to_string() })
+    };
+    let region = Region::from_corners(corner(upper_left)?, corner(lower_right)?)?;
+    Ok(RenderJob { output: output.to_string(), bounds, region })
+}
+
+#[test]
+fn test_parse_manifest() {
+    let manifest = "# 全体と拡大
+overview.png 1200x800 -2.2,1.2 1,-1.2
+
+seahorse.png 640x480 -0.8,0.15 -0.7,0.075
+broken.png 640x480 -0.7,0.15
+flipped.png 640x480 -0.7,0.15 -0.8,0.075
+";
+    let jobs = parse_manifest(manifest);
+    assert_eq!(jobs.iter().map(|(number, _)| *number).collect::<Vec<_>>(), [2, 4, 5, 6]);
+
+    let ok: Vec<&RenderJob> = jobs.iter().filter_map(|(_, job)| job.as_ref().ok()).collect();
+    assert_eq!(ok, [
+        &RenderJob { output: "overview.png".to_string(), bounds: (1200, 800), region: Region::CLASSIC },
+        &RenderJob {
+            output: "seahorse.png".to_string(),
+            bounds: (640, 480),
+            region: Region::from_corners(Complex::new(-0.8, 0.15), Complex::new(-0.7, 0.075)).unwrap(),
+        },
+    ]);
+    assert!(jobs[2].1.is_err() && jobs[3].1.is_err());
+    assert!(matches!(parse_render_job("a.png 640,480 -1,1 1,-1"), Err(RenderError::ParseBounds(_))));
+    assert!(matches!(parse_render_job("a.png 640x480 -1,1 x"),
+                     Err(RenderError::ParseComplex { what: "corner point", .. })));
+}
+
+/// `--estimate` で計算する点の間隔（縦横このピクセル数おき）
+const ESTIMATE_STRIDE: usize = 16;
+
+/// `--auto-iter` で使う反復回数の上限の上限（深く潜っても 1 枚の描画が終わらなくならないように）
+const AUTO_ITER_CEILING: u32 = 20_000;
+
+/// `--auto-iter` の反復回数の上限（`iterations_for_zoom` を `AUTO_ITER_CEILING` で頭打ちにしたもの）
+fn auto_iterations(base: u32, zoom: f64) -> u32 {
+    iterations_for_zoom(base, zoom).min(AUTO_ITER_CEILING)
+}
+
+/// 表示範囲の倍率（`Region::from_center_zoom` の逆。幅が倍率 1 の範囲の何分の 1 か）
+fn region_zoom(bounds: (usize, usize), region: &Region) -> f64 {
+    Region::from_center_zoom(bounds, region.center(), 1.0).width() / region.width()
+}
+
+#[test]
+fn test_auto_iterations() {
+    let zooms = [0.5, 1.0, 2.0, 10.0, 1e3, 1e6, 1e12];
+    let limits: Vec<u32> = zooms.iter().map(|&zoom| auto_iterations(200, zoom)).collect();
+    assert_eq!(limits[0], 200);
+    assert!(limits.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", limits);
+    assert!(limits[5] > limits[3]);
+
+    // 倍率がどれだけ大きくても上限で止まる
+    assert_eq!(auto_iterations(200, 1e300), AUTO_ITER_CEILING);
+    assert_eq!(auto_iterations(AUTO_ITER_CEILING + 1, 1.0), AUTO_ITER_CEILING);
+
+    let region = Region::from_center_zoom((1200, 800), Complex::new(-0.75, 0.1), 40.0);
+    assert!((region_zoom((1200, 800), &region) - 40.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_dive() {
+    let dive = Dive {
+        center: Complex::new(-0.743643887037151, 0.131825904205330),
+        frames: 10,
+        factor: 1.5,
+        outdir: "dive".to_string(),
+    };
     let bounds = (1200, 800);
-    let upper_left = Complex::new(-2.2, 1.2);
-    let lower_right = Complex::new(1.0, -1.2);
-    let max_iter = 200;
+    let width = |index| {
+        Region::from_center_zoom(bounds, dive.center, dive.zoom(1.0, index)).width()
+    };
+
+    for index in 0..dive.frames - 1 {
+        let ratio = width(index) / width(index + 1);
+        assert!((ratio - dive.factor).abs() < 1e-9, "frame {}: ratio {}", index, ratio);
+    }
+
+    assert_eq!(iterations_for_zoom(200, 1.0), 200);
+    assert_eq!(iterations_for_zoom(200, 0.5), 200);
+    assert_eq!(iterations_for_zoom(200, 4.0), 300);
+    assert!(iterations_for_zoom(200, dive.zoom(1.0, 9)) > iterations_for_zoom(200, dive.zoom(1.0, 8)));
+}
+
+#[test]
+fn test_library_complex_type() {
+    // ライブラリの関数が返す Complex は、main.rs の num_complex::Complex と同じ型
+    let center: num_complex::Complex<f64> = Region::CLASSIC.center();
+    let dive = Dive { center, frames: 2, factor: 2.0, outdir: "dive".to_string() };
+    let region = Region::from_center_zoom((1200, 800), dive.center, dive.zoom(1.0, 1));
+    assert!((region.center() - center).norm() < 1e-12);
+
+    let start = parse_complex("-0.8,0.156").unwrap();
+    let animation = JuliaAnimation { start, end: start, frames: 1, outdir: "frames".to_string() };
+    assert_eq!(Fractal::Julia(animation.constant(0)), Fractal::Julia(Complex::new(-0.8, 0.156)));
+}
+
+/// コマンドライン引数
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    /// `--size`（出力画像の幅と高さ）
+    bounds: (usize, usize),
+    color_mode: ColorMode,
+    /// 1 ピクセルあたり supersample × supersample 点をサンプリングする
+    supersample: usize,
+    /// `--color-gamma-space`（スーパーサンプリング・ジッター AA の色をどの空間で平均するか）
+    gamma_space: GammaSpace,
+    /// `--center`（指定があれば中心 + 倍率で表示範囲を決める）
+    center: Option<Complex<f64>>,
+    /// `--zoom`
+    zoom: Option<f64>,
+    /// `--region`（左上・右下の角で表示範囲を直接指定する）
+    region: Option<Region>,
+    /// `--keep-aspect`（表示範囲の縦横比を画像に合わせる）
+    keep_aspect: bool,
+    /// `--progress`（描画中の進捗を標準エラーに出す）
+    progress: bool,
+    /// `--animate-julia`
+    animate_julia: Option<JuliaAnimation>,
+    /// `--dive`
+    dive: Option<Dive>,
+    /// `--mandelbrot-movie-spec`
+    movie: Option<Movie>,
+    /// `--precision`
+    precision: Precision,
+    /// `--simd`（4 点ずつまとめて計算する）
+    simd: bool,
+    /// `--palette`（`--cycles` は虹色の周回数）
+    palette: Palette,
+    /// `--palette-image`（参照画像のファイル名。描く前に `resolve_palette` で読み込んで `palette` にする）
+    palette_image: Option<String>,
+    /// `--repeat-palette`（パレットを繰り返す回数。描く前に `resolve_palette` で `palette` に畳み込む）
+    repeat_palette: u32,
+    /// `--light`（shaded の光源の向き）
+    light: Light,
+    /// `--tile`（画像全体のうちこの部分だけを描いて書き出す）
+    tile: Option<Tile>,
+    /// `--max-iter`（反復回数の上限。`--dive` では最初のフレームの上限）
+    max_iter: u32,
+    /// `--output`（1 枚だけ描くときの出力ファイル名）
+    output: String,
+    /// `--interior`（集合の内部の色。linear のみ）
+    interior: InteriorColor,
+    /// `--fractal`（`Mandelbrot` か `BurningShip`。ジュリア集合は `--animate-julia` で描く）
+    ///
+    /// `--power` が 2 以外なら `Multibrot` になる。
+    fractal: Fractal,
+    /// `--bailout`（発散半径。指定がなければ色付けの方式に合わせて決める）
+    bailout: Option<f64>,
+    /// `--dump-iterations`（画像の代わりに反復回数をこのファイルに書き出す）
+    dump_iterations: Option<String>,
+    /// `--jitter`（1 ピクセルあたりのランダムなサンプル数）
+    jitter: Option<usize>,
+    /// `--seed`（ジッターの位置や `--estimate-area` の点を決める乱数の種）
+    seed: u64,
+    /// `--auto-iter`（反復回数の上限を倍率から決める。`--max-iter` を指定したときは使わない）
+    auto_iter: bool,
+    /// `--output-bits`（8 なら RGB、16 なら反復回数を 16 ビットのグレースケールで書き出す）
+    output_bits: u32,
+    /// `--estimate`（描画せずにかかる時間を見積もって表示する）
+    estimate: bool,
+    /// `--find-detail`（描画せずに、粗く描いた中でいちばん入り組んだ場所の中心を表示する）
+    find_detail: bool,
+    /// `--stats`（描画した後に反復回数の統計を標準エラーに表示する）
+    stats: bool,
+    /// `--pattern`（フラクタルの代わりにテストパターンを描く）
+    pattern: Option<Pattern>,
+    /// `--svg`（画像の代わりに集合の境界を SVG でこのファイルに書き出す）
+    svg: Option<String>,
+    /// `--stream`（`STREAM_BAND_ROWS` 行ずつ描いては PNG に書き足す）
+    stream: bool,
+    /// `--batch`（このファイルに並べた範囲を 1 枚ずつ描く）
+    batch: Option<String>,
+    /// `--gamma`（書き出す前に RGB にかけるガンマ補正。指定しなければ `DEFAULT_GAMMA`）
+    gamma: Option<f64>,
+    /// `--legend`（画像の右にパレットと反復回数の目盛りの凡例を足す）
+    legend: bool,
+    /// `--transparent-interior`（RGBA で書き出し、集合の内部を透明にする）
+    transparent_interior: bool,
+    /// `--verify`（描画せずに `REFERENCE_POINTS` の反復回数を確かめる）
+    verify: bool,
+    /// `--sample-point`（描画せずに、この点から始まる `fractal` の軌道を表示する）
+    sample_point: Option<Complex<f64>>,
+    /// `--fuzz-check SEED`（開発用。描画せずに、ランダムな条件で小さな画像を描いてパニックしないか確かめる）
+    fuzz_check: Option<u64>,
+    /// `--estimate-area SAMPLES`（描画せずに、この数の点をばらまいて集合の面積を推定する）
+    estimate_area: Option<usize>,
+    /// `--compare`（書き出す代わりに、描いた画像をこの基準画像と比べる）
+    compare: Option<String>,
+    /// `--tolerance`（`--compare` で同じとみなすチャンネルの差の上限）
+    tolerance: u8,
+    /// `--dither`（histogram・potential・smooth の色を u8 に丸めるときに組織的ディザをかける）
+    dither: bool,
+    /// `--crop`（描いた画像からこの長方形だけを切り出して書き出す）
+    crop: Option<Tile>,
+    /// `--preview`（先に 1/`PREVIEW_FACTOR` の大きさで描いて書き出す）
+    preview: bool,
+    /// `--exploit-symmetry`（範囲が実軸に対して対称なら上半分だけ計算して下半分に写す）
+    exploit_symmetry: bool,
+    /// `--format exr`（smooth・potential の色を 8 ビットに丸めずに 32 ビット浮動小数点の OpenEXR で書く）
+    exr: bool,
+    /// `--tonemap`（smooth・potential の色に明るさを掛けてから 8 ビットに縮める）
+    tone_map: Option<ToneMap>,
+    /// `--exposure`（`--tonemap` で集合に近い点を明るくする度合い。`hdr_brightness` に渡す）
+    exposure: f32,
+    /// `--max-time`（締め切りを過ぎたら残りの帯を描かずに灰色で埋める）
+    max_time: Option<std::time::Duration>,
+    /// `--auto-band`（試しに描いて、並列に描く帯の高さを `band_rows` に選ぶ）
+    auto_band: bool,
+    /// linear で 1 つの仕事として並列に描く行数（`--auto-band` のときは描く前に選び直す）
+    band_rows: usize,
+    /// `--verbose`（選んだ帯の高さなど、描画の途中で決めた値を標準エラーに表示する）
+    verbose: bool,
+}
+
+impl Options {
+    /// 発散半径の 2 乗（distance では `DISTANCE_BAILOUT`、potential・smooth では `POTENTIAL_BAILOUT`、
+    /// それ以外では `DEFAULT_BAILOUT` が既定）
+    fn bailout_sqr(&self) -> f64 {
+        let bailout = self.bailout.unwrap_or(match self.color_mode {
+            ColorMode::Distance => DISTANCE_BAILOUT,
+            ColorMode::Potential | ColorMode::Smooth => POTENTIAL_BAILOUT,
+            _ => DEFAULT_BAILOUT,
+        });
+        bailout * bailout
+    }
+
+    /// RGB にかけるガンマ補正（既定は `DEFAULT_GAMMA`）
+    fn gamma(&self) -> f64 {
+        self.gamma.unwrap_or(DEFAULT_GAMMA)
+    }
+}
+
+/// `--gamma` を指定しないときのガンマ補正
+///
+/// パレットの色は線形に補間しているので、そのまま sRGB として表示すると中間色が暗く見える。
+/// 補正は RGB で書き出す画像にだけかけ、16 ビット・SVG・EXR などの出力はそのまま書く。
+const DEFAULT_GAMMA: f64 = 2.2;
+
+/// `--config` で読み込む設定ファイル（TOML）
+///
+/// どの項目も省略できる。読み込んだ値は同じ意味のコマンドライン引数に直して実際の引数の前に並べるので、
+/// 値の検査は引数と同じになり、引数でも指定すればそちらが優先される。
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderConfig {
+    /// `--size`（[幅, 高さ]）
+    bounds: Option<(usize, usize)>,
+    /// `--center`（[実部, 虚部]）
+    center: Option<(f64, f64)>,
+    zoom: Option<f64>,
+    max_iter: Option<u32>,
+    palette: Option<String>,
+    supersample: Option<usize>,
+    output: Option<String>,
+}
+
+impl RenderConfig {
+    fn load(path: &str) -> Result<RenderConfig, RenderError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("error reading {}: {}", path, err))?;
+        toml::from_str(&text).map_err(|err| format!("error parsing {}: {}", path, err).into())
+    }
+
+    /// 同じ意味のコマンドライン引数
+    fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |flag: &str, value: Option<String>| {
+            if let Some(value) = value {
+                args.extend([flag.to_string(), value]);
+            }
+        };
+        push("--size", self.bounds.map(|(width, height)| format!("{}x{}", width, height)));
+        push("--center", self.center.map(|(re, im)| format!("{},{}", re, im)));
+        push("--zoom", self.zoom.map(|zoom| zoom.to_string()));
+        push("--max-iter", self.max_iter.map(|n| n.to_string()));
+        push("--palette", self.palette.clone());
+        push("--supersample", self.supersample.map(|n| n.to_string()));
+        push("--output", self.output.clone());
+        args
+    }
+}
+
+/// `--config FILE` を取り除き、設定ファイルの値を引数の先頭（プログラム名の直後）に展開する
+fn expand_config(args: &[String]) -> Result<Vec<String>, RenderError> {
+    let mut config_args = Vec::new();
+    let mut rest = Vec::new();
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            let path = iter.next().ok_or("--config requires a value")?;
+            config_args.extend(RenderConfig::load(path)?.to_args());
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+
+    Ok(args.iter().take(1).cloned().chain(config_args).chain(rest).collect())
+}
+
+#[cfg(test)]
+const SAMPLE_CONFIG: &str = r#"
+# 海馬の谷
+bounds = [1920, 1080]
+center = [-0.75, 0.1]
+zoom = 40.0
+max_iter = 1000
+palette = "hsv"
+supersample = 2
+output = "seahorse.png"
+"#;
+
+#[test]
+fn test_render_config() {
+    let config: RenderConfig = toml::from_str(SAMPLE_CONFIG).unwrap();
+    assert_eq!(config, RenderConfig {
+        bounds: Some((1920, 1080)),
+        center: Some((-0.75, 0.1)),
+        zoom: Some(40.0),
+        max_iter: Some(1000),
+        palette: Some("hsv".to_string()),
+        supersample: Some(2),
+        output: Some("seahorse.png".to_string()),
+    });
+
+    assert_eq!(toml::from_str::<RenderConfig>("").unwrap(), RenderConfig::default());
+    // 綴りを間違えた項目を黙って無視しない
+    assert!(toml::from_str::<RenderConfig>("max_iters = 1000").is_err());
+}
+
+#[test]
+fn test_config_overridden_by_args() {
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_config_{}.toml", std::process::id()));
+    std::fs::write(&path, SAMPLE_CONFIG).unwrap();
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let config = path.to_str().unwrap();
+
+    let from_file = parse_args(&args(&["color", "--config", config]));
+    // 引数は --config の前に書いても後に書いても設定ファイルより優先される
+    let overridden = parse_args(&args(&["color", "--max-iter", "300", "--config", config, "--zoom", "8"]));
+    std::fs::remove_file(&path).unwrap();
+
+    let from_file = from_file.unwrap();
+    assert_eq!(from_file.bounds, (1920, 1080));
+    assert_eq!(from_file.center, Some(Complex::new(-0.75, 0.1)));
+    assert_eq!(from_file.zoom, Some(40.0));
+    assert_eq!(from_file.max_iter, 1000);
+    assert_eq!(from_file.palette, Palette::Hsv { cycles: 1.0 });
+    assert_eq!(from_file.supersample, 2);
+    assert_eq!(from_file.output, "seahorse.png");
+
+    let overridden = overridden.unwrap();
+    assert_eq!(overridden.zoom, Some(8.0));
+    assert_eq!(overridden.max_iter, 300);
+    assert_eq!(overridden.bounds, (1920, 1080));
+
+    assert!(parse_args(&args(&["color", "--config"])).is_err());
+    assert!(parse_args(&args(&["color", "--config", "no_such_file.toml"])).is_err());
+}
+
+/// `--palette` の値を解釈する（虹色は 1 周）
+fn parse_palette(s: &str) -> Option<Palette> {
+    match s {
+        "classic" => Some(Palette::Classic),
+        "hsv" => Some(Palette::Hsv { cycles: 1.0 }),
+        _ => None,
+    }
+}
+
+/// `--tonemap` の値を解釈する
+fn parse_tone_map(s: &str) -> Option<ToneMap> {
+    match s {
+        "reinhard" => Some(ToneMap::Reinhard),
+        "aces" => Some(ToneMap::Aces),
+        _ => None,
+    }
+}
+
+/// `--fractal` の値を解釈する
+fn parse_fractal(s: &str) -> Option<Fractal> {
+    match s {
+        "mandelbrot" => Some(Fractal::Mandelbrot),
+        "burning-ship" => Some(Fractal::BurningShip),
+        _ => None,
+    }
+}
+
+/// フラクタルごとの既定の表示範囲
+fn preset_region(fractal: Fractal) -> Region {
+    match fractal {
+        Fractal::BurningShip => Region::BURNING_SHIP,
+        _ => Region::CLASSIC,
+    }
+}
+
+/// `--preset` で選べる有名な場所
+#[derive(Debug, PartialEq)]
+struct Preset {
+    name: &'static str,
+    center: Complex<f64>,
+    zoom: f64,
+    max_iter: u32,
+}
+
+const PRESETS: [Preset; 4] = [
+    Preset { name: "seahorse-valley", center: Complex::new(-0.75, 0.1), zoom: 40.0, max_iter: 1000 },
+    Preset { name: "elephant-valley", center: Complex::new(0.275, 0.006), zoom: 60.0, max_iter: 1000 },
+    Preset { name: "triple-spiral", center: Complex::new(-0.088, 0.654), zoom: 100.0, max_iter: 1500 },
+    Preset { name: "mini-mandelbrot", center: Complex::new(-1.7548776662, 0.0), zoom: 80.0, max_iter: 1000 },
+];
+
+/// `--preset` の値を解釈する（知らない名前なら、選べる名前を並べたエラー）
+fn parse_preset(s: &str) -> Result<&'static Preset, String> {
+    PRESETS.iter().find(|preset| preset.name == s).ok_or_else(|| {
+        let names: Vec<_> = PRESETS.iter().map(|preset| preset.name).collect();
+        format!("unknown preset: {} (available: {})", s, names.join(", "))
+    })
+}
+
+#[test]
+fn test_presets() {
+    for bounds in [(1200, 800), (800, 800), (640, 1024)] {
+        for preset in &PRESETS {
+            let region = Region::from_center_zoom(bounds, preset.center, preset.zoom);
+            assert_eq!(Region::from_corners(region.upper_left, region.lower_right), Ok(region),
+                       "{}", preset.name);
+            assert!((region.center() - preset.center).norm() < 1e-12);
+        }
+    }
+    assert_eq!(parse_preset("triple-spiral").unwrap().max_iter, 1500);
+
+    let err = parse_preset("double-spiral").unwrap_err();
+    assert!(err.contains("double-spiral"), "{}", err);
+    assert!(PRESETS.iter().all(|preset| err.contains(preset.name)), "{}", err);
+}
+
+/// `--interior` の値を解釈する
+fn parse_interior(s: &str) -> Option<InteriorColor> {
+    match s {
+        "black" => Some(InteriorColor::Black),
+        "final-norm" => Some(InteriorColor::FinalNorm),
+        "white" => Some(InteriorColor::White),
+        _ => None,
+    }
+}
+
+/// `--color-gamma-space` の値を解釈する
+fn parse_gamma_space(s: &str) -> Option<GammaSpace> {
+    match s {
+        "linear" => Some(GammaSpace::Linear),
+        "srgb" => Some(GammaSpace::Srgb),
+        _ => None,
+    }
+}
+
+/// `--pattern` の値を解釈する
+fn parse_pattern(s: &str) -> Option<Pattern> {
+    match s {
+        "checker" => Some(Pattern::Checker),
+        "coords" => Some(Pattern::Coords),
+        _ => None,
+    }
+}
+
+/// 同時に指定できないオプション
+///
+/// 左のオプションを指定したら、右のどれも指定できない。名前は `given_flags` が返すものと同じ。
+const CONFLICTS: &[(&str, &[&str])] = &[
+    ("--region", &["--center", "--zoom"]),
+    ("--preset", &["--region", "--center", "--zoom"]),
+    ("--verify", &["--sample-point"]),
+    ("--fuzz-check", &["--verify", "--sample-point"]),
+    ("--estimate-area", &["--verify", "--sample-point", "--fuzz-check"]),
+    // 比べるのは、ふつうに 1 枚描いて書き出すはずだった RGB の画像
+    ("--compare", &["--verify", "--sample-point", "--fuzz-check", "--estimate-area", "--estimate", "--find-detail",
+                    "--preview", "--dump-iterations", "--svg", "--output-bits 16", "--transparent-interior",
+                    "--format exr", "--max-time", "--stream", "--legend", "--crop", "--animate-julia", "--dive",
+                    "--mandelbrot-movie-spec", "--batch"]),
+    ("--dump-iterations", &["--animate-julia", "--dive", "--tile"]),
+    ("--svg", &["--animate-julia", "--dive", "--tile", "--dump-iterations", "--pattern"]),
+    // 16 ビットの画像は 1 枚分の反復回数をそのまま書くので、サンプリングの指定とは組み合わせない
+    ("--output-bits 16", &["--animate-julia", "--dive", "--tile", "--dump-iterations", "--jitter", "--supersample"]),
+    ("--estimate", &["--animate-julia", "--dive"]),
+    ("--find-detail", &["--animate-julia", "--dive", "--batch", "--estimate"]),
+    // 統計は描画で集めた反復回数から出すので、反復回数から色を付けられるときだけ
+    ("--stats", &["--animate-julia", "--dive", "--tile", "--estimate", "--stream", "--format exr", "--supersample",
+                  "--jitter", "--interior", "--exploit-symmetry", "--simd"]),
+    // 反復回数を使う出力にはテストパターンを描けない
+    ("--pattern", &["--output-bits 16", "--dump-iterations", "--estimate", "--stats"]),
+    ("--stream", &["--tile", "--jitter", "--animate-julia", "--dive", "--output-bits 16", "--dump-iterations",
+                   "--svg", "--estimate"]),
+    // 動画のフレームはキーフレームの中心・倍率・反復回数・パレットで描く
+    ("--mandelbrot-movie-spec", &["--animate-julia", "--dive", "--batch", "--tile", "--estimate", "--find-detail",
+                                  "--stats", "--svg", "--dump-iterations", "--output-bits 16", "--stream",
+                                  "--preview", "--format exr", "--max-time"]),
+    // ガンマ補正は RGB の画像にだけかける
+    ("--gamma", &["--output-bits 16", "--dump-iterations", "--svg"]),
+    // 凡例は linear の色と反復回数の対応を描くので、画像 1 枚全体を RGB で書くときだけ
+    ("--legend", &["--tile", "--stream", "--output-bits 16", "--dump-iterations", "--svg", "--pattern"]),
+    // 透明にする内部は反復回数から決めるので、1 ピクセル 1 点で塗るときだけ
+    ("--transparent-interior", &["--supersample", "--jitter", "--tile", "--stream", "--output-bits 16",
+                                 "--dump-iterations", "--svg", "--pattern", "--legend", "--gamma", "--interior",
+                                 "--animate-julia", "--dive"]),
+    // 出力ファイル名・大きさ・範囲はマニフェストの各行で決める
+    ("--batch", &["--animate-julia", "--dive", "--tile", "--region", "--center", "--zoom", "--preset",
+                  "--dump-iterations", "--svg"]),
+    ("--dither", &["--pattern"]),
+    // 切り出すのは画像 1 枚全体を RGB で描いてから
+    ("--crop", &["--tile", "--stream", "--output-bits 16", "--dump-iterations", "--svg", "--legend",
+                 "--transparent-interior", "--animate-julia", "--dive", "--batch"]),
+    // 上半分を写すのは画像全体を行ごとに描くときだけ
+    ("--exploit-symmetry", &["--tile", "--stream", "--jitter", "--simd", "--pattern"]),
+    // 標準出力に書けるのは PNG 1 枚だけ
+    ("--output -", &["--preview", "--format exr"]),
+    // 1 ピクセル 1 点で画像 1 枚全体を描くときだけ
+    ("--format exr", &["--dump-iterations", "--svg", "--supersample", "--dither", "--gamma", "--crop", "--pattern",
+                       "--animate-julia", "--dive", "--batch"]),
+    ("--tonemap", &["--dither", "--format exr", "--pattern"]),
+    // 帯ごとに締め切りを確かめるのは、画像全体を 1 ピクセル 1 点で描くときだけ
+    ("--max-time", &["--tile", "--stream", "--jitter", "--supersample", "--simd", "--exploit-symmetry", "--pattern",
+                     "--legend", "--crop", "--output-bits 16", "--dump-iterations", "--svg", "--transparent-interior",
+                     "--animate-julia", "--dive", "--batch", "--estimate",
+                     #[cfg(feature = "high-precision")] "--precision high"]),
+    // 帯の高さを変えられるのは、1 ピクセルずつ色を付けながら描くときだけ
+    ("--auto-band", &["--jitter", "--simd", "--exploit-symmetry", "--pattern", "--max-time",
+                      #[cfg(feature = "high-precision")] "--precision high"]),
+    // 下見は画像 1 枚全体を同じ範囲・色付けで小さく描く
+    ("--preview", &["--tile", "--animate-julia", "--dive", "--dump-iterations", "--svg", "--output-bits 16",
+                    "--estimate"]),
+    // タイルごとに描くとサンプルの番号が画像全体と合わなくなる
+    ("--jitter", &["--supersample", "--tile"]),
+    ("--palette-image", &["--palette hsv"]),
+    // 倍々精度は反復回数から直接色を決める描き方にだけ用意してある
+    #[cfg(feature = "high-precision")]
+    ("--precision high", &["--jitter"]),
+];
+
+/// `--color-mode linear` でしか使えないオプション
+///
+/// ヒストグラムなどは画像全体を見て色を決めるので、タイルや帯ごとには描けない。
+/// 反復回数だけを集める方式では、内部の点の最後の z も残らない。
+const LINEAR_ONLY: &[&str] = &[
+    "--tile", "--output-bits 16", "--stream", "--legend", "--transparent-interior", "--exploit-symmetry",
+    "--max-time", "--auto-band", "--interior", "--jitter",
+    #[cfg(feature = "high-precision")] "--precision high",
+];
+
+/// `CONFLICTS`・`LINEAR_ONLY` に出てくるオプションごとに、指定したかどうか
+fn given_flags(options: &Options, preset: bool) -> Vec<(&'static str, bool)> {
+    vec![
+        ("--region", options.region.is_some()),
+        ("--center", options.center.is_some()),
+        ("--zoom", options.zoom.is_some()),
+        ("--preset", preset),
+        ("--verify", options.verify),
+        ("--sample-point", options.sample_point.is_some()),
+        ("--fuzz-check", options.fuzz_check.is_some()),
+        ("--estimate-area", options.estimate_area.is_some()),
+        ("--compare", options.compare.is_some()),
+        ("--estimate", options.estimate),
+        ("--find-detail", options.find_detail),
+        ("--preview", options.preview),
+        ("--dump-iterations", options.dump_iterations.is_some()),
+        ("--svg", options.svg.is_some()),
+        ("--output-bits 16", options.output_bits == 16),
+        ("--transparent-interior", options.transparent_interior),
+        ("--format exr", options.exr),
+        ("--max-time", options.max_time.is_some()),
+        ("--stream", options.stream),
+        ("--legend", options.legend),
+        ("--crop", options.crop.is_some()),
+        ("--animate-julia", options.animate_julia.is_some()),
+        ("--dive", options.dive.is_some()),
+        ("--mandelbrot-movie-spec", options.movie.is_some()),
+        ("--batch", options.batch.is_some()),
+        ("--tile", options.tile.is_some()),
+        ("--pattern", options.pattern.is_some()),
+        ("--jitter", options.jitter.is_some()),
+        ("--supersample", options.supersample > 1),
+        ("--stats", options.stats),
+        ("--gamma", options.gamma.is_some()),
+        ("--interior", options.interior != InteriorColor::Black),
+        ("--dither", options.dither),
+        ("--simd", options.simd),
+        ("--exploit-symmetry", options.exploit_symmetry),
+        ("--tonemap", options.tone_map.is_some()),
+        ("--auto-band", options.auto_band),
+        ("--output -", options.output == STDOUT_FILENAME),
+        ("--palette-image", options.palette_image.is_some()),
+        ("--palette hsv", matches!(options.palette, Palette::Hsv { .. })),
+        #[cfg(feature = "high-precision")]
+        ("--precision high", options.precision == Precision::High),
+    ]
+}
+
+/// 名前を「a, b or c」の形に並べる
+fn join_or(names: &[&str]) -> String {
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+#[test]
+fn test_conflicts_table() {
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    // 表に書いた名前は、どれも given_flags にある
+    let options = parse_args(&args(&["color"])).unwrap();
+    let given = given_flags(&options, false);
+    let known = |flag: &str| given.iter().any(|&(name, _)| name == flag);
+    for &(flag, others) in CONFLICTS {
+        assert!(known(flag), "{}", flag);
+        assert!(!others.is_empty() && others.iter().all(|&other| known(other)), "{}", flag);
+    }
+    assert!(LINEAR_ONLY.iter().all(|&flag| known(flag)));
+
+    // エラーメッセージは表の行から作る
+    let err = parse_args(&args(&["color", "--max-time", "1", "--stream"])).unwrap_err();
+    assert!(err.to_string().starts_with("--max-time cannot be combined with --tile, --stream, "), "{}", err);
+    let err = parse_args(&args(&["color", "--output", "-", "--preview"])).unwrap_err();
+    assert_eq!(err.to_string(), "--output - cannot be combined with --preview or --format exr");
+    let err = parse_args(&args(&["color", "--legend", "--color-mode", "histogram"])).unwrap_err();
+    assert_eq!(err.to_string(), "--legend requires --color-mode linear");
+
+    assert_eq!(join_or(&["--a"]), "--a");
+    assert_eq!(join_or(&["--a", "--b", "--c"]), "--a, --b or --c");
+}
+
+/// コマンドライン引数を解析する（`args[0]` はプログラム名）
+fn parse_args(args: &[String]) -> Result<Options, RenderError> {
+    let args = expand_config(args)?;
+    let mut options = Options {
+        bounds: (1200, 800),
+        color_mode: ColorMode::Linear,
+        supersample: 1,
+        gamma_space: GammaSpace::Linear,
+        center: None,
+        zoom: None,
+        region: None,
+        keep_aspect: false,
+        progress: false,
+        animate_julia: None,
+        dive: None,
+        movie: None,
+        precision: Precision::F64,
+        simd: false,
+        palette: Palette::Classic,
+        palette_image: None,
+        repeat_palette: 1,
+        light: Light::DEFAULT,
+        tile: None,
+        max_iter: 200,
+        output: "mandelbrot.png".to_string(),
+        interior: InteriorColor::Black,
+        fractal: Fractal::Mandelbrot,
+        bailout: None,
+        dump_iterations: None,
+        jitter: None,
+        seed: 0,
+        auto_iter: false,
+        output_bits: 8,
+        estimate: false,
+        find_detail: false,
+        stats: false,
+        pattern: None,
+        svg: None,
+        stream: false,
+        batch: None,
+        gamma: None,
+        legend: false,
+        transparent_interior: false,
+        verify: false,
+        sample_point: None,
+        fuzz_check: None,
+        estimate_area: None,
+        compare: None,
+        tolerance: 0,
+        dither: false,
+        crop: None,
+        preview: false,
+        exploit_symmetry: false,
+        exr: false,
+        tone_map: None,
+        exposure: 1.0,
+        max_time: None,
+        auto_band: false,
+        band_rows: 1,
+        verbose: false,
+    };
+    let mut cycles = None;
+    let mut light = None;
+    let mut power = None;
+    let mut seed = None;
+    let mut exposure = None;
+    let mut output = None;
+    let mut gamma_space = None;
+    let mut tolerance = None;
+    let mut max_iter = None;
+    let mut preset = None;
+
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--size" => {
+                let value = rest.next().ok_or("--size requires a value")?;
+                options.bounds = match parse_pair(value, 'x') {
+                    Some((width, height)) if width > 0 && height > 0 => (width, height),
+                    _ => return Err(RenderError::ParseBounds(value.clone())),
+                };
+            }
+            "--color-mode" => {
+                let value = rest.next().ok_or("--color-mode requires a value")?;
+                options.color_mode = parse_color_mode(value)
+                    .ok_or_else(|| format!("unknown color mode: {}", value))?;
+            }
+            "--supersample" => {
+                let value = rest.next().ok_or("--supersample requires a value")?;
+                options.supersample = match value.parse() {
+                    Ok(n) if n >= 1 => n,
+                    _ => return Err(format!("invalid supersample factor: {}", value).into()),
+                };
+            }
+            "--jitter" => {
+                let value = rest.next().ok_or("--jitter requires a value")?;
+                options.jitter = match value.parse() {
+                    Ok(k) if k >= 1 => Some(k),
+                    _ => return Err(format!("invalid jitter sample count: {}", value).into()),
+                };
+            }
+            "--seed" => {
+                let value = rest.next().ok_or("--seed requires a value")?;
+                seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+            }
+            "--preset" => {
+                let value = rest.next().ok_or("--preset requires a value")?;
+                preset = Some(parse_preset(value)?);
+            }
+            "--center" => {
+                let value = rest.next().ok_or("--center requires a value")?;
+                options.center = Some(parse_complex(value).ok_or_else(|| RenderError::ParseComplex {
+                    what: "center point",
+                    text: value.clone(),
+                })?);
+            }
+            "--zoom" => {
+                let value = rest.next().ok_or("--zoom requires a value")?;
+                options.zoom = match value.parse::<f64>() {
+                    Ok(zoom) if zoom > 0.0 && zoom.is_finite() => Some(zoom),
+                    _ => return Err(format!("invalid zoom factor: {}", value).into()),
+                };
+            }
+            "--region" => {
+                let mut value = || rest.next().ok_or("--region requires UPPER_LEFT LOWER_RIGHT");
+                let (upper_left, lower_right) = (value()?, value()?);
+                let corner = |s: &String| {
+                    parse_complex(s).ok_or_else(|| RenderError::ParseComplex {
+                        what: "corner point",
+                        text: s.clone(),
+                    })
+                };
+                options.region = Some(Region::from_corners(corner(upper_left)?, corner(lower_right)?)?);
+            }
+            "--keep-aspect" => options.keep_aspect = true,
+            "--precision" => {
+                let value = rest.next().ok_or("--precision requires a value")?;
+                options.precision = parse_precision(value)
+                    .ok_or_else(|| format!("unknown precision: {}", value))?;
+            }
+            "--progress" => options.progress = true,
+            "--auto-iter" => options.auto_iter = true,
+            "--estimate" => options.estimate = true,
+            "--find-detail" => options.find_detail = true,
+            "--stats" => options.stats = true,
+            "--stream" => options.stream = true,
+            "--simd" => options.simd = true,
+            "--palette" => {
+                let value = rest.next().ok_or("--palette requires a value")?;
+                options.palette = parse_palette(value)
+                    .ok_or_else(|| format!("unknown palette: {}", value))?;
+            }
+            "--cycles" => {
+                let value = rest.next().ok_or("--cycles requires a value")?;
+                cycles = match value.parse::<f32>() {
+                    Ok(n) if n > 0.0 && n.is_finite() => Some(n),
+                    _ => return Err(format!("invalid cycle count: {}", value).into()),
+                };
+            }
+            "--light" => {
+                let value = rest.next().ok_or("--light requires AZ,ELEV")?;
+                light = match parse_pair::<f64>(value, ',') {
+                    Some((azimuth, elevation))
+                        if azimuth.is_finite() && (0.0..=90.0).contains(&elevation) =>
+                    {
+                        Some(Light { azimuth, elevation })
+                    }
+                    _ => return Err(format!("invalid light direction (expected AZ,ELEV): {}", value).into()),
+                };
+            }
+            "--palette-image" => {
+                options.palette_image = Some(rest.next().ok_or("--palette-image requires a file name")?.clone());
+            }
+            "--repeat-palette" => {
+                let value = rest.next().ok_or("--repeat-palette requires a value")?;
+                options.repeat_palette = match value.parse::<u32>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid repeat count: {}", value).into()),
+                };
+            }
+            "--animate-julia" => {
+                let mut value = || rest.next().ok_or("--animate-julia requires START END FRAMES OUTDIR");
+                let (start, end, frames, outdir) = (value()?, value()?, value()?, value()?);
+                options.animate_julia = Some(JuliaAnimation {
+                    start: parse_complex(start).ok_or_else(|| RenderError::ParseComplex {
+                        what: "julia constant",
+                        text: start.clone(),
+                    })?,
+                    end: parse_complex(end).ok_or_else(|| RenderError::ParseComplex {
+                        what: "julia constant",
+                        text: end.clone(),
+                    })?,
+                    frames: match frames.parse() {
+                        Ok(n) if n >= 1 => n,
+                        _ => return Err(format!("invalid frame count: {}", frames).into()),
+                    },
+                    outdir: outdir.clone(),
+                });
+            }
+            "--dive" => {
+                let mut value = || rest.next().ok_or("--dive requires CENTER FRAMES FACTOR OUTDIR");
+                let (center, frames, factor, outdir) = (value()?, value()?, value()?, value()?);
+                options.dive = Some(Dive {
+                    center: parse_complex(center).ok_or_else(|| RenderError::ParseComplex {
+                        what: "center point",
+                        text: center.clone(),
+                    })?,
+                    frames: match frames.parse() {
+                        Ok(n) if n >= 1 => n,
+                        _ => return Err(format!("invalid frame count: {}", frames).into()),
+                    },
+                    factor: match factor.parse::<f64>() {
+                        Ok(f) if f > 0.0 && f.is_finite() => f,
+                        _ => return Err(format!("invalid zoom factor: {}", factor).into()),
+                    },
+                    outdir: outdir.clone(),
+                });
+            }
+            "--mandelbrot-movie-spec" => {
+                let mut value = || rest.next().ok_or("--mandelbrot-movie-spec requires SPEC FRAMES OUTDIR");
+                let (spec, frames, outdir) = (value()?, value()?, value()?);
+                options.movie = Some(Movie {
+                    keyframes: parse_movie_spec(&std::fs::read_to_string(spec)?)?,
+                    frames: match frames.parse() {
+                        Ok(n) if n >= 1 => n,
+                        _ => return Err(format!("invalid frame count: {}", frames).into()),
+                    },
+                    outdir: outdir.clone(),
+                });
+            }
+            "--tile" => {
+                let mut value = || rest.next().ok_or("--tile requires COLxROW OFFX,OFFY");
+                let (size, offset) = (value()?, value()?);
+                options.tile = Some(Tile {
+                    size: match parse_pair(size, 'x') {
+                        Some((width, height)) if width > 0 && height > 0 => (width, height),
+                        _ => return Err(format!("invalid tile size: {}", size).into()),
+                    },
+                    offset: parse_pair(offset, ',')
+                        .ok_or_else(|| format!("invalid tile offset: {}", offset))?,
+                });
+            }
+            "--max-iter" => {
+                let value = rest.next().ok_or("--max-iter requires a value")?;
+                max_iter = match value.parse() {
+                    Ok(n) if n >= 1 => Some(n),
+                    _ => return Err(format!("invalid iteration limit: {}", value).into()),
+                };
+            }
+            "--output" => output = Some(rest.next().ok_or("--output requires a value")?.clone()),
+            "--output-bits" => {
+                let value = rest.next().ok_or("--output-bits requires a value")?;
+                options.output_bits = match value.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    _ => return Err(format!("invalid output bits (must be 8 or 16): {}", value).into()),
+                };
+            }
+            "--dump-iterations" => {
+                let value = rest.next().ok_or("--dump-iterations requires a value")?;
+                options.dump_iterations = Some(value.clone());
+            }
+            "--svg" => {
+                let value = rest.next().ok_or("--svg requires a value")?;
+                options.svg = Some(value.clone());
+            }
+            "--gamma" => {
+                let value = rest.next().ok_or("--gamma requires a value")?;
+                options.gamma = match value.parse::<f64>() {
+                    Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Some(gamma),
+                    _ => return Err(format!("invalid gamma: {}", value).into()),
+                };
+            }
+            "--legend" => options.legend = true,
+            "--verify" => options.verify = true,
+            "--fuzz-check" => {
+                let value = rest.next().ok_or("--fuzz-check requires a seed")?;
+                options.fuzz_check = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+            }
+            "--estimate-area" => {
+                let value = rest.next().ok_or("--estimate-area requires a sample count")?;
+                options.estimate_area = match value.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(format!("invalid sample count: {}", value).into()),
+                };
+            }
+            "--compare" => {
+                options.compare = Some(rest.next().ok_or("--compare requires a baseline image")?.clone());
+            }
+            "--tolerance" => {
+                let value = rest.next().ok_or("--tolerance requires a value")?;
+                tolerance = Some(value.parse().map_err(|_| format!("invalid tolerance: {}", value))?);
+            }
+            "--dither" => options.dither = true,
+            "--preview" => options.preview = true,
+            "--exploit-symmetry" => options.exploit_symmetry = true,
+            "--auto-band" => options.auto_band = true,
+            "--verbose" => options.verbose = true,
+            "--max-time" => {
+                let value = rest.next().ok_or("--max-time requires a value")?;
+                options.max_time = match value.parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                        Some(std::time::Duration::from_secs_f64(seconds))
+                    }
+                    _ => return Err(format!("invalid time budget: {}", value).into()),
+                };
+            }
+            "--tonemap" => {
+                let value = rest.next().ok_or("--tonemap requires a value")?;
+                options.tone_map = Some(parse_tone_map(value)
+                    .ok_or_else(|| format!("unknown tone mapping: {}", value))?);
+            }
+            "--exposure" => {
+                let value = rest.next().ok_or("--exposure requires a value")?;
+                exposure = match value.parse::<f32>() {
+                    Ok(e) if e >= 0.0 && e.is_finite() => Some(e),
+                    _ => return Err(format!("invalid exposure: {}", value).into()),
+                };
+            }
+            "--format" => {
+                let value = rest.next().ok_or("--format requires a value")?;
+                options.exr = match value.as_str() {
+                    "png" => false,
+                    "exr" => true,
+                    _ => return Err(format!("unknown format: {}", value).into()),
+                };
+            }
+            "--crop" => {
+                let value = rest.next().ok_or("--crop requires X,Y,W,H")?;
+                let fields: Option<Vec<usize>> = value.split(',').map(|field| field.trim().parse().ok()).collect();
+                options.crop = match fields.as_deref() {
+                    Some(&[x, y, width, height]) if width > 0 && height > 0 => {
+                        Some(Tile { size: (width, height), offset: (x, y) })
+                    }
+                    _ => return Err(format!("invalid crop rectangle (expected X,Y,W,H): {}", value).into()),
+                };
+            }
+            "--sample-point" => {
+                let value = rest.next().ok_or("--sample-point requires a value")?;
+                options.sample_point = Some(parse_complex(value).ok_or_else(|| RenderError::ParseComplex {
+                    what: "sample point",
+                    text: value.clone(),
+                })?);
+            }
+            "--transparent-interior" => options.transparent_interior = true,
+            "--batch" => {
+                let value = rest.next().ok_or("--batch requires a value")?;
+                options.batch = Some(value.clone());
+            }
+            "--power" => {
+                let value = rest.next().ok_or("--power requires a value")?;
+                power = match value.parse::<u32>() {
+                    Ok(d) if d >= 2 => Some(d),
+                    _ => return Err(format!("invalid power: {}", value).into()),
+                };
+            }
+            "--fractal" => {
+                let value = rest.next().ok_or("--fractal requires a value")?;
+                options.fractal = parse_fractal(value)
+                    .ok_or_else(|| format!("unknown fractal: {}", value))?;
+            }
+            "--bailout" => {
+                let value = rest.next().ok_or("--bailout requires a value")?;
+                options.bailout = match value.parse::<f64>() {
+                    Ok(r) if r >= DEFAULT_BAILOUT && r.is_finite() => Some(r),
+                    _ => return Err(format!("invalid bailout radius (must be at least {}): {}",
+                                            DEFAULT_BAILOUT, value).into()),
+                };
+            }
+            "--pattern" => {
+                let value = rest.next().ok_or("--pattern requires a value")?;
+                options.pattern = Some(parse_pattern(value)
+                    .ok_or_else(|| format!("unknown pattern: {}", value))?);
+            }
+            "--interior" => {
+                let value = rest.next().ok_or("--interior requires a value")?;
+                options.interior = parse_interior(value)
+                    .ok_or_else(|| format!("unknown interior color: {}", value))?;
+            }
+            "--color-gamma-space" => {
+                let value = rest.next().ok_or("--color-gamma-space requires a value")?;
+                gamma_space = Some(parse_gamma_space(value)
+                    .ok_or_else(|| format!("unknown color gamma space: {}", value))?);
+            }
+            // 白黒版（rayon など）と同じ FILE SIZE UPPERLEFT LOWERRIGHT の並び
+            file if file == STDOUT_FILENAME || !file.starts_with('-') => {
+                let mut value = || rest.next().ok_or("FILE requires SIZE UPPERLEFT LOWERRIGHT");
+                let (size, upper_left, lower_right) = (value()?, value()?, value()?);
+                output = Some(file.to_string());
+                options.bounds = match parse_pair(size, 'x') {
+                    Some((width, height)) if width > 0 && height > 0 => (width, height),
+                    _ => return Err(RenderError::ParseBounds(size.clone())),
+                };
+                let corner = |s: &String| {
+                    parse_complex(s).ok_or_else(|| RenderError::ParseComplex {
+                        what: "corner point",
+                        text: s.clone(),
+                    })
+                };
+                options.region = Some(Region::from_corners(corner(upper_left)?, corner(lower_right)?)?);
+            }
+            _ => return Err(format!("unknown argument: {}", arg).into()),
+        }
+    }
+    // 出力ファイル名を指定しなければ、形式に合わせた拡張子にする
+    match output {
+        Some(output) => options.output = output,
+        None if options.exr => options.output = "mandelbrot.exr".to_string(),
+        None => {}
+    }
+
+    let given = given_flags(&options, preset.is_some());
+    let is_given = |flag: &str| given.iter().any(|&(name, set)| set && name == flag);
+    for &(flag, others) in CONFLICTS {
+        if is_given(flag) && others.iter().any(|&other| is_given(other)) {
+            return Err(format!("{} cannot be combined with {}", flag, join_or(others)).into());
+        }
+    }
+    if options.color_mode != ColorMode::Linear {
+        if let Some(flag) = LINEAR_ONLY.iter().find(|&&flag| is_given(flag)) {
+            return Err(format!("{} requires --color-mode linear", flag).into());
+        }
+    }
+    if options.stats && !matches!(options.color_mode, ColorMode::Linear | ColorMode::Histogram) {
+        return Err("--stats requires --color-mode linear or histogram".into());
+    }
+    match tolerance {
+        Some(tolerance) if options.compare.is_some() => options.tolerance = tolerance,
+        Some(_) => return Err("--tolerance requires --compare".into()),
+        None => {}
+    }
+    // --max-iter を明示したときはその値をそのまま使い、倍率からは決めない
+    if max_iter.is_some() {
+        options.auto_iter = false;
+    }
+    // プリセットは中心・倍率・反復回数の上限をまとめて指定したのと同じ（上限は --max-iter が優先）
+    if let Some(preset) = preset {
+        options.center = Some(preset.center);
+        options.zoom = Some(preset.zoom);
+        max_iter = max_iter.or(Some(preset.max_iter));
+    }
+    if let Some(n) = max_iter {
+        options.max_iter = n;
+    }
+    if let Some(tile) = options.tile {
+        if !tile.fits(options.bounds) {
+            return Err(format!("tile {}x{} at {},{} does not fit in the {}x{} image",
+                               tile.size.0, tile.size.1, tile.offset.0, tile.offset.1,
+                               options.bounds.0, options.bounds.1).into());
+        }
+    }
+    match (power, options.fractal) {
+        (None, _) | (Some(2), Fractal::Mandelbrot) => {}
+        (Some(d), Fractal::Mandelbrot) => options.fractal = Fractal::multibrot(d)?,
+        (Some(_), _) => return Err("--power requires --fractal mandelbrot".into()),
+    }
+    if options.animate_julia.is_some() && options.fractal != Fractal::Mandelbrot {
+        return Err("--fractal and --power cannot be combined with --animate-julia".into());
+    }
+    // linear などは色が整数の反復回数から決まるので、丸める前の小数の色がない
+    if options.dither && !matches!(options.color_mode, ColorMode::Histogram | ColorMode::Potential | ColorMode::Smooth)
+    {
+        return Err("--dither requires --color-mode histogram, potential or smooth".into());
+    }
+    if let Some(crop) = options.crop {
+        if !crop.fits(options.bounds) {
+            return Err(format!("crop {}x{} at {},{} does not fit in the {}x{} image",
+                               crop.size.0, crop.size.1, crop.offset.0, crop.offset.1,
+                               options.bounds.0, options.bounds.1).into());
+        }
+    }
+    // 丸める前の色があるのは smooth と potential だけ
+    if options.exr {
+        if !matches!(options.color_mode, ColorMode::Smooth | ColorMode::Potential) {
+            return Err("--format exr requires --color-mode smooth or potential".into());
+        }
+        if !options.output.ends_with(".exr") {
+            return Err(format!("--format exr requires an .exr output file: {}", options.output).into());
+        }
+    }
+    // トーンマッピングは丸める前の色を 8 ビットにするときにかける
+    if options.tone_map.is_some() && !matches!(options.color_mode, ColorMode::Smooth | ColorMode::Potential) {
+        return Err("--tonemap requires --color-mode smooth or potential".into());
+    }
+    match exposure {
+        Some(e) if options.tone_map.is_some() => options.exposure = e,
+        Some(_) => return Err("--exposure requires --tonemap".into()),
+        None => {}
+    }
+    match seed {
+        Some(seed) if options.jitter.is_some() || options.estimate_area.is_some() => options.seed = seed,
+        Some(_) => return Err("--seed requires --jitter or --estimate-area".into()),
+        None => {}
+    }
+    // 色を平均するのはサブピクセルを縮小するときとジッター AA だけ
+    match gamma_space {
+        Some(space) if options.supersample > 1 || options.jitter.is_some() => options.gamma_space = space,
+        Some(_) => return Err("--color-gamma-space requires --supersample greater than 1 or --jitter".into()),
+        None => {}
+    }
+    if let Some(n) = cycles {
+        match &mut options.palette {
+            Palette::Hsv { cycles } if options.palette_image.is_none() => *cycles = n,
+            _ => return Err("--cycles requires --palette hsv".into()),
+        }
+    }
+    if let Some(light) = light {
+        if options.color_mode != ColorMode::Shaded {
+            return Err("--light requires --color-mode shaded".into());
+        }
+        options.light = light;
+    }
+
+    Ok(options)
+}
+
+/// `--palette-image` の参照画像を読み込み、`--repeat-palette` の繰り返しと合わせて `options.palette` にする
+///
+/// `parse_args` はファイルを読まないので、引数を解釈した後に 1 回だけ呼ぶ。
+fn resolve_palette(mut options: Options) -> Result<Options, RenderError> {
+    if let Some(filename) = &options.palette_image {
+        options.palette = Palette::Gradient(load_palette_image(filename)?);
+    }
+    if options.repeat_palette > 1 {
+        let palette = Box::new(options.palette);
+        options.palette = Palette::Repeat { palette, times: options.repeat_palette };
+    }
+    Ok(options)
+}
+
+#[test]
+fn test_resolve_palette() {
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    let resolve = |s: &[&str]| resolve_palette(parse_args(&args(s)).unwrap());
+
+    assert_eq!(resolve(&["color"]).unwrap().palette, Palette::Classic);
+
+    let palette = resolve(&["color", "--repeat-palette", "2"]).unwrap().palette;
+    assert_eq!(palette, Palette::Repeat { palette: Box::new(Palette::Classic), times: 2 });
+    assert_eq!(palette.color_at(0.25), palette.color_at(0.75));
+
+    // 参照画像は解釈した後に読む（黒と白の 2 ピクセルなら、真ん中は灰色）
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_palette_args_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+    let options = parse_args(&args(&["color", "--palette-image", filename, "--repeat-palette", "2"])).unwrap();
+    assert_eq!(options.palette_image.as_deref(), Some(filename));
+    write_image(filename, &[0, 0, 0, 255, 255, 255], (2, 1)).unwrap();
+    let palette = resolve_palette(options);
+    std::fs::remove_file(filename).unwrap();
+    let palette = palette.unwrap().palette;
+    assert_eq!(palette.color_at(0.25), [128, 128, 128]);
+    assert_eq!(palette.color_at(0.75), [128, 128, 128]);
+    assert!(resolve(&["color", "--palette-image", filename]).is_err());
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().bounds, (1200, 800));
+    assert_eq!(parse_args(&args(&["color", "--size", "640x480"])).unwrap().bounds, (640, 480));
+    assert!(matches!(parse_args(&args(&["color", "--size", "640,480"])),
+                     Err(RenderError::ParseBounds(_))));
+    assert!(parse_args(&args(&["color", "--size", "0x480"])).is_err());
+    assert!(matches!(parse_args(&args(&["color", "--center", "x"])),
+                     Err(RenderError::ParseComplex { what: "center point", .. })));
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().color_mode, ColorMode::Linear);
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "histogram"])).unwrap().color_mode,
+               ColorMode::Histogram);
+    assert!(parse_args(&args(&["color", "--color-mode"])).is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "rainbow"])).is_err());
+    assert!(parse_args(&args(&["color", "--bogus"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().jitter, None);
+    let options = parse_args(&args(&["color", "--jitter", "8", "--seed", "42"])).unwrap();
+    assert_eq!((options.jitter, options.seed), (Some(8), 42));
+    assert_eq!(parse_args(&args(&["color", "--jitter", "8"])).unwrap().seed, 0);
+    assert!(parse_args(&args(&["color", "--jitter", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--seed", "42"])).is_err());
+    let options = parse_args(&args(&["color", "--estimate-area", "100000", "--seed", "7"])).unwrap();
+    assert_eq!((options.estimate_area, options.seed), (Some(100_000), 7));
+    assert_eq!(parse_args(&args(&["color"])).unwrap().estimate_area, None);
+    assert!(parse_args(&args(&["color", "--estimate-area", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--estimate-area", "1000", "--verify"])).is_err());
+
+    let options = parse_args(&args(&["color", "--compare", "golden.png", "--tolerance", "2"])).unwrap();
+    assert_eq!((options.compare.as_deref(), options.tolerance), (Some("golden.png"), 2));
+    assert_eq!(parse_args(&args(&["color", "--compare", "golden.png"])).unwrap().tolerance, 0);
+    assert!(parse_args(&args(&["color", "--compare"])).is_err());
+    assert!(parse_args(&args(&["color", "--tolerance", "2"])).is_err());
+    assert!(parse_args(&args(&["color", "--compare", "golden.png", "--tolerance", "256"])).is_err());
+    assert!(parse_args(&args(&["color", "--compare", "golden.png", "--legend"])).is_err());
+    assert!(parse_args(&args(&["color", "--jitter", "8", "--seed", "-1"])).is_err());
+    assert!(parse_args(&args(&["color", "--jitter", "8", "--supersample", "2"])).is_err());
 
-    let mut pixels = Vec::with_capacity(bounds.0 * bounds.1 * 3);
+    assert_eq!(parse_args(&args(&["color", "--supersample", "2"])).unwrap().gamma_space, GammaSpace::Linear);
+    let options = parse_args(&args(&["color", "--supersample", "2", "--color-gamma-space", "srgb"])).unwrap();
+    assert_eq!(options.gamma_space, GammaSpace::Srgb);
+    let options = parse_args(&args(&["color", "--jitter", "8", "--color-gamma-space", "linear"])).unwrap();
+    assert_eq!(options.gamma_space, GammaSpace::Linear);
+    assert!(parse_args(&args(&["color", "--supersample", "2", "--color-gamma-space", "cmyk"])).is_err());
+    assert!(parse_args(&args(&["color", "--color-gamma-space", "srgb"])).is_err());
+    assert!(parse_args(&args(&["color", "--jitter", "8", "--color-mode", "histogram"])).is_err());
 
-    for y in 0..bounds.1 {
-        for x in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (x, y), upper_left, lower_right);
-            let iter = escape_time(point, max_iter);
-            let [r, g, b] = color_map(iter, max_iter);
-            pixels.extend_from_slice(&[r, g, b]);
+    assert_eq!(parse_args(&args(&["color"])).unwrap().supersample, 1);
+    assert_eq!(parse_args(&args(&["color", "--supersample", "3"])).unwrap().supersample, 3);
+    assert!(parse_args(&args(&["color", "--supersample", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--supersample", "x"])).is_err());
+
+    let options = parse_args(&args(&["color", "--center", "-0.75,0.1", "--zoom", "4"])).unwrap();
+    assert_eq!(options.center, Some(Complex::new(-0.75, 0.1)));
+    assert_eq!(options.zoom, Some(4.0));
+    assert!(parse_args(&args(&["color", "--center", "-0.75"])).is_err());
+    assert!(parse_args(&args(&["color", "--zoom", "0"])).is_err());
+
+    let options = parse_args(&args(&["color", "--region", "-1.2,0.35", "-1,0.2"])).unwrap();
+    assert_eq!(options.region, Some(Region {
+        upper_left: Complex::new(-1.2, 0.35),
+        lower_right: Complex::new(-1.0, 0.2),
+    }));
+    assert!(parse_args(&args(&["color", "--region", "-1,0.2", "-1.2,0.35"])).is_err());
+    assert!(parse_args(&args(&["color", "--region", "-1.2,0.35"])).is_err());
+    assert!(parse_args(&args(&["color", "--region", "-1.2,0.35", "-1,0.2", "--zoom", "2"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().keep_aspect);
+    assert!(parse_args(&args(&["color", "--keep-aspect"])).unwrap().keep_aspect);
+    assert!(parse_args(&args(&["color", "--progress"])).unwrap().progress);
+    assert_eq!(parse_args(&args(&["color"])).unwrap().precision, Precision::F64);
+    assert_eq!(parse_args(&args(&["color", "--precision", "f32"])).unwrap().precision, Precision::F32);
+    assert!(parse_args(&args(&["color", "--precision", "f16"])).is_err());
+    #[cfg(feature = "high-precision")]
+    {
+        assert_eq!(parse_args(&args(&["color", "--precision", "high"])).unwrap().precision, Precision::High);
+        assert!(parse_args(&args(&["color", "--precision", "high", "--color-mode", "histogram"])).is_err());
+        assert!(parse_args(&args(&["color", "--precision", "high", "--jitter", "4"])).is_err());
+    }
+    assert!(parse_args(&args(&["color", "--simd"])).unwrap().simd);
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().palette, Palette::Classic);
+    assert_eq!(parse_args(&args(&["color", "--palette", "hsv"])).unwrap().palette,
+               Palette::Hsv { cycles: 1.0 });
+    assert_eq!(parse_args(&args(&["color", "--cycles", "8", "--palette", "hsv"])).unwrap().palette,
+               Palette::Hsv { cycles: 8.0 });
+    assert!(parse_args(&args(&["color", "--palette", "fire"])).is_err());
+    assert!(parse_args(&args(&["color", "--palette", "hsv", "--cycles", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--cycles", "8"])).is_err());
+    assert_eq!(parse_args(&args(&["color"])).unwrap().repeat_palette, 1);
+    let options = parse_args(&args(&["color", "--repeat-palette", "2", "--palette", "hsv", "--cycles", "3"]))
+        .unwrap();
+    assert_eq!((options.palette, options.repeat_palette), (Palette::Hsv { cycles: 3.0 }, 2));
+    assert!(parse_args(&args(&["color", "--repeat-palette", "0"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "shaded"])).unwrap().light, Light::DEFAULT);
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "shaded", "--light", "90,30"])).unwrap().light,
+               Light { azimuth: 90.0, elevation: 30.0 });
+    assert!(parse_args(&args(&["color", "--color-mode", "shaded", "--light", "90"])).is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "shaded", "--light", "90,120"])).is_err());
+    assert!(parse_args(&args(&["color", "--light", "90,30"])).is_err());
+    assert!(parse_args(&args(&["color", "--repeat-palette", "1.5"])).is_err());
+
+    assert!(parse_args(&args(&["color", "--palette-image", "ref.png", "--palette", "hsv"])).is_err());
+    assert!(parse_args(&args(&["color", "--palette-image", "ref.png", "--cycles", "2"])).is_err());
+    assert!(parse_args(&args(&["color", "--palette-image"])).is_err());
+
+    let options = parse_args(&args(&["color", "--size", "800x600", "--tile", "400x300", "400,0"])).unwrap();
+    assert_eq!(options.tile, Some(Tile { size: (400, 300), offset: (400, 0) }));
+    assert!(parse_args(&args(&["color", "--size", "800x600", "--tile", "400x300", "500,0"])).is_err());
+    assert!(parse_args(&args(&["color", "--tile", "400x300"])).is_err());
+    assert!(parse_args(&args(&["color", "--tile", "0x300", "0,0"])).is_err());
+    assert!(parse_args(&args(&["color", "--tile", "400x300", "0,0", "--color-mode", "histogram"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().max_iter, 200);
+    assert_eq!(parse_args(&args(&["color", "--max-iter", "1000"])).unwrap().max_iter, 1000);
+    assert!(parse_args(&args(&["color", "--max-iter", "0"])).is_err());
+
+    let options = parse_args(&args(&["color", "--preset", "seahorse-valley"])).unwrap();
+    assert_eq!(options.center, Some(Complex::new(-0.75, 0.1)));
+    assert_eq!((options.zoom, options.max_iter), (Some(40.0), 1000));
+    let options = parse_args(&args(&["color", "--max-iter", "300", "--preset", "seahorse-valley"])).unwrap();
+    assert_eq!(options.max_iter, 300);
+    assert!(parse_args(&args(&["color", "--preset", "nowhere"])).is_err());
+
+    assert!(parse_args(&args(&["color", "--auto-iter"])).unwrap().auto_iter);
+    assert!(parse_args(&args(&["color", "--auto-iter", "--preset", "seahorse-valley"])).unwrap().auto_iter);
+    assert!(!parse_args(&args(&["color", "--auto-iter", "--max-iter", "300"])).unwrap().auto_iter);
+    assert!(parse_args(&args(&["color", "--preset", "triple-spiral", "--zoom", "2"])).is_err());
+    assert_eq!(parse_args(&args(&["color"])).unwrap().output, "mandelbrot.png");
+    assert_eq!(parse_args(&args(&["color", "--output", "out.ppm"])).unwrap().output, "out.ppm");
+
+    // 白黒版と同じ並び
+    let options = parse_args(&args(&["color", "mandel.png", "1000x750", "-1.20,0.35", "-1,0.20",
+                                     "--max-iter", "500", "--palette", "hsv"])).unwrap();
+    assert_eq!(options.output, "mandel.png");
+    assert_eq!(options.bounds, (1000, 750));
+    assert_eq!(options.region,
+               Some(Region::from_corners(Complex::new(-1.2, 0.35), Complex::new(-1.0, 0.2)).unwrap()));
+    assert_eq!(options.max_iter, 500);
+    assert_eq!(options.palette, Palette::Hsv { cycles: 1.0 });
+    let flags_first = parse_args(&args(&["color", "--max-iter", "500", "mandel.png", "1000x750", "-1.20,0.35",
+                                          "-1,0.20"])).unwrap();
+    assert_eq!(flags_first.region, options.region);
+    assert!(parse_args(&args(&["color", "mandel.png", "1000x750", "-1.20,0.35"])).is_err());
+    assert!(parse_args(&args(&["color", "mandel.png", "1000", "-1.20,0.35", "-1,0.20"])).is_err());
+    assert!(parse_args(&args(&["color", "mandel.png", "1000x750", "-1.20,0.35", "-1,0.50"])).is_err());
+    assert!(parse_args(&args(&["color", "mandel.png", "1000x750", "-1.20,0.35", "-1,0.20", "--zoom", "2"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().stream);
+    assert!(parse_args(&args(&["color", "--stream", "--size", "10000x10000"])).unwrap().stream);
+    assert!(parse_args(&args(&["color", "--stream", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--stream", "--tile", "10x10", "0,0"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().pattern, None);
+    assert_eq!(parse_args(&args(&["color", "--pattern", "checker"])).unwrap().pattern, Some(Pattern::Checker));
+    assert_eq!(parse_args(&args(&["color", "--pattern", "coords", "--tile", "10x10", "0,0"])).unwrap().pattern,
+               Some(Pattern::Coords));
+    assert!(parse_args(&args(&["color", "--pattern", "stripes"])).is_err());
+    assert!(parse_args(&args(&["color", "--pattern", "checker", "--stats"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().stats);
+    assert!(parse_args(&args(&["color", "--stats", "--color-mode", "histogram"])).unwrap().stats);
+    assert!(parse_args(&args(&["color", "--stats", "--tile", "10x10", "0,0"])).is_err());
+    assert!(parse_args(&args(&["color", "--stats", "--stream"])).is_err());
+    assert!(parse_args(&args(&["color", "--stats", "--supersample", "2"])).is_err());
+    assert!(parse_args(&args(&["color", "--stats", "--color-mode", "smooth"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().estimate);
+    assert!(parse_args(&args(&["color", "--estimate", "--size", "8000x6000"])).unwrap().estimate);
+    assert!(parse_args(&args(&["color", "--estimate", "--dive", "-0.75,0.1", "10", "1.1", "dive"])).is_err());
+    assert!(!parse_args(&args(&["color"])).unwrap().find_detail);
+    let options = parse_args(&args(&["color", "--find-detail", "--center", "-0.75,0.1", "--zoom", "20"])).unwrap();
+    assert!(options.find_detail);
+    assert!(parse_args(&args(&["color", "--find-detail", "--estimate"])).is_err());
+    assert!(parse_args(&args(&["color", "--find-detail", "--batch", "jobs.txt"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().output_bits, 8);
+    assert_eq!(parse_args(&args(&["color", "--output-bits", "16"])).unwrap().output_bits, 16);
+    assert!(parse_args(&args(&["color", "--output-bits", "12"])).is_err());
+    assert!(parse_args(&args(&["color", "--output-bits", "16", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--output-bits", "16", "--supersample", "2"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().gamma(), DEFAULT_GAMMA);
+    assert_eq!(parse_args(&args(&["color", "--gamma", "1.0"])).unwrap().gamma(), 1.0);
+    assert!(parse_args(&args(&["color", "--output-bits", "16"])).is_ok());
+    assert!(parse_args(&args(&["color", "--gamma", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--gamma", "2.2", "--output-bits", "16"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().transparent_interior);
+    assert!(parse_args(&args(&["color", "--transparent-interior"])).unwrap().transparent_interior);
+    assert!(parse_args(&args(&["color", "--transparent-interior", "--supersample", "2"])).is_err());
+    assert!(parse_args(&args(&["color", "--transparent-interior", "--color-mode", "histogram"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().verify);
+    assert!(parse_args(&args(&["color", "--verify"])).unwrap().verify);
+    assert_eq!(parse_args(&args(&["color"])).unwrap().fuzz_check, None);
+    assert_eq!(parse_args(&args(&["color", "--fuzz-check", "42"])).unwrap().fuzz_check, Some(42));
+    assert!(parse_args(&args(&["color", "--fuzz-check", "-1"])).is_err());
+    assert!(parse_args(&args(&["color", "--fuzz-check", "42", "--verify"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().dither);
+    assert!(parse_args(&args(&["color", "--dither", "--color-mode", "histogram"])).unwrap().dither);
+    assert!(parse_args(&args(&["color", "--dither", "--color-mode", "potential"])).unwrap().dither);
+    assert!(parse_args(&args(&["color", "--dither", "--color-mode", "smooth"])).unwrap().dither);
+    assert!(parse_args(&args(&["color", "--dither"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().exploit_symmetry);
+    assert!(parse_args(&args(&["color", "--exploit-symmetry", "--supersample", "2"])).unwrap().exploit_symmetry);
+    assert!(parse_args(&args(&["color", "--exploit-symmetry", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--exploit-symmetry", "--stream"])).is_err());
+
+    let options = parse_args(&args(&["color"])).unwrap();
+    assert_eq!((options.auto_band, options.band_rows, options.verbose), (false, 1, false));
+    let options = parse_args(&args(&["color", "--auto-band", "--verbose", "--supersample", "2"])).unwrap();
+    assert!(options.auto_band && options.verbose);
+    assert!(parse_args(&args(&["color", "--auto-band", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--auto-band", "--simd"])).is_err());
+    assert!(parse_args(&args(&["color", "--auto-band", "--exploit-symmetry"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().exr);
+    let options = parse_args(&args(&["color", "--format", "exr", "--color-mode", "smooth"])).unwrap();
+    assert!(options.exr);
+    assert_eq!(options.output, "mandelbrot.exr");
+    let options = parse_args(&args(&["color", "--color-mode", "potential", "--format", "exr",
+                                     "--output", "out.exr"])).unwrap();
+    assert_eq!(options.output, "out.exr");
+    assert!(parse_args(&args(&["color", "--format", "exr", "--color-mode", "smooth", "--output", "out.png"]))
+        .is_err());
+    assert!(parse_args(&args(&["color", "--format", "exr", "--color-mode", "smooth", "out", "40x30", "-2,1", "1,-1"]))
+        .is_err());
+    assert!(!parse_args(&args(&["color", "--format", "png"])).unwrap().exr);
+    assert!(parse_args(&args(&["color", "--format", "tiff"])).is_err());
+    assert!(parse_args(&args(&["color", "--format", "exr"])).is_err());
+    assert!(parse_args(&args(&["color", "--format", "exr", "--color-mode", "smooth", "--dither"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().max_time, None);
+    assert_eq!(parse_args(&args(&["color", "--max-time", "1.5"])).unwrap().max_time,
+               Some(std::time::Duration::from_millis(1500)));
+    assert!(parse_args(&args(&["color", "--max-time", "0"])).is_err());
+    assert!(parse_args(&args(&["color", "--max-time", "-1"])).is_err());
+    assert!(parse_args(&args(&["color", "--max-time", "1", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--max-time", "1", "--supersample", "2"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().tone_map, None);
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "reinhard"])).unwrap()
+                   .tone_map,
+               Some(ToneMap::Reinhard));
+    assert_eq!(parse_args(&args(&["color", "--tonemap", "aces", "--color-mode", "potential"])).unwrap()
+                   .tone_map,
+               Some(ToneMap::Aces));
+    assert!(parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "filmic"])).is_err());
+    assert!(parse_args(&args(&["color", "--tonemap", "reinhard"])).is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "aces", "--dither"]))
+        .is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "aces", "--format", "exr"]))
+        .is_err());
+
+    let options = parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "aces"])).unwrap();
+    assert_eq!(options.exposure, 1.0);
+    let options = parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "aces",
+                                     "--exposure", "8"])).unwrap();
+    assert_eq!(options.exposure, 8.0);
+    // EXR には明るさを掛けない色を書くので、--exposure は --tonemap のときだけ
+    assert!(parse_args(&args(&["color", "--exposure", "0.5", "--format", "exr", "--color-mode", "potential"]))
+        .is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "smooth", "--tonemap", "aces", "--exposure", "-1"]))
+        .is_err());
+    assert!(parse_args(&args(&["color", "--color-mode", "smooth", "--exposure", "2"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color", "--output", "-"])).unwrap().output, STDOUT_FILENAME);
+    let options = parse_args(&args(&["color", "-", "40x30", "-2.0,1.0", "1.0,-1.0"])).unwrap();
+    assert_eq!((options.output.as_str(), options.bounds), (STDOUT_FILENAME, (40, 30)));
+    assert!(parse_args(&args(&["color", "--output", "-", "--preview"])).is_err());
+    assert!(parse_args(&args(&["color", "--output", "-", "--format", "exr", "--color-mode", "smooth"]))
+        .is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().preview);
+    assert!(parse_args(&args(&["color", "--preview", "--supersample", "2"])).unwrap().preview);
+    assert!(parse_args(&args(&["color", "--preview", "--tile", "10x10", "0,0"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().crop, None);
+    let options = parse_args(&args(&["color", "--size", "800x600", "--crop", "10,20,700,500"])).unwrap();
+    assert_eq!(options.crop, Some(Tile { size: (700, 500), offset: (10, 20) }));
+    assert!(parse_args(&args(&["color", "--size", "800x600", "--crop", "200,0,700,500"])).is_err());
+    assert!(parse_args(&args(&["color", "--crop", "0,0,0,10"])).is_err());
+    assert!(parse_args(&args(&["color", "--crop", "0,0,10"])).is_err());
+    assert!(parse_args(&args(&["color", "--crop", "0,0,10,10", "--legend"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().sample_point, None);
+    assert_eq!(parse_args(&args(&["color", "--sample-point", "-0.75,0.1"])).unwrap().sample_point,
+               Some(Complex::new(-0.75, 0.1)));
+    assert!(parse_args(&args(&["color", "--sample-point", "-0.75"])).is_err());
+    assert!(parse_args(&args(&["color", "--sample-point", "0,0", "--verify"])).is_err());
+
+    assert!(!parse_args(&args(&["color"])).unwrap().legend);
+    assert!(parse_args(&args(&["color", "--legend", "--gamma", "2.2"])).unwrap().legend);
+    assert!(parse_args(&args(&["color", "--legend", "--color-mode", "histogram"])).is_err());
+    assert!(parse_args(&args(&["color", "--legend", "--stream"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color", "--batch", "views.txt"])).unwrap().batch, Some("views.txt".to_string()));
+    assert!(parse_args(&args(&["color", "--batch", "views.txt", "--zoom", "2"])).is_err());
+    assert!(parse_args(&args(&["color", "--batch", "views.txt", "--dive", "-0.75,0.1", "10", "1.1", "dive"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().svg, None);
+    assert_eq!(parse_args(&args(&["color", "--svg", "outline.svg"])).unwrap().svg, Some("outline.svg".to_string()));
+    assert!(parse_args(&args(&["color", "--svg", "outline.svg", "--dive", "-0.75,0.1", "10", "1.1", "dive"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().dump_iterations, None);
+    assert_eq!(parse_args(&args(&["color", "--dump-iterations", "iters.dat"])).unwrap().dump_iterations,
+               Some("iters.dat".to_string()));
+    assert!(parse_args(&args(&["color", "--dump-iterations"])).is_err());
+    assert!(parse_args(&args(&["color", "--dump-iterations", "iters.dat", "--tile", "10x10", "0,0"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().interior, InteriorColor::Black);
+    assert_eq!(parse_args(&args(&["color", "--interior", "final-norm"])).unwrap().interior,
+               InteriorColor::FinalNorm);
+    assert!(parse_args(&args(&["color", "--interior", "gray"])).is_err());
+    assert!(parse_args(&args(&["color", "--interior", "white", "--color-mode", "distance"])).is_err());
+
+    assert_eq!(parse_args(&args(&["color"])).unwrap().fractal, Fractal::Mandelbrot);
+    assert_eq!(parse_args(&args(&["color", "--fractal", "burning-ship"])).unwrap().fractal,
+               Fractal::BurningShip);
+    assert!(parse_args(&args(&["color", "--fractal", "julia"])).is_err());
+    assert_eq!(parse_args(&args(&["color", "--power", "2"])).unwrap().fractal, Fractal::Mandelbrot);
+    assert_eq!(parse_args(&args(&["color", "--power", "3"])).unwrap().fractal, Fractal::Multibrot(3));
+    assert!(parse_args(&args(&["color", "--power", "1"])).is_err());
+    assert!(parse_args(&args(&["color", "--power", "3", "--fractal", "burning-ship"])).is_err());
+    assert_eq!(parse_args(&args(&["color"])).unwrap().bailout_sqr(), 4.0);
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "distance"])).unwrap().bailout_sqr(), 16.0);
+    assert_eq!(parse_args(&args(&["color", "--color-mode", "potential"])).unwrap().bailout_sqr(), 1e6);
+    assert_eq!(parse_args(&args(&["color", "--bailout", "10"])).unwrap().bailout_sqr(), 100.0);
+    assert_eq!(parse_args(&args(&["color", "--bailout", "3", "--color-mode", "distance"])).unwrap()
+                   .bailout_sqr(), 9.0);
+    assert!(parse_args(&args(&["color", "--bailout", "1.5"])).is_err());
+    assert!(parse_args(&args(&["color", "--bailout", "inf"])).is_err());
+    assert!(parse_args(&args(&["color", "--fractal", "burning-ship",
+                                "--animate-julia", "-0.8,0.156", "0.285,0.01", "60", "out"])).is_err());
+
+    let options = parse_args(&args(&["color", "--animate-julia", "-0.8,0.156", "0.285,0.01", "60", "out"]))
+        .unwrap();
+    assert_eq!(options.animate_julia, Some(JuliaAnimation {
+        start: Complex::new(-0.8, 0.156),
+        end: Complex::new(0.285, 0.01),
+        frames: 60,
+        outdir: "out".to_string(),
+    }));
+    assert!(parse_args(&args(&["color", "--animate-julia", "-0.8,0.156", "0.285,0.01", "60"])).is_err());
+    assert!(parse_args(&args(&["color", "--animate-julia", "-0.8,0.156", "0.285,0.01", "0", "out"])).is_err());
+
+    let options = parse_args(&args(&["color", "--dive", "-0.75,0.1", "100", "1.1", "dive"])).unwrap();
+    assert_eq!(options.dive, Some(Dive {
+        center: Complex::new(-0.75, 0.1),
+        frames: 100,
+        factor: 1.1,
+        outdir: "dive".to_string(),
+    }));
+    assert!(parse_args(&args(&["color", "--dive", "-0.75,0.1", "100", "-1", "dive"])).is_err());
+
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_movie_{}.txt", std::process::id()));
+    let spec = path.to_str().unwrap();
+    std::fs::write(spec, "0 -0.5,0 1 100 classic\n10 -0.75,0.1 1000 800 hsv\n").unwrap();
+    let options = parse_args(&args(&["color", "--mandelbrot-movie-spec", spec, "240", "movie"]));
+    let with_dive = parse_args(&args(&["color", "--mandelbrot-movie-spec", spec, "240", "movie",
+                                       "--dive", "-0.75,0.1", "100", "1.1", "dive"]));
+    let no_frames = parse_args(&args(&["color", "--mandelbrot-movie-spec", spec, "0", "movie"]));
+    std::fs::remove_file(spec).unwrap();
+    let movie = options.unwrap().movie.unwrap();
+    assert_eq!((movie.keyframes.len(), movie.frames, movie.outdir.as_str()), (2, 240, "movie"));
+    assert!(with_dive.is_err());
+    assert!(no_frames.is_err());
+    assert!(parse_args(&args(&["color", "--mandelbrot-movie-spec", spec, "240", "movie"])).is_err());
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} [OPTIONS]", program);
+    eprintln!("       {} [OPTIONS] FILE SIZE UPPERLEFT LOWERRIGHT", program);
+    eprintln!("  FILE SIZE UPPERLEFT LOWERRIGHT --output FILE --size SIZE --region UPPERLEFT LOWERRIGHT と同じ");
+    eprintln!("  --config FILE                  TOML の設定ファイルを読む（同じ項目は引数のほうが優先）");
+    eprintln!("  --output FILE                  出力ファイル名（既定: mandelbrot.png。- なら PNG を標準出力に書く）");
+    eprintln!("  --max-iter N                   反復回数の上限（既定: 200）");
+    eprintln!("  --auto-iter                    反復回数の上限を倍率に合わせて増やす（--max-iter を指定したときは無効。最大 {}）",
+              AUTO_ITER_CEILING);
+    eprintln!("  --output-bits 8|16             16 なら反復回数を 16 ビットのグレースケール PNG で書き出す（既定: 8）");
+    eprintln!("  --estimate                     描画せずに、間引いた点を計算してかかる時間を見積もる");
+    eprintln!("  --find-detail                  描画せずに、粗く描いた中でいちばん入り組んだ場所を --center の形で表示する");
+    eprintln!("  --stats                        描画した後に反復回数の最小・最大・平均・内部の割合とヒストグラムを表示する");
+    eprintln!("                                 （--color-mode linear・histogram のとき。描いたときの反復回数から出す）");
+    eprintln!("  --svg FILE                     画像の代わりに集合の境界のピクセルを SVG で書き出す");
+    eprintln!("  --dump-iterations FILE         画像の代わりに反復回数を u32 のバイナリで書き出す");
+    eprintln!("  --size WIDTHxHEIGHT            出力画像の大きさ（既定: 1200x800）");
+    eprintln!("  --fractal mandelbrot|burning-ship");
+    eprintln!("                                 描くフラクタル（既定: mandelbrot。範囲を指定しなければそれぞれの全体図）");
+    eprintln!("  --power D                      z ← z^D + c のマルチブロ集合を描く（mandelbrot のみ。既定: 2）");
+    eprintln!("  --bailout R                    発散半径（2 以上。既定: distance は 4、potential・smooth は 1000、それ以外は 2）");
+    eprintln!("  --color-mode linear|histogram|orbit-trap|distance|potential|smooth|shaded");
+    eprintln!("                                 色付けの方式（既定: linear）");
+    eprintln!("  --palette classic|hsv          linear・histogram・potential・smooth で使う色（既定: classic）");
+    eprintln!("  --cycles N                     hsv の色相を N 周させる（既定: 1）");
+    eprintln!("  --palette-image FILE           参照画像の真ん中の行を左から右へ読んだ色で塗る（--palette の代わり）");
+    eprintln!("  --repeat-palette N             パレットを N 回繰り返して塗る（既定: 1）");
+    eprintln!("  --light AZ,ELEV                shaded の光源の方位角（右が 0°、反時計回り）と仰角（0..=90°）（既定: 135,45）");
+    eprintln!("  --interior black|final-norm|white");
+    eprintln!("                                 集合の内部の色（final-norm は最後の |z| の濃淡、linear のみ。既定: black）");
+    eprintln!("  --supersample N                1 ピクセルを N×N 点で平均する（既定: 1）");
+    eprintln!("  --color-gamma-space linear|srgb");
+    eprintln!("                                 --supersample・--jitter で色を平均する空間（既定: linear。srgb は縁が暗くなる）");
+    eprintln!("  --jitter K                     1 ピクセルをランダムな位置の K 点で平均する（linear のみ）");
+    eprintln!("  --seed S                       --jitter の位置を決める乱数の種（既定: 0。同じ種なら同じ画像）");
+    eprintln!("  --preset NAME                  有名な場所の中心・倍率・反復回数の上限を使う");
+    eprintln!("                                 （{}）", PRESETS.map(|preset| preset.name).join("|"));
+    eprintln!("  --center RE,IM                 表示範囲の中心");
+    eprintln!("  --zoom Z                       倍率（1.0 で従来の範囲全体）");
+    eprintln!("  --region UPPER_LEFT LOWER_RIGHT");
+    eprintln!("                                 表示範囲を左上・右下の角で指定する（例: -1.20,0.35 -1,0.20）");
+    eprintln!("  --keep-aspect                  表示範囲の縦横比を画像に合わせる");
+    eprintln!("  --tile COLxROW OFFX,OFFY       画像全体のうち (OFFX, OFFY) から COLxROW ピクセルだけを描く（linear のみ）");
+    eprintln!("  --pattern checker|coords       フラクタルの代わりに市松模様か座標の色を描く（座標の対応を確かめる用）");
+    eprintln!("  --stream                       {} 行ずつ描いては PNG に書き足す（大きな画像でもメモリを抑える。linear のみ）",
+              STREAM_BAND_ROWS);
+    eprintln!("  --precision f32|f64            計算に使う浮動小数点数の型（既定: f64）");
+    eprintln!("                                 high: 倍々精度（high-precision 機能つきのビルドのみ。linear のみ）");
+    eprintln!("  --progress                     描画の進捗を標準エラーに表示する");
+    eprintln!("  --simd                         4 点ずつまとめて計算する（linear・マンデルブロ集合・f64 のみ）");
+    eprintln!("  --auto-band                    試しに描いて、並列に描く帯の高さを速いものに選ぶ（linear のみ）");
+    eprintln!("  --verbose                      --auto-band で選んだ帯の高さなどを表示する");
+    eprintln!("  --animate-julia START END FRAMES OUTDIR");
+    eprintln!("                                 ジュリア集合の定数を START → END と動かした連番画像を書き出す");
+    eprintln!("  --dive CENTER FRAMES FACTOR OUTDIR");
+    eprintln!("                                 CENTER に向かって 1 フレームごとに FACTOR 倍ズームする連番画像を書き出す");
+    eprintln!("  --mandelbrot-movie-spec SPEC FRAMES OUTDIR");
+    eprintln!("                                 SPEC の「時刻 中心 倍率 反復回数 パレット」のキーフレームの間を滑らかに動く連番画像を書き出す");
+    eprintln!("  --gamma G                      書き出す前に RGB をガンマ補正する（既定: 2.2。中間色が明るくなる。1.0 なら補正しない）");
+    eprintln!("  --transparent-interior         RGBA の PNG で書き出し、集合の内部を透明にする（linear のみ）");
+    eprintln!("  --max-time SECONDS             この秒数を過ぎたら残りの帯を描かずに灰色で埋めて書き出す（linear のみ）");
+    eprintln!("  --tonemap reinhard|aces        smooth・potential の色を集合に近いほど明るくし、白く飛ばないように縮めて 8 ビットにする");
+    eprintln!("  --format png|exr               exr なら smooth・potential の色を丸めずに 32 ビット浮動小数点の OpenEXR で書き出す");
+    eprintln!("  --exposure E                   --tonemap で集合に近い点を明るくする度合い（既定: 1.0。明るさは 1 + E × 回数 / 反復回数の上限）");
+    eprintln!("  --exploit-symmetry             範囲が実軸に対して対称なら上半分だけ計算し、下半分は写す（linear のみ）");
+    eprintln!("  --preview                      先に 1/8 の大きさで描いて OUTFILE に .preview.png を付けた名前で書き出す");
+    eprintln!("  --crop X,Y,W,H                 描いた画像から左上 (X, Y)、大きさ W×H の長方形だけを書き出す");
+    eprintln!("  --dither                       色を 8 ビットに丸めるときに組織的ディザをかけ、段差を目立たなくする");
+    eprintln!("                                 （histogram・potential・smooth のみ）");
+    eprintln!("  --estimate-area SAMPLES        描画せずに、SAMPLES 個のランダムな点から集合の面積を推定する（--seed で点が決まる）");
+    eprintln!("  --verify                       描画せずに、決まった点の反復回数が期待どおりか確かめる");
+    eprintln!("  --compare BASELINE             書き出さずに、描いた画像を BASELINE と比べ、違うピクセルがあれば失敗する");
+    eprintln!("  --tolerance N                  --compare でチャンネルの差が N 以下なら同じとみなす（既定: 0）");
+    eprintln!("  --sample-point RE,IM           描画せずに、点 RE+IMi の --fractal の軌道の z と |z|² を 1 回ずつ表示する");
+    eprintln!("  --legend                       画像の右にパレットと反復回数の目盛りの凡例を足す（linear のみ）");
+    eprintln!("  --batch MANIFEST               MANIFEST の各行（OUTFILE BOUNDS UPPERLEFT LOWERRIGHT）を 1 枚ずつ描く");
+}
+
+fn main() -> Result<(), RenderError> {
+    // 段階（反復回数の計算・色付け・書き出しなど）ごとにかかった時間を、RUST_LOG=info で標準エラーに出す
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let options = match parse_args(&args) {
+        Ok(options) => resolve_palette(options)?,
+        Err(message) => {
+            eprintln!("{}", message);
+            print_usage(&args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    if options.verify {
+        let results = verify_reference_points();
+        let failed = results.iter().filter(|(_, expected, actual)| expected != actual).count();
+        for (point, expected, actual) in results {
+            let status = if expected == actual { "ok" } else { "FAILED" };
+            println!("{:>6} {}: 期待 {} 回 / 計算 {} 回", status, point, expected, actual);
+        }
+        if failed > 0 {
+            eprintln!("{} 点が期待どおりではありません", failed);
+            std::process::exit(1);
+        }
+        println!("{} 点すべて期待どおりです", REFERENCE_POINTS.len());
+        return Ok(());
+    }
+
+    if let Some(seed) = options.fuzz_check {
+        let failures = fuzz_check(seed, FUZZ_CASES, FUZZ_MAX_ITER);
+        for failure in &failures {
+            eprintln!("{}", failure);
+        }
+        if !failures.is_empty() {
+            eprintln!("{} / {} 通りで問題がありました（seed {}）", failures.len(), FUZZ_CASES, seed);
+            std::process::exit(1);
+        }
+        println!("{} 通りすべて問題ありません（seed {}）", FUZZ_CASES, seed);
+        return Ok(());
+    }
+
+    if let Some(samples) = options.estimate_area {
+        let estimate = estimate_area(samples, AREA_MAX_ITER, options.seed);
+        let (low, high) = estimate.confidence_interval();
+        println!("集合の面積の推定値: {:.4} ± {:.4}（95% 信頼区間 {:.4}..{:.4}）",
+                 estimate.area, high - estimate.area, low, high);
+        println!("{} 点のうち {} 点が内部（反復回数の上限 {} 回、seed {}）",
+                 estimate.samples, estimate.inside, AREA_MAX_ITER, options.seed);
+        return Ok(());
+    }
+
+    if let Some(c) = options.sample_point {
+        let bailout = options.bailout.unwrap_or(DEFAULT_BAILOUT);
+        let (orbit, count) = sample_orbit(options.fractal, c, options.max_iter, bailout * bailout);
+        for (i, z) in orbit.iter().enumerate() {
+            println!("{:>6} z = {} |z|² = {}", i, z, z.norm_sqr());
+        }
+        if count < options.max_iter {
+            println!("{}: {} 回で発散（|z| > {}）", c, count, bailout);
+        } else {
+            println!("{}: 上限 {} 回まで発散しない", c, options.max_iter);
+        }
+        return Ok(());
+    }
+
+    let bounds = options.bounds;
+    let max_iter = options.max_iter;
+    let metadata = |region: &Region, max_iter| image_metadata(&options, region, max_iter);
+
+    if let Some(animation) = &options.animate_julia {
+        // ジュリア集合は原点を中心に描く
+        let region = options.region.unwrap_or_else(|| Region::from_center_zoom(
+            bounds, options.center.unwrap_or_default(), options.zoom.unwrap_or(1.0)));
+
+        std::fs::create_dir_all(&animation.outdir)?;
+        for index in 0..animation.frames {
+            let fractal = Fractal::Julia(animation.constant(index));
+            let pixels = render_image(&options, bounds, &region, None, max_iter, fractal);
+            let filename = frame_filename(&animation.outdir, index);
+            write_image_with_metadata(&filename, &pixels, &metadata(&region, max_iter))?;
+            println!("{} を生成しました", filename);
         }
+
+        println!("{} フレームを生成しました！", animation.frames);
+        return Ok(());
     }
 
-    write_image("mandelbrot.png", &pixels, bounds).unwrap();
-    let elapsed = start.elapsed(); // ★ 経過時間 
-    println!( "mandelbrot.png を生成しました！\n処理時間: {:.3} 秒", elapsed.as_secs_f64() );
+    if let Some(dive) = &options.dive {
+        let start_zoom = options.zoom.unwrap_or(1.0);
+
+        std::fs::create_dir_all(&dive.outdir)?;
+        for index in 0..dive.frames {
+            let zoom = dive.zoom(start_zoom, index);
+            let region = Region::from_center_zoom(bounds, dive.center, zoom);
+            let frame_iter = if options.auto_iter {
+                auto_iterations(max_iter, zoom)
+            } else {
+                iterations_for_zoom(max_iter, zoom)
+            };
+            let pixels = render_image(&options, bounds, &region, Some((dive.center, zoom)), frame_iter,
+                                      options.fractal);
+            let filename = frame_filename(&dive.outdir, index);
+            write_image_with_metadata(&filename, &pixels, &metadata(&region, frame_iter))?;
+            println!("{} を生成しました（zoom {:.3e}）", filename, zoom);
+        }
+
+        println!("{} フレームを生成しました！", dive.frames);
+        return Ok(());
+    }
+
+    if let Some(movie) = &options.movie {
+        std::fs::create_dir_all(&movie.outdir)?;
+        for index in 0..movie.frames {
+            let frame = movie.frame(index);
+            let region = Region::from_center_zoom(bounds, frame.center, frame.zoom);
+            let frame_options = Options { palette: frame.palette, ..options.clone() };
+            let pixels = render_image(&frame_options, bounds, &region, Some((frame.center, frame.zoom)),
+                                      frame.max_iter, options.fractal);
+            let filename = frame_filename(&movie.outdir, index);
+            write_image_with_metadata(&filename, &pixels, &metadata(&region, frame.max_iter))?;
+            println!("{} を生成しました（zoom {:.3e}）", filename, frame.zoom);
+        }
+
+        println!("{} フレームを生成しました！", movie.frames);
+        return Ok(());
+    }
+
+    if let Some(manifest) = &options.batch {
+        return render_batch(&options, manifest);
+    }
+
+    render_single(&options)
+}
+
+/// 書き出す画像に埋め込む描画条件（`--tile` のときはタイルの大きさ・範囲）
+fn image_metadata(options: &Options, region: &Region, max_iter: u32) -> ImageMetadata {
+    match options.tile {
+        Some(tile) => ImageMetadata { bounds: tile.size, region: tile.region(options.bounds, region), max_iter },
+        None => ImageMetadata { bounds: options.bounds, region: *region, max_iter },
+    }
 }
 
-/// ピクセル座標 → 複素平面上の点
-fn pixel_to_point(
+/// `--batch` のマニフェストの各行を 1 枚ずつ描く
+///
+/// 1 行ごとに `options` の出力ファイル名・大きさ・範囲を置き換えて `render_single` を呼ぶ。
+/// 書き方の誤りや書き出しの失敗はその行だけ報告して次の行に進む。
+fn render_batch(options: &Options, manifest: &str) -> Result<(), RenderError> {
+    let text = std::fs::read_to_string(manifest)?;
+    let (mut rendered, mut failed) = (0, 0);
+    for (number, job) in parse_manifest(&text) {
+        let _span = tracing::info_span!("batch_job", manifest, line = number).entered();
+        let started = std::time::Instant::now();
+        let result = job.and_then(|job| {
+            let options = Options {
+                output: job.output,
+                bounds: job.bounds,
+                region: Some(job.region),
+                ..options.clone()
+            };
+            render_single(&options)
+        });
+        match result {
+            Ok(()) => {
+                rendered += 1;
+                eprintln!("{}:{}: {:.2?}", manifest, number, started.elapsed());
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!("{}:{}: {}", manifest, number, err);
+            }
+        }
+    }
+
+    println!("{} 枚を生成しました（失敗 {} 件）", rendered, failed);
+    Ok(())
+}
+
+/// `options.output` に書き出したことを知らせる
+///
+/// `--output -` で画像を標準出力に書いたときは、画像のバイト列に混ざらないように標準エラーに出す。
+fn report_written(options: &Options) {
+    if options.output == STDOUT_FILENAME {
+        eprintln!("標準出力に書き出しました！");
+    } else {
+        println!("{} を生成しました！", options.output);
+    }
+}
+
+/// 1 枚だけ描いて `options.output` に書き出す（`--dump-iterations` などの出力もここ）
+fn render_single(options: &Options) -> Result<(), RenderError> {
+    let bounds = options.bounds;
+    let max_iter = options.max_iter;
+    let metadata = |region: &Region, max_iter| image_metadata(options, region, max_iter);
+
+    let preset = preset_region(options.fractal);
+    // 中心と倍率で指定されたときは、--precision high で範囲を作り直せるように覚えておく
+    let center_zoom = match options.region {
+        None if options.center.is_some() || options.zoom.is_some() => {
+            Some((options.center.unwrap_or(preset.center()), options.zoom.unwrap_or(1.0)))
+        }
+        _ => None,
+    };
+    let region = match (options.region, center_zoom) {
+        (Some(region), _) => region,
+        (None, Some((center, zoom))) => Region::from_center_zoom(bounds, center, zoom),
+        (None, None) => preset,
+    };
+    let region = if options.keep_aspect { region.aspect_correct(bounds) } else { region };
+    let max_iter = if options.auto_iter { auto_iterations(max_iter, region_zoom(bounds, &region)) } else { max_iter };
+
+    if options.estimate {
+        // スーパーサンプリングでは N×N 倍の点を計算する
+        let n = options.supersample;
+        let sample_bounds = (bounds.0 * n, bounds.1 * n);
+        let estimate = estimate_render(sample_bounds, &region, max_iter, options.bailout_sqr(), options.fractal,
+                                       ESTIMATE_STRIDE);
+        let threads = rayon::current_num_threads();
+        println!("推定描画時間: {:.2?}（{} 点の平均反復回数 {:.1} 回から {} 点 / {} スレッドで見積もり）",
+                 estimate.duration(threads), estimate.sampled, estimate.mean_iterations, estimate.pixels,
+                 threads);
+        return Ok(());
+    }
+
+    if options.find_detail {
+        // プレビューと同じ大きさで粗く描き、いちばん入り組んだタイルの中心をそのまま --center に使える形で表示する
+        let coarse = preview_bounds(bounds);
+        let iters = compute_iterations_counted::<f64>(coarse, &region, max_iter, options.bailout_sqr(),
+                                                      options.fractal, &Default::default());
+        if let Some(center) = find_detail(&iters, &region, DETAIL_TILE) {
+            println!("--center {},{}", center.re, center.im);
+        }
+        return Ok(());
+    }
+
+    // 最適な帯の高さは max_iter やコア数で変わるので、この画像の真ん中を試しに描いて選ぶ
+    let tuned;
+    let options = if options.auto_band {
+        let n = options.supersample;
+        let band_rows = tune_band_rows((bounds.0 * n, bounds.1 * n), &region, max_iter, options.bailout_sqr(),
+                                       options.fractal);
+        if options.verbose {
+            eprintln!("帯の高さ: {} 行", band_rows);
+        }
+        tuned = Options { band_rows, ..options.clone() };
+        &tuned
+    } else {
+        options
+    };
+
+    // 範囲や色が合っているかすぐ確かめられるように、本番の前に小さく描いておく
+    if options.preview {
+        let preview = preview_bounds(bounds);
+        let _span = tracing::info_span!("preview", width = preview.0, height = preview.1).entered();
+        let pixels = render_image(options, preview, &region, center_zoom, max_iter, options.fractal);
+        let filename = preview_filename(&options.output);
+        write_image(&filename, &pixels, preview)?;
+        println!("{} を生成しました！（{}x{}）", filename, preview.0, preview.1);
+    }
+
+    let iterations = || with_progress(options.progress, bounds.1, |rows_done| match options.precision {
+        Precision::F32 => compute_iterations_counted::<f32>(bounds, &region, max_iter, options.bailout_sqr(),
+                                                            options.fractal, rows_done),
+        Precision::F64 => compute_iterations_counted::<f64>(bounds, &region, max_iter, options.bailout_sqr(),
+                                                            options.fractal, rows_done),
+        #[cfg(feature = "high-precision")]
+        Precision::High => compute_iterations_counted_as(bounds, &precise_region(bounds, &region, center_zoom),
+                                                         max_iter, options.bailout_sqr(), options.fractal,
+                                                         rows_done),
+    });
+
+    if let Some(filename) = &options.dump_iterations {
+        let iters = iterations();
+        let counts: Vec<u32> = iters.data.iter().map(|&iter| iter as u32).collect();
+        write_iterations(filename, &counts, bounds)?;
+        println!("{} を生成しました！", filename);
+        if options.stats {
+            print_stats(&iters.stats(STATS_BINS), max_iter);
+        }
+        return Ok(());
+    }
+
+    if let Some(filename) = &options.svg {
+        let iters = iterations();
+        write_svg(filename, bounds, &region, &extract_boundary(&iters))?;
+        println!("{} を生成しました！", filename);
+        if options.stats {
+            print_stats(&iters.stats(STATS_BINS), max_iter);
+        }
+        return Ok(());
+    }
+
+    if options.output_bits == 16 {
+        let iters = iterations();
+        write_image_gray16_with_metadata(&options.output, &iters.to_gray16(), &metadata(&region, max_iter))?;
+        report_written(options);
+        if options.stats {
+            print_stats(&iters.stats(STATS_BINS), max_iter);
+        }
+        return Ok(());
+    }
+
+    if options.transparent_interior {
+        let iters = iterations();
+        write_image_rgba_with_metadata(&options.output, &iters.colorize_rgba(&options.palette),
+                                       &metadata(&region, max_iter))?;
+        report_written(options);
+        if options.stats {
+            print_stats(&iters.stats(STATS_BINS), max_iter);
+        }
+        return Ok(());
+    }
+
+    if options.exr {
+        let pixels = with_progress(options.progress, bounds.1, |rows_done| {
+            render_exact(options, bounds, &region, max_iter, options.fractal, rows_done)
+        });
+        write_image_exr(&options.output, &pixels, bounds)?;
+        report_written(options);
+        return Ok(());
+    }
+
+    if let Some(budget) = options.max_time {
+        let deadline = std::time::Instant::now() + budget;
+        let (mut pixels, rendered) = with_progress(options.progress, bounds.1, |rows_done| {
+            let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+            let rendered = match options.precision {
+                Precision::F32 => render_budgeted_counted::<f32>(&mut pixels, bounds, &region, max_iter,
+                                                                 options.bailout_sqr(), options.fractal,
+                                                                 &options.palette, options.interior, deadline,
+                                                                 rows_done, &AtomicBool::new(false)),
+                // 倍々精度とは組み合わせない（`parse_args` で確かめてある）
+                _ => render_budgeted_counted::<f64>(&mut pixels, bounds, &region, max_iter,
+                                                    options.bailout_sqr(), options.fractal, &options.palette,
+                                                    options.interior, deadline, rows_done, &AtomicBool::new(false)),
+            };
+            (pixels, rendered)
+        });
+        if options.gamma() != 1.0 {
+            apply_gamma(&mut pixels, options.gamma());
+        }
+        write_image_with_metadata(&options.output, &pixels, &metadata(&region, max_iter))?;
+        report_written(options);
+        // 統計のために全体を計算し直すと締め切りの意味がなくなるので、描けた割合だけを表示する
+        if options.stats {
+            eprintln!("描画できた割合: {:.1}%（{} / {} 行）", rendered as f64 * 100.0 / bounds.1.max(1) as f64,
+                     rendered, bounds.1);
+        }
+        return Ok(());
+    }
+
+    if options.stream {
+        write_png_streamed(&options.output, bounds, Some(&metadata(&region, max_iter)), STREAM_BAND_ROWS,
+                           |band, first_row| {
+            let tile = Tile { size: (bounds.0, band.len() / (bounds.0 * 3)), offset: (0, first_row) };
+            band.copy_from_slice(&render_tile_image(options, bounds, &tile, &region, center_zoom, max_iter,
+                                                    options.fractal));
+        })?;
+        report_written(options);
+        return Ok(());
+    }
+
+    // 統計をとるときは、描画で集めた反復回数から色を付けて、同じ反復回数から統計を出す
+    let (pixels, iters) = if options.stats {
+        let iters = iterations();
+        (colorize_iterations(options, &iters), Some(iters))
+    } else {
+        (render_image(options, bounds, &region, center_zoom, max_iter, options.fractal), None)
+    };
+
+    if let Some(baseline) = &options.compare {
+        let (expected, expected_bounds) = read_image(baseline)?;
+        let rendered_bounds = metadata(&region, max_iter).bounds;
+        if expected_bounds != rendered_bounds {
+            return Err(format!("{} is {}x{}, but the rendered image is {}x{}", baseline, expected_bounds.0,
+                               expected_bounds.1, rendered_bounds.0, rendered_bounds.1).into());
+        }
+        let diff = diff_pixels(&pixels, &expected, options.tolerance)?;
+        println!("{}: {} / {} ピクセルが違います（チャンネルの差の最大 {}、許容 {}）", baseline, diff.differing,
+                 rendered_bounds.0 * rendered_bounds.1, diff.max_delta, options.tolerance);
+        if diff.differing > 0 {
+            return Err(format!("{} pixels differ from {}", diff.differing, baseline).into());
+        }
+        return Ok(());
+    }
+
+    if options.legend {
+        // 凡例の分だけ幅が広がり範囲と合わなくなるので、描画条件は埋め込まない
+        let mut pixels = add_legend(&pixels, bounds, &options.palette, max_iter);
+        if options.gamma() != 1.0 {
+            let width = bounds.0 + LEGEND_WIDTH;
+            for row in pixels.chunks_mut(width * 3) {
+                apply_gamma(&mut row[bounds.0 * 3..], options.gamma());
+            }
+        }
+        write_image(&options.output, &pixels, (bounds.0 + LEGEND_WIDTH, bounds.1))?;
+    } else if let Some(area) = &options.crop {
+        // 切り出した部分の範囲を描画条件として埋め込む
+        let metadata = ImageMetadata { bounds: area.size, region: area.region(bounds, &region), max_iter };
+        write_image_with_metadata(&options.output, &crop(&pixels, bounds, area)?, &metadata)?;
+    } else {
+        write_image_with_metadata(&options.output, &pixels, &metadata(&region, max_iter))?;
+    }
+    report_written(options);
+    if let Some(iters) = iters {
+        print_stats(&iters.stats(STATS_BINS), max_iter);
+    }
+
+    Ok(())
+}
+
+/// `--verify` で反復回数を確かめる点と、`VERIFY_MAX_ITER` 回までで期待する反復回数
+///
+/// 上限に達するのは内部の点（原点、周期 2 の -1、境界上の i・-2・カスプ 0.25 など）。
+/// 境界に近い点ほど反復のループの小さな違い（丸めの順序など）で回数が変わるので、回帰を見つけやすい。
+const REFERENCE_POINTS: [((f64, f64), u32); 12] = [
+    ((0.0, 0.0), VERIFY_MAX_ITER),
+    ((-1.0, 0.0), VERIFY_MAX_ITER),
+    ((0.0, 1.0), VERIFY_MAX_ITER),
+    ((-2.0, 0.0), VERIFY_MAX_ITER),
+    ((0.25, 0.0), VERIFY_MAX_ITER),
+    ((2.0, 2.0), 1),
+    ((1.0, 0.0), 3),
+    ((0.5, 0.0), 5),
+    ((0.26, 0.0), 30),
+    ((-0.75, 0.1), 33),
+    ((-0.1, 0.65), 75),
+    ((-0.75, 0.01), 315),
+];
+
+/// `--verify` の反復回数の上限
+const VERIFY_MAX_ITER: u32 = 1000;
+
+/// `REFERENCE_POINTS` の各点を `escape_time` で計算し、(点, 期待する回数, 計算した回数) を並べる
+fn verify_reference_points() -> Vec<(Complex<f64>, u32, u32)> {
+    REFERENCE_POINTS
+        .iter()
+        .map(|&((re, im), expected)| {
+            let point = Complex::new(re, im);
+            (point, expected, escape_time(point, VERIFY_MAX_ITER, DEFAULT_BAILOUT * DEFAULT_BAILOUT))
+        })
+        .collect()
+}
+
+#[test]
+fn test_reference_points() {
+    for (point, expected, actual) in verify_reference_points() {
+        assert_eq!(actual, expected, "{}", point);
+    }
+}
+
+/// `--fuzz-check` で試す条件の数
+const FUZZ_CASES: usize = 500;
+
+/// `--fuzz-check` の反復回数の上限（各条件の上限は 1..=これ からランダムに選ぶ）
+const FUZZ_MAX_ITER: u32 = 256;
+
+/// `--fuzz-check` で描く 1 通りの条件（`parse_args` が受け付ける範囲の値だけを作る）
+#[derive(Clone, Debug, PartialEq)]
+struct FuzzCase {
     bounds: (usize, usize),
-    pixel: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) -> Complex<f64> {
-    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    region: Region,
+    max_iter: u32,
+    bailout_sqr: f64,
+    fractal: Fractal,
+    palette: Palette,
+}
+
+/// `seed` と通し番号 `index` だけで決まる条件（同じ seed なら何度でも同じ条件を作る）
+fn fuzz_case(seed: u64, index: u64, max_iter: u32) -> FuzzCase {
+    let mut draws = 0;
+    let mut random = || {
+        draws += 1;
+        splitmix64(seed ^ splitmix64(index * 64 + draws))
+    };
+    let mut unit = || (random() >> 11) as f64 / (1u64 << 53) as f64;
+
+    // 1x1 や細長い画像、拡大しすぎて角の座標が丸められる寸前の範囲も混ぜる
+    let bounds = (1 + (unit() * 24.0) as usize, 1 + (unit() * 24.0) as usize);
+    let center = Complex::new(unit() * 5.0 - 2.5, unit() * 5.0 - 2.5);
+    let zoom = 10f64.powf(unit() * 12.0);
+    let region = if unit() < 0.5 {
+        Region::from_center_zoom(bounds, center, zoom)
+    } else {
+        // 縦横比の合わない範囲を aspect_correct で直す
+        let size = |unit: f64| (0.1 + unit * 3.0) / zoom;
+        let (width, height) = (size(unit()), size(unit()));
+        let corners = Region {
+            upper_left: center + Complex::new(-width, height),
+            lower_right: center + Complex::new(width, -height),
+        };
+        corners.aspect_correct(bounds)
+    };
+    let fractal = match (unit() * 4.0) as u32 {
+        0 => Fractal::Mandelbrot,
+        1 => Fractal::BurningShip,
+        2 => Fractal::Multibrot(2 + (unit() * 4.0) as u32),
+        _ => Fractal::Julia(Complex::new(unit() * 4.0 - 2.0, unit() * 4.0 - 2.0)),
+    };
+    let bailout = 2.0 + unit() * 998.0;
+    let palette = if unit() < 0.5 {
+        Palette::Repeat { palette: Box::new(Palette::Classic), times: 1 + (unit() * 4.0) as u32 }
+    } else {
+        Palette::Hsv { cycles: (0.1 + unit() * 8.0) as f32 }
+    };
+
+    FuzzCase {
+        bounds,
+        region,
+        max_iter: 1 + (unit() * max_iter as f64) as u32,
+        bailout_sqr: bailout * bailout,
+        fractal,
+        palette,
+    }
+}
+
+/// `fuzz_case` の条件で小さな画像をいくつかの方式で描き、パニックや範囲外の値を確かめる
+///
+/// 問題のなかった条件は `Ok`、あった条件は何が起きたかを `Err` で返す。
+fn fuzz_render(case: &FuzzCase) -> Result<(), String> {
+    let FuzzCase { bounds, region, max_iter, bailout_sqr, fractal, ref palette } = *case;
+    let pixel_count = bounds.0 * bounds.1;
+    let check = |ok: bool, what: &str| if ok { Ok(()) } else { Err(what.to_string()) };
 
-    Complex::new(
-        upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-        upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
-    )
+    let render = || -> Result<(), String> {
+        let rows_done = std::sync::atomic::AtomicUsize::new(0);
+        let iters = compute_iterations_counted::<f64>(bounds, &region, max_iter, bailout_sqr, fractal,
+                                                      &rows_done);
+        check(iters.data.len() == pixel_count, "iteration buffer has the wrong length")?;
+        check(iters.data.iter().all(|&iter| (0.0..=max_iter as f64).contains(&iter)),
+              "iteration count out of range")?;
+        check(rows_done.into_inner() == bounds.1, "progress counter does not match the row count")?;
+
+        let f32_iters = compute_iterations_counted::<f32>(bounds, &region, max_iter, bailout_sqr, fractal,
+                                                          &Default::default());
+        check(f32_iters.data.iter().all(|&iter| (0.0..=max_iter as f64).contains(&iter)),
+              "f32 iteration count out of range")?;
+
+        check(iters.colorize(palette).len() == pixel_count * 3, "linear colors have the wrong length")?;
+        check(iters.colorize_histogram(palette).len() == pixel_count * 3,
+              "histogram colors have the wrong length")?;
+        let stats = iters.stats(16);
+        let summary = [stats.min, stats.max, stats.mean, stats.interior_fraction];
+        check(summary.iter().all(|value| value.is_finite()), "statistics are not finite")?;
+
+        let mut pixels = vec![0u8; pixel_count * 3];
+        render_with(&mut pixels, bounds, &region, |point| {
+            smooth_color(fractal.escape_time_smooth(point, max_iter, bailout_sqr), max_iter, palette)
+        });
+        let smooth = [region.upper_left, region.center(), region.lower_right]
+            .map(|point| fractal.escape_time_smooth(point, max_iter, bailout_sqr));
+        check(smooth.iter().flatten().all(|value| value.is_finite()),
+              "smooth iteration count is not finite")?;
+        Ok(())
+    };
+
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(render)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(format!("panicked: {}", message))
+        }
+    }
 }
 
-/// マンデルブロ集合の発散判定
-fn escape_time(c: Complex<f64>, max_iter: u32) -> u32 {
-    let mut z = Complex::new(0.0, 0.0);
+/// `seed` から `cases` 通りの条件を作って描き、問題のあった条件を並べる（`--fuzz-check`）
+fn fuzz_check(seed: u64, cases: usize, max_iter: u32) -> Vec<String> {
+    (0..cases as u64)
+        .filter_map(|index| {
+            let case = fuzz_case(seed, index, max_iter);
+            fuzz_render(&case).err().map(|err| format!("case {} ({:?}): {}", index, case, err))
+        })
+        .collect()
+}
+
+#[test]
+fn test_fuzz_check() {
+    assert_eq!(fuzz_check(42, 40, 32), Vec::<String>::new());
+
+    // 同じ seed なら同じ条件、違う seed なら違う条件
+    assert_eq!(fuzz_case(42, 7, 32), fuzz_case(42, 7, 32));
+    assert_ne!(fuzz_case(42, 7, 32), fuzz_case(43, 7, 32));
+    assert_ne!(fuzz_case(42, 7, 32), fuzz_case(42, 8, 32));
+    for index in 0..40 {
+        let case = fuzz_case(42, index, 32);
+        assert!(case.bounds.0 > 0 && case.bounds.1 > 0);
+        assert!((1..=32).contains(&case.max_iter));
+    }
+}
+
+/// `--sample-point` の点 `point` から始まる `fractal` の軌道 z₀, z₁, … と反復回数
+///
+/// `Fractal::escape_time` と同じループで、|z|² が `bailout_sqr` を超えた z（発散した点）まで含める。
+fn sample_orbit(
+    fractal: Fractal,
+    point: Complex<f64>,
+    max_iter: u32,
+    bailout_sqr: f64,
+) -> (Vec<Complex<f64>>, u32) {
+    let (mut z, c) = fractal.initial(point);
+    let mut orbit = Vec::new();
 
     for i in 0..max_iter {
-        if z.norm_sqr() > 4.0 {
-            return i;
+        orbit.push(z);
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr || !norm_sqr.is_finite() {
+            return (orbit, i);
         }
-        z = z * z + c;
+        z = fractal.step(z, c);
     }
-    max_iter
+    (orbit, max_iter)
 }
 
-/// 反復回数 → RGB 色変換（滑らかなグラデーション）
-fn color_map(iter: u32, max_iter: u32) -> [u8; 3] {
-    if iter >= max_iter {
-        return [0, 0, 0]; // 内部は緑
+#[test]
+fn test_sample_orbit() {
+    let bailout_sqr = DEFAULT_BAILOUT * DEFAULT_BAILOUT;
+
+    // c = 0: ずっと原点にとどまる
+    let (orbit, count) = sample_orbit(Fractal::Mandelbrot, Complex::new(0.0, 0.0), 50, bailout_sqr);
+    assert_eq!(count, 50);
+    assert_eq!(orbit.len(), 50);
+    assert!(orbit.iter().all(|z| *z == Complex::new(0.0, 0.0)));
+
+    // c = 1: 0 → 1 → 2 → 5。|z₂|² = 4 はまだ発散半径の内側で、|z₃|² = 25 で発散する
+    let c = Complex::new(1.0, 0.0);
+    let (orbit, count) = sample_orbit(Fractal::Mandelbrot, c, 50, bailout_sqr);
+    assert_eq!(orbit, vec![Complex::new(0.0, 0.0), Complex::new(1.0, 0.0),
+                           Complex::new(2.0, 0.0), Complex::new(5.0, 0.0)]);
+    assert_eq!(count, 3);
+    assert_eq!(count, escape_time(c, 50, DEFAULT_BAILOUT * DEFAULT_BAILOUT));
+
+    // ジュリア集合では点が z₀ になり、c は固定の定数
+    let julia = Fractal::Julia(Complex::new(1.0, 0.0));
+    let (orbit, count) = sample_orbit(julia, Complex::new(0.0, 0.0), 50, bailout_sqr);
+    assert_eq!(orbit, vec![Complex::new(0.0, 0.0), Complex::new(1.0, 0.0),
+                           Complex::new(2.0, 0.0), Complex::new(5.0, 0.0)]);
+    assert_eq!(count, 3);
+
+    // どのフラクタルでも回数は描画と同じ
+    let point = Complex::new(-0.6, 0.45);
+    for fractal in [Fractal::Mandelbrot, Fractal::BurningShip, Fractal::Multibrot(3), julia] {
+        let (orbit, count) = sample_orbit(fractal, point, 200, bailout_sqr);
+        assert_eq!(count, fractal.escape_time(point, 200, bailout_sqr), "{:?}", fractal);
+        assert_eq!(orbit.len() as u32, count.min(199) + 1, "{:?}", fractal);
+    }
+}
+
+/// `--preview` で縦横を何分の 1 にするか
+const PREVIEW_FACTOR: usize = 8;
+
+/// `--preview` の画像の大きさ（`bounds` を `PREVIEW_FACTOR` で割って切り上げるので、0 にはならない）
+fn preview_bounds(bounds: (usize, usize)) -> (usize, usize) {
+    (bounds.0.div_ceil(PREVIEW_FACTOR), bounds.1.div_ceil(PREVIEW_FACTOR))
+}
+
+/// `--preview` の出力ファイル名（`output` の後ろに `.preview.png` を付ける）
+fn preview_filename(output: &str) -> String {
+    format!("{}.preview.png", output)
+}
+
+#[test]
+fn test_preview() {
+    assert_eq!(preview_bounds((1200, 800)), (150, 100));
+    assert_eq!(preview_bounds((1201, 799)), (151, 100));
+    assert_eq!(preview_bounds((1, 7)), (1, 1));
+    assert_eq!(preview_bounds((9, 16)), (2, 2));
+
+    assert_eq!(preview_filename("mandelbrot.png"), "mandelbrot.png.preview.png");
+    assert_eq!(preview_filename("out/view.ppm"), "out/view.ppm.preview.png");
+    assert_eq!(preview_filename("view"), "view.preview.png");
+}
+
+/// `--stream` で 1 度に描く行数
+const STREAM_BAND_ROWS: usize = 64;
+
+/// `--stats` のヒストグラムの区間の数
+const STATS_BINS: usize = 10;
+
+/// `--stats` の統計を標準エラーに表示する（ヒストグラムは最も多い区間を 40 文字の棒にする）
+fn print_stats(stats: &IterStats, max_iter: u32) {
+    eprintln!("反復回数（発散した点）: 最小 {} / 最大 {} / 平均 {:.1}（上限 {}）",
+              stats.min, stats.max, stats.mean, max_iter);
+    eprintln!("内部の点: {:.1}%", stats.interior_fraction * 100.0);
+
+    let bins = stats.histogram.len();
+    let largest = stats.histogram.iter().copied().max().unwrap_or(0).max(1);
+    for (i, &count) in stats.histogram.iter().enumerate() {
+        let (start, end) = (max_iter as usize * i / bins, max_iter as usize * (i + 1) / bins);
+        let bar = "#".repeat(count * 40 / largest);
+        eprintln!("{:>6}..{:<6} {:>9} {}", start, end, count, bar);
+    }
+}
+
+/// オプション（色付けの方式・スーパーサンプリング・進捗表示）に従って 1 枚分の RGB を描画する
+///
+/// `--tile` のときはタイルの部分だけを描くので、大きさは `bounds` ではなくタイルの大きさになる。
+/// `center_zoom` は `region` を中心と倍率で作ったときのもの（`--precision high` で範囲を作り直す）。
+fn render_image(
+    options: &Options,
+    bounds: (usize, usize),
+    region: &Region,
+    center_zoom: Option<(Complex<f64>, f64)>,
+    max_iter: u32,
+    fractal: Fractal,
+) -> Vec<u8> {
+    let tile = options.tile.unwrap_or(Tile::full(bounds));
+    render_tile_image(options, bounds, &tile, region, center_zoom, max_iter, fractal)
+}
+
+/// `render_image` と同じだが、`--tile` の代わりに `tile` の部分を描く（`--stream` の帯など）
+fn render_tile_image(
+    options: &Options,
+    bounds: (usize, usize),
+    tile: &Tile,
+    region: &Region,
+    #[cfg_attr(not(feature = "high-precision"), allow(unused_variables))] center_zoom: Option<(Complex<f64>, f64)>,
+    max_iter: u32,
+    fractal: Fractal,
+) -> Vec<u8> {
+    // テストパターンは出力のピクセルごとに描く（スーパーサンプリングしない）
+    if let Some(pattern) = options.pattern {
+        let mut pixels = vec![0u8; tile.size.0 * tile.size.1 * 3];
+        render_pattern(&mut pixels, bounds, region, tile, pattern);
+        return pixels;
+    }
+
+    // スーパーサンプリング時は N 倍の解像度で計算してから縮小する
+    let n = options.supersample;
+    let sample_bounds = (bounds.0 * n, bounds.1 * n);
+    let sample_tile = tile.scaled(n);
+
+    // SIMD 版はマンデルブロ集合を f64 で linear に色付けする場合だけ使える（タイル・内部の色は未対応）
+    let simd = options.simd
+        && *tile == Tile::full(bounds)
+        && options.jitter.is_none()
+        && options.interior == InteriorColor::Black
+        && fractal == Fractal::Mandelbrot
+        && options.color_mode == ColorMode::Linear
+        && options.precision == Precision::F64;
+
+    let pixels = match options.precision {
+        _ if simd => with_progress(options.progress, sample_bounds.1, |rows_done| {
+            let mut pixels = vec![0u8; sample_bounds.0 * sample_bounds.1 * 3];
+            render_simd_counted(&mut pixels, sample_bounds, region, max_iter, options.bailout_sqr(),
+                                &options.palette, rows_done);
+            pixels
+        }),
+        Precision::F32 => render_samples::<f32>(options, sample_bounds, &sample_tile, region,
+                                                max_iter, fractal),
+        Precision::F64 => render_samples::<f64>(options, sample_bounds, &sample_tile, region,
+                                                max_iter, fractal),
+        // linear だけ（`parse_args` で確かめてある）
+        #[cfg(feature = "high-precision")]
+        Precision::High => with_progress(options.progress, sample_tile.size.1, |rows_done| {
+            let mut pixels = vec![0u8; sample_tile.size.0 * sample_tile.size.1 * 3];
+            render_tile_counted_as(&mut pixels, sample_bounds, &precise_region(sample_bounds, region, center_zoom),
+                                   &sample_tile, max_iter, options.bailout_sqr(), fractal, &options.palette,
+                                   options.interior, rows_done, &AtomicBool::new(false));
+            pixels
+        }),
+    };
+
+    let mut pixels = if n > 1 {
+        downsample(&pixels, tile.size, n, options.gamma_space)
+    } else {
+        pixels
+    };
+    // 縮小してから補正する（サブピクセルの平均は補正前の値でとる）
+    if options.gamma() != 1.0 {
+        apply_gamma(&mut pixels, options.gamma());
     }
+    pixels
+}
+
+/// `--stats` のときに、描画で集めた反復回数 `iters` から `render_image` と同じ色を付ける
+///
+/// linear・histogram だけで、スーパーサンプリングやジッター、内部の色は使わない（`parse_args` で確かめてある）。
+fn colorize_iterations(options: &Options, iters: &IterBuffer) -> Vec<u8> {
+    let mut pixels = match options.color_mode {
+        ColorMode::Histogram if options.dither => iters.colorize_histogram_dithered(&options.palette),
+        ColorMode::Histogram => iters.colorize_histogram(&options.palette),
+        _ => iters.colorize(&options.palette),
+    };
+    if options.gamma() != 1.0 {
+        apply_gamma(&mut pixels, options.gamma());
+    }
+    pixels
+}
 
-    let t = iter as f32 / max_iter as f32;
+#[test]
+fn test_colorize_iterations() {
+    let (bounds, region, max_iter) = ((24, 16), Region::CLASSIC, 100);
+    for mode in ["linear", "histogram"] {
+        let args = ["color", "--stats", "--color-mode", mode, "--gamma", "1.8"].map(String::from);
+        let options = parse_args(&args).unwrap();
+        let iters = compute_iterations_counted::<f64>(bounds, &region, max_iter, options.bailout_sqr(),
+                                                      options.fractal, &Default::default());
+        let expected = render_image(&options, bounds, &region, None, max_iter, options.fractal);
+        assert_eq!(colorize_iterations(&options, &iters), expected, "{}", mode);
+    }
+}
+
+/// `--precision high` で計算する範囲
+///
+/// 中心と倍率が分かっていれば倍々精度で作り直す（f64 の角からでは深い拡大で幅が潰れる）。
+#[cfg(feature = "high-precision")]
+fn precise_region(
+    bounds: (usize, usize),
+    region: &Region,
+    center_zoom: Option<(Complex<f64>, f64)>,
+) -> Region<double_double::DoubleDouble> {
+    match center_zoom {
+        Some((center, zoom)) => Region::from_center_zoom_as(bounds, center, zoom),
+        None => region.cast(),
+    }
+}
 
-    // 有名な smooth coloring（青→紫→赤→黄）
-    let r = (9.0 * (1.0 - t) * t * t * t * 255.0) as u8;
-    let g = (15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0) as u8;
-    let b = (8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0) as u8;
+/// smooth・potential の点 `point` の色（0.0..=1.0）と、それに掛ける明るさ（`hdr_brightness`）
+fn hdr_sample(options: &Options, point: Complex<f64>, max_iter: u32, fractal: Fractal) -> ([f32; 3], f32) {
+    let bailout_sqr = options.bailout_sqr();
+    let (color, count) = match options.color_mode {
+        ColorMode::Smooth => {
+            let smooth = fractal.escape_time_smooth(point, max_iter, bailout_sqr);
+            (smooth_color_exact(smooth, max_iter, &options.palette), smooth)
+        }
+        // parse_args で smooth か potential に限ってある
+        _ => {
+            let log_potential = fractal.escape_time_log_potential(point, max_iter, bailout_sqr);
+            let count = log_potential.map(|l| -l / std::f64::consts::LN_2);
+            (potential_color_exact(log_potential, &options.palette), count)
+        }
+    };
+    (color.map(|channel| channel / 255.0), hdr_brightness(count, max_iter, options.exposure))
+}
 
-    [r, g, b]
+/// smooth・potential の色を 8 ビットに丸めずに描く（`--format exr`。1.0 が 8 ビットの 255 にあたる）
+///
+/// トーンマップは書き出した後に外部のツールでかけるので、`hdr_brightness` の明るさは掛けない。
+fn render_exact(
+    options: &Options,
+    bounds: (usize, usize),
+    region: &Region,
+    max_iter: u32,
+    fractal: Fractal,
+    rows_done: &std::sync::atomic::AtomicUsize,
+) -> Vec<f32> {
+    let mut pixels = vec![0.0f32; bounds.0 * bounds.1 * 3];
+    render_pixels_parallel(&mut pixels, bounds, rows_done, |x, y| {
+        let point = region.pixel_to_point(bounds, (x, y));
+        hdr_sample(options, point, max_iter, fractal).0
+    });
+    pixels
 }
 
-/// 画像保存
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> image::ImageResult<()> {
-    let buffer: ImageBuffer<Rgb<u8>, _> =
-        ImageBuffer::from_raw(bounds.0 as u32, bounds.1 as u32, pixels.to_vec())
-            .expect("buffer size mismatch");
+#[test]
+fn test_render_exact() {
+    let args = ["color", "--format", "exr", "--color-mode", "smooth"].map(String::from);
+    let options = parse_args(&args).unwrap();
+    let (bounds, region, max_iter) = ((16, 12), Region::CLASSIC, 200);
+    let pixels = render_exact(&options, bounds, &region, max_iter, Fractal::Mandelbrot, &Default::default());
 
-    buffer.save(filename)
+    // 明るさを掛けずに、8 ビットで書く色を 255 で割ったものになる
+    for (i, color) in pixels.chunks(3).enumerate() {
+        let point = region.pixel_to_point(bounds, (i % bounds.0, i / bounds.0));
+        let smooth = Fractal::Mandelbrot.escape_time_smooth(point, max_iter, options.bailout_sqr());
+        let expected = smooth_color_exact(smooth, max_iter, &options.palette).map(|channel| channel / 255.0);
+        assert_eq!(color, expected);
+    }
 }
 
+/// `render_image` の本体（浮動小数点数の型 `T` で計算する）
+///
+/// `tile` が画像全体でないのは linear のときだけ（`parse_args` で確かめてある）。
+fn render_samples<T: Float + Send + Sync>(
+    options: &Options,
+    bounds: (usize, usize),
+    tile: &Tile,
+    region: &Region,
+    max_iter: u32,
+    fractal: Fractal,
+) -> Vec<u8> {
+    let bailout_sqr = options.bailout_sqr();
+
+    with_progress(options.progress, tile.size.1, |rows_done| {
+        // ジッター AA は linear・画像全体のときだけ（`parse_args` で確かめてある）
+        if let Some(samples) = options.jitter {
+            let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+            let colors = ColorTable::new(&options.palette, max_iter);
+            let sample = |point| {
+                let result = fractal.escape_time_full(complex_as::<T>(point), max_iter, bailout_sqr);
+                match result.count {
+                    Some(iter) => (colors.color(iter), false),
+                    None => (options.interior.color(result.final_z.norm_sqr().to_f64().unwrap()), true),
+                }
+            };
+            render_jittered_counted(&mut pixels, bounds, region, samples, options.seed, options.gamma_space,
+                                    rows_done, sample);
+            return pixels;
+        }
+        // トーンマッピングは smooth・potential だけ（`parse_args` で確かめてある）
+        if let Some(tone_map) = options.tone_map {
+            let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+            render_pixels_parallel(&mut pixels, bounds, rows_done, |x, y| {
+                let point = region.pixel_to_point(bounds, (x, y));
+                let (color, brightness) = hdr_sample(options, point, max_iter, fractal);
+                tone_map.quantize(color, brightness)
+            });
+            return pixels;
+        }
+
+        match options.color_mode {
+            // 反復回数から直接色が決まるので、行ごとに並列で描画する
+            // 範囲やフラクタルが対称でなければ、全体を計算するのと同じ
+            ColorMode::Linear if options.exploit_symmetry => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_symmetric_counted::<T>(&mut pixels, bounds, region, max_iter, bailout_sqr, fractal,
+                                              &options.palette, options.interior, rows_done, &AtomicBool::new(false));
+                pixels
+            }
+            ColorMode::Linear => {
+                let mut pixels = vec![0u8; tile.size.0 * tile.size.1 * 3];
+                // 帯は AtomicChunksMut で取り合う（スレッド数は rayon と同じく RAYON_NUM_THREADS に従う）
+                render_color_bands(&mut pixels, bounds, &region.cast::<T>(), tile, options.band_rows,
+                                   rayon::current_num_threads(), max_iter, bailout_sqr, fractal, &options.palette,
+                                   options.interior, rows_done, &AtomicBool::new(false));
+                pixels
+            }
+            // 1 パス目で反復回数を集め、2 パス目で色を付ける
+            ColorMode::Histogram => {
+                let iters = compute_iterations_counted::<T>(bounds, region, max_iter, bailout_sqr,
+                                                            fractal, rows_done);
+                if options.dither {
+                    iters.colorize_histogram_dithered(&options.palette)
+                } else {
+                    iters.colorize_histogram(&options.palette)
+                }
+            }
+
+            // トラップまでの距離は反復回数と違って f64 で計算する
+            ColorMode::OrbitTrap => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_with_counted(&mut pixels, bounds, region, rows_done, |point| {
+                    let (iter, distance) = fractal.escape_time_trap(point, max_iter, bailout_sqr);
+                    trap_color(iter, distance, max_iter)
+                });
+                pixels
+            }
+            // 推定距離は 1 ピクセルの幅を単位にして濃淡を付ける
+            ColorMode::Distance => {
+                let pixel_size = region.width() / bounds.0 as f64;
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_with_counted(&mut pixels, bounds, region, rows_done, |point| {
+                    let estimate = fractal.escape_time_distance(point, max_iter, bailout_sqr);
+                    distance_color(estimate, pixel_size)
+                });
+                pixels
+            }
+            // ディザはピクセルの位置で決まるので、点ではなくピクセルごとに描く
+            ColorMode::Potential if options.dither => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_pixels_parallel(&mut pixels, bounds, rows_done, |x, y| {
+                    let point = region.pixel_to_point(bounds, (x, y));
+                    let log_potential = fractal.escape_time_log_potential(point, max_iter, bailout_sqr);
+                    potential_color_dithered(log_potential, &options.palette, (x, y))
+                });
+                pixels
+            }
+            ColorMode::Potential => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_with_counted(&mut pixels, bounds, region, rows_done, |point| {
+                    let log_potential = fractal.escape_time_log_potential(point, max_iter, bailout_sqr);
+                    potential_color(log_potential, &options.palette)
+                });
+                pixels
+            }
+            ColorMode::Smooth if options.dither => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_pixels_parallel(&mut pixels, bounds, rows_done, |x, y| {
+                    let point = region.pixel_to_point(bounds, (x, y));
+                    let smooth = fractal.escape_time_smooth(point, max_iter, bailout_sqr);
+                    smooth_color_dithered(smooth, max_iter, &options.palette, (x, y))
+                });
+                pixels
+            }
+            ColorMode::Smooth => {
+                let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+                render_with_counted(&mut pixels, bounds, region, rows_done, |point| {
+                    let smooth = fractal.escape_time_smooth(point, max_iter, bailout_sqr);
+                    smooth_color(smooth, max_iter, &options.palette)
+                });
+                pixels
+            }
+            // 法線は隣のピクセルの回数から求めるので、ヒストグラムと同じく先に全体を計算する
+            ColorMode::Shaded => {
+                let iters = compute_iterations_counted::<T>(bounds, region, max_iter, bailout_sqr,
+                                                            fractal, rows_done);
+                iters.colorize_shaded(&options.palette, options.light)
+            }
+        }
+    })
+}
@@ -0,0 +1,896 @@
+//! 画像・反復回数・境界のファイルへの書き出しと読み戻し
+//!
+//! ファイルシステムを使うので、WebAssembly（`wasm32`）向けのビルドには含めない。
+//! ブラウザでは `render_to_rgba` の RGBA バッファをそのまま canvas に描く。
+
+use crate::*;
+use image::{ImageBuffer, ImageFormat, Rgb};
+
+/// 画像に埋め込む描画条件（どの範囲をどれだけ反復して描いたか）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageMetadata {
+    pub bounds: (usize, usize),
+    pub region: Region,
+    pub max_iter: u32,
+}
+
+// PNG のテキストチャンクのキーワード
+const KEY_BOUNDS: &str = "bounds";
+const KEY_UPPER_LEFT: &str = "upper_left";
+const KEY_LOWER_RIGHT: &str = "lower_right";
+const KEY_MAX_ITER: &str = "max_iter";
+
+impl ImageMetadata {
+    /// テキストチャンクに書く (キーワード, 値) の組
+    ///
+    /// 値は `--size` や `parse_complex` と同じ形で書くので、そのまま読み戻せる。
+    fn text_chunks(&self) -> [(&'static str, String); 4] {
+        let complex = |z: Complex<f64>| format!("{},{}", z.re, z.im);
+        [
+            (KEY_BOUNDS, format!("{}x{}", self.bounds.0, self.bounds.1)),
+            (KEY_UPPER_LEFT, complex(self.region.upper_left)),
+            (KEY_LOWER_RIGHT, complex(self.region.lower_right)),
+            (KEY_MAX_ITER, self.max_iter.to_string()),
+        ]
+    }
+}
+
+/// 画像保存（拡張子が `.ppm` なら PPM、それ以外は PNG）
+///
+/// まず `{filename}.tmp` に書き出し、成功したときだけ `filename` にリネームする。
+/// 途中で失敗したり中断されたりしても、書きかけのファイルが `filename` に残ることはない。
+/// `pixels` の長さが `bounds` の RGB（1 ピクセル 3 バイト）と合わなければ、何も書かずにエラーを返す。
+pub fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), RenderError> {
+    write_image_checked(filename, pixels, bounds, None)
+}
+
+/// `write_image` と同じだが、PNG なら描画条件 `metadata` をテキストチャンクとして埋め込む
+///
+/// 画像の大きさは `metadata.bounds` を使う。PPM など PNG 以外では `metadata` は書かれない。
+/// 埋め込んだ値は `read_metadata` で読み戻せる。
+pub fn write_image_with_metadata(filename: &str, pixels: &[u8], metadata: &ImageMetadata)
+    -> Result<(), RenderError>
+{
+    write_image_checked(filename, pixels, metadata.bounds, Some(metadata))
+}
+
+/// 16 ビットのグレースケール PNG で保存する（`IterBuffer::to_gray16` の値などを 8 ビットに丸めずに書く）
+///
+/// PNG 以外の拡張子はエラーになる。書き出し方は `write_image` と同じで、`samples` の長さが
+/// `bounds` と合わなければ何も書かない。
+pub fn write_image_gray16(filename: &str, samples: &[u16], bounds: (usize, usize)) -> Result<(), RenderError> {
+    write_gray16_checked(filename, samples, bounds, None)
+}
+
+/// `write_image_gray16` と同じだが、描画条件 `metadata` を埋め込む（`write_image_with_metadata` と同じ）
+pub fn write_image_gray16_with_metadata(filename: &str, samples: &[u16], metadata: &ImageMetadata)
+    -> Result<(), RenderError>
+{
+    write_gray16_checked(filename, samples, metadata.bounds, Some(metadata))
+}
+
+fn write_gray16_checked(
+    filename: &str,
+    samples: &[u16],
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    let expected = bounds.0 * bounds.1;
+    if samples.len() != expected {
+        return Err(RenderError::BufferMismatch { expected: expected * 2, actual: samples.len() * 2 });
+    }
+
+    // PNG の 16 ビットのサンプルはビッグエンディアン
+    let data: Vec<u8> = samples.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+    write_png_only("16-bit output", filename, &data, bounds, png::ColorType::Grayscale, png::BitDepth::Sixteen,
+                   metadata)
+}
+
+/// RGBA の PNG で保存する（`IterBuffer::colorize_rgba` の透明な内部などをそのまま書く）
+///
+/// PNG 以外の拡張子はエラーになる。書き出し方は `write_image` と同じで、`pixels` の長さが
+/// `bounds` の RGBA（1 ピクセル 4 バイト）と合わなければ何も書かない。
+pub fn write_image_rgba(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), RenderError> {
+    write_rgba_checked(filename, pixels, bounds, None)
+}
+
+/// `write_image_rgba` と同じだが、描画条件 `metadata` を埋め込む（`write_image_with_metadata` と同じ）
+pub fn write_image_rgba_with_metadata(filename: &str, pixels: &[u8], metadata: &ImageMetadata)
+    -> Result<(), RenderError>
+{
+    write_rgba_checked(filename, pixels, metadata.bounds, Some(metadata))
+}
+
+fn write_rgba_checked(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    let expected = bounds.0 * bounds.1 * 4;
+    if pixels.len() != expected {
+        return Err(RenderError::BufferMismatch { expected, actual: pixels.len() });
+    }
+    write_png_only("RGBA output", filename, pixels, bounds, png::ColorType::Rgba, png::BitDepth::Eight, metadata)
+}
+
+/// 32 ビット浮動小数点の RGB の OpenEXR で保存する（8 ビットに丸める前の色を外部のツールでトーンマップするため）
+///
+/// `pixels` は 1 ピクセル 3 つの f32（行優先、1.0 が 8 ビットの 255 にあたる）。`.exr` 以外の拡張子は
+/// エラーになる。書き出し方は `write_image` と同じで、`pixels` の長さが `bounds` と合わなければ何も書かない。
+pub fn write_image_exr(filename: &str, pixels: &[f32], bounds: (usize, usize)) -> Result<(), RenderError> {
+    let expected = bounds.0 * bounds.1 * 3;
+    if pixels.len() != expected {
+        return Err(RenderError::BufferMismatch { expected: expected * 4, actual: pixels.len() * 4 });
+    }
+    if !filename.ends_with(".exr") {
+        return Err(format!("float output requires an EXR file: {}", filename).into());
+    }
+
+    let _span = tracing::info_span!("write_image", filename).entered();
+    write_atomically(filename, |file| write_exr(file, pixels, bounds))
+}
+
+fn write_exr(file: &mut std::fs::File, pixels: &[f32], bounds: (usize, usize)) -> Result<(), RenderError> {
+    use exr::prelude::*;
+
+    let channels = SpecificChannels::rgb(|Vec2(x, y)| {
+        let i = (y * bounds.0 + x) * 3;
+        (pixels[i], pixels[i + 1], pixels[i + 2])
+    });
+    Ok(Image::from_channels((bounds.0, bounds.1), channels).write().to_unbuffered(file)?)
+}
+
+#[test]
+fn test_write_image_exr() {
+    let bounds = (5, 3);
+    // 1.0 を超える値や、8 ビットでは区別できない細かい差もそのまま残る
+    let pixels: Vec<f32> = (0..bounds.0 * bounds.1 * 3).map(|i| i as f32 * 0.137 + 1e-4).collect();
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_test_{}.exr", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image_exr(filename, &pixels, bounds).unwrap();
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        filename,
+        |resolution, _| vec![0.0f32; resolution.width() * resolution.height() * 3],
+        move |buffer: &mut Vec<f32>, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            let i = (position.y() * bounds.0 + position.x()) * 3;
+            buffer[i..i + 3].copy_from_slice(&[r, g, b]);
+        },
+    ).unwrap();
+    std::fs::remove_file(filename).unwrap();
+
+    assert_eq!(image.layer_data.size.width(), bounds.0);
+    assert_eq!(image.layer_data.size.height(), bounds.1);
+    for (read, written) in image.layer_data.channel_data.pixels.iter().zip(&pixels) {
+        assert!((read - written).abs() <= written.abs() * 1e-6, "{} {}", read, written);
+    }
+
+    // write_image も拡張子が .exr なら 0..=255 を 0.0..=1.0 にして書く
+    let rgb: Vec<u8> = (0..bounds.0 * bounds.1 * 3).map(|i| (i * 17 % 256) as u8).collect();
+    write_image(filename, &rgb, bounds).unwrap();
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        filename,
+        |resolution, _| vec![0.0f32; resolution.width() * resolution.height() * 3],
+        move |buffer: &mut Vec<f32>, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            let i = (position.y() * bounds.0 + position.x()) * 3;
+            buffer[i..i + 3].copy_from_slice(&[r, g, b]);
+        },
+    ).unwrap();
+    std::fs::remove_file(filename).unwrap();
+    for (read, &written) in image.layer_data.channel_data.pixels.iter().zip(&rgb) {
+        assert!((read * 255.0 - written as f32).abs() < 1e-3);
+    }
+
+    // 長さが合わない・拡張子が違うときは何も書かない
+    assert!(write_image_exr(filename, &pixels[1..], bounds).is_err());
+    assert!(write_image_exr(filename.trim_end_matches(".exr"), &pixels, bounds).is_err());
+    assert!(!path.exists());
+}
+
+/// PNG でしか書けない形式（`what`）を、`write_image` と同じく一時ファイルを経由して書き出す
+fn write_png_only(
+    what: &str,
+    filename: &str,
+    data: &[u8],
+    bounds: (usize, usize),
+    color: png::ColorType,
+    depth: png::BitDepth,
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    let _span = tracing::info_span!("write_image", filename).entered();
+    if filename == STDOUT_FILENAME {
+        return encode_png(std::io::stdout().lock(), data, bounds, color, depth, metadata);
+    }
+    if ImageFormat::from_path(filename)? != ImageFormat::Png {
+        return Err(format!("{} requires a PNG file: {}", what, filename).into());
+    }
+
+    write_atomically(filename, |file| encode_png(file, data, bounds, color, depth, metadata))
+}
+
+fn write_image_checked(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    let expected = bounds.0 * bounds.1 * 3;
+    if pixels.len() != expected {
+        return Err(RenderError::BufferMismatch { expected, actual: pixels.len() });
+    }
+
+    let _span = tracing::info_span!("write_image", filename).entered();
+    // 標準出力には一時ファイルを経由せずに、PNG のバイト列をそのまま書く
+    if filename == STDOUT_FILENAME {
+        return encode_image(std::io::stdout().lock(), pixels, bounds, metadata);
+    }
+    write_atomically(filename, |file| write_image_as(file, filename, pixels, bounds, metadata))
+}
+
+/// `filename` に `write` で書き出す
+///
+/// まず `{filename}.tmp` を作って `write` に渡し、成功したときだけ `filename` にリネームする。
+/// 失敗したら一時ファイルを消してエラーを返すので、書きかけのファイルはどちらの名前でも残らない。
+fn write_atomically<E: From<std::io::Error>>(
+    filename: &str,
+    write: impl FnOnce(&mut std::fs::File) -> Result<(), E>,
+) -> Result<(), E> {
+    let temp = format!("{}.tmp", filename);
+    // ファイルは write の中で閉じてからリネームする
+    let result = std::fs::File::create(&temp)
+        .map_err(E::from)
+        .and_then(|mut file| write(&mut file))
+        .and_then(|()| Ok(std::fs::rename(&temp, filename)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+#[test]
+fn test_write_atomically() {
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_write_atomically_{}.dat", std::process::id()));
+    let filename = path.to_str().unwrap();
+    let temp = format!("{}.tmp", filename);
+
+    // 途中で失敗したら、一時ファイルも出力ファイルも残さない
+    let result = write_atomically(filename, |file| {
+        use std::io::Write;
+        file.write_all(b"partial")?;
+        Err(std::io::Error::other("interrupted"))
+    });
+    assert_eq!(result.unwrap_err().to_string(), "interrupted");
+    assert!(!std::path::Path::new(&temp).exists());
+    assert!(!path.exists());
+
+    // 成功すれば書いた内容が出力ファイルになる
+    write_atomically(filename, |file| std::io::Write::write_all(file, b"done")).unwrap();
+    assert_eq!(std::fs::read(filename).unwrap(), b"done");
+    assert!(!std::path::Path::new(&temp).exists());
+    std::fs::remove_file(filename).unwrap();
+}
+
+/// `write_image` でファイルの代わりに標準出力に PNG を書くときの名前（`--output -`）
+pub const STDOUT_FILENAME: &str = "-";
+
+/// RGB の PNG にエンコードして `writer` に書く（`metadata` があればテキストチャンクとして埋め込む）
+///
+/// `write_image` のファイル名が `STDOUT_FILENAME` のときはこれで標準出力に書く。
+/// `pixels` の長さが `bounds` と合わなければ何も書かない。
+pub fn encode_image(
+    writer: impl std::io::Write,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    let expected = bounds.0 * bounds.1 * 3;
+    if pixels.len() != expected {
+        return Err(RenderError::BufferMismatch { expected, actual: pixels.len() });
+    }
+    encode_png(writer, pixels, bounds, png::ColorType::Rgb, png::BitDepth::Eight, metadata)
+}
+
+#[test]
+fn test_encode_image() {
+    // メモリ上に書いた PNG を読み戻すと同じ画像になる
+    let bounds = (40, 30);
+    let pixels = compute_iterations(bounds, &Region::CLASSIC, 100).colorize(&Palette::Classic);
+    let mut buffer = Vec::new();
+    encode_image(&mut buffer, &pixels, bounds, None).unwrap();
+    assert!(buffer.starts_with(b"\x89PNG\r\n\x1a\n"));
+    let decoded = image::load_from_memory_with_format(&buffer, ImageFormat::Png).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (bounds.0 as u32, bounds.1 as u32));
+    assert_eq!(decoded.as_raw(), &pixels);
+
+    // ファイルに書いたものとバイト単位で同じ（描画条件も埋め込まれる）
+    let metadata = ImageMetadata { bounds, region: Region::CLASSIC, max_iter: 100 };
+    let mut buffer = Vec::new();
+    encode_image(&mut buffer, &pixels, bounds, Some(&metadata)).unwrap();
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_encode_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+    write_image_with_metadata(filename, &pixels, &metadata).unwrap();
+    let written = std::fs::read(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+    assert_eq!(buffer, written);
+
+    assert!(matches!(encode_image(Vec::new(), &pixels[3..], bounds, None),
+                     Err(RenderError::BufferMismatch { .. })));
+}
+
+/// `file` に書き出す。形式は `filename` の拡張子で決める。
+fn write_image_as(
+    file: &mut std::fs::File,
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    if filename.ends_with(".ppm") {
+        return Ok(write_ppm(file, pixels, bounds)?);
+    }
+    if filename.ends_with(".exr") {
+        let floats: Vec<f32> = pixels.iter().map(|&channel| channel as f32 / 255.0).collect();
+        return write_exr(file, &floats, bounds);
+    }
+    let format = ImageFormat::from_path(filename)?;
+    if let (ImageFormat::Png, Some(metadata)) = (format, metadata) {
+        return encode_png(file, pixels, bounds, png::ColorType::Rgb, png::BitDepth::Eight, Some(metadata));
+    }
+
+    // 長さは write_image で確かめてあるので、ここで失敗することはない
+    let expected = bounds.0 * bounds.1 * 3;
+    let buffer: ImageBuffer<Rgb<u8>, _> =
+        ImageBuffer::from_raw(bounds.0 as u32, bounds.1 as u32, pixels.to_vec())
+            .ok_or(RenderError::BufferMismatch { expected, actual: pixels.len() })?;
+
+    Ok(buffer.write_to(&mut std::io::BufWriter::new(file), format)?)
+}
+
+/// `png` クレートで直接 `writer` に書く（`image` クレートの保存ではテキストチャンクを付けられない）
+///
+/// `data` は `color`・`depth` の形式に並べたサンプル。`metadata` があればテキストチャンクとして埋め込む。
+fn encode_png(
+    writer: impl std::io::Write,
+    data: &[u8],
+    bounds: (usize, usize),
+    color: png::ColorType,
+    depth: png::BitDepth,
+    metadata: Option<&ImageMetadata>,
+) -> Result<(), RenderError> {
+    write_png_stream(writer, bounds, color, depth, metadata, |stream| Ok(stream.write_all(data)?))
+}
+
+/// `encode_png` の本体（サンプルは `write` が先頭から順に `stream` に書く）
+///
+/// 1 度に書いても少しずつ書いても同じバイト列になるので、`write_png_streamed` とも一致する。
+fn write_png_stream(
+    writer: impl std::io::Write,
+    bounds: (usize, usize),
+    color: png::ColorType,
+    depth: png::BitDepth,
+    metadata: Option<&ImageMetadata>,
+    write: impl FnOnce(&mut dyn std::io::Write) -> Result<(), RenderError>,
+) -> Result<(), RenderError> {
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(writer), bounds.0 as u32, bounds.1 as u32);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    for (keyword, text) in metadata.iter().flat_map(|metadata| metadata.text_chunks()) {
+        encoder.add_text_chunk(keyword.to_string(), text)?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+    write(&mut stream)?;
+    Ok(stream.finish()?)
+}
+
+/// 画像を `band_rows` 行ずつ描いては PNG に書き足していく（画像全体の RGB バッファを持たない）
+///
+/// `render_band(band, first_row)` は `first_row` 行目からの `band.len() / (bounds.0 * 3)` 行分の RGB を
+/// `band` に書く。最後の帯は `band_rows` 行より短いことがある。使うメモリは帯 1 つ分だけで、
+/// 書き出したファイルは画像全体を描いてから `write_image_with_metadata`（`metadata` がなければ
+/// 同じ設定の PNG）で書いたものとバイト単位で一致する。書き出し方は `write_image` と同じ。
+pub fn write_png_streamed<F>(
+    filename: &str,
+    bounds: (usize, usize),
+    metadata: Option<&ImageMetadata>,
+    band_rows: usize,
+    mut render_band: F,
+) -> Result<(), RenderError>
+where
+    F: FnMut(&mut [u8], usize),
+{
+    assert!(band_rows > 0);
+    // 帯ごとの描画もこの中で行うので、描画と書き出しを合わせた時間になる
+    let _span = tracing::info_span!("write_image", filename, streamed = true).entered();
+    let write_bands = |stream: &mut dyn std::io::Write| -> Result<(), RenderError> {
+        let mut band = vec![0u8; bounds.0 * band_rows * 3];
+        for first_row in (0..bounds.1).step_by(band_rows) {
+            let rows = band_rows.min(bounds.1 - first_row);
+            let band = &mut band[..bounds.0 * rows * 3];
+            render_band(band, first_row);
+            stream.write_all(band)?;
+        }
+        Ok(())
+    };
+    if filename == STDOUT_FILENAME {
+        return write_png_stream(std::io::stdout().lock(), bounds, png::ColorType::Rgb, png::BitDepth::Eight,
+                                metadata, write_bands);
+    }
+    if ImageFormat::from_path(filename)? != ImageFormat::Png {
+        return Err(format!("streaming output requires a PNG file: {}", filename).into());
+    }
+
+    write_atomically(filename, |file| {
+        write_png_stream(file, bounds, png::ColorType::Rgb, png::BitDepth::Eight, metadata, write_bands)
+    })
+}
+
+#[test]
+fn test_write_png_streamed_matches_buffered() {
+    let bounds = (301, 203);
+    let region = Region::CLASSIC;
+    let metadata = ImageMetadata { bounds, region, max_iter: 100 };
+    let dir = std::env::temp_dir();
+    let buffered = dir.join(format!("mandelbrot_color_buffered_{}.png", std::process::id()));
+    let streamed = dir.join(format!("mandelbrot_color_streamed_{}.png", std::process::id()));
+
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    render_color_parallel(&mut pixels, bounds, &region, 100);
+    write_image_with_metadata(buffered.to_str().unwrap(), &pixels, &metadata).unwrap();
+
+    // 行数が帯の大きさで割り切れないようにする
+    let mut first_rows = vec![];
+    write_png_streamed(streamed.to_str().unwrap(), bounds, Some(&metadata), 16, |band, first_row| {
+        let tile = Tile { size: (bounds.0, band.len() / (bounds.0 * 3)), offset: (0, first_row) };
+        render_tile_counted::<f64>(band, bounds, &region, &tile, 100, default_bailout_sqr(), Fractal::Mandelbrot,
+                                   &Palette::Classic, InteriorColor::Black, &AtomicUsize::new(0),
+                                   &AtomicBool::new(false));
+        first_rows.push(first_row);
+    }).unwrap();
+
+    let (buffered_bytes, streamed_bytes) = (std::fs::read(&buffered).unwrap(), std::fs::read(&streamed).unwrap());
+    std::fs::remove_file(&buffered).unwrap();
+    std::fs::remove_file(&streamed).unwrap();
+    assert_eq!(first_rows.len(), 13);
+    assert_eq!(first_rows.last(), Some(&192));
+    assert!(buffered_bytes == streamed_bytes);
+
+    assert!(write_png_streamed("out.ppm", bounds, None, 16, |_, _| {}).is_err());
+}
+
+/// `load_palette_image` が参照画像から取り出す色の最大数
+pub const PALETTE_IMAGE_STOPS: usize = 256;
+
+/// 参照画像の真ん中の横 1 行を左から右へ読んでグラデーションにする（`--palette-image`）
+///
+/// 幅が `PALETTE_IMAGE_STOPS` より広い画像は、位置に比例して間引いた色を使う。
+pub fn load_palette_image(filename: &str) -> Result<Gradient, RenderError> {
+    let image = image::open(filename)?.into_rgb8();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Err(RenderError::InvalidArgument(format!("empty palette image: {}", filename)));
+    }
+
+    let row: Vec<[u8; 3]> = (0..width).map(|x| image.get_pixel(x, height / 2).0).collect();
+    Ok(Gradient::sample(&row, PALETTE_IMAGE_STOPS))
+}
+
+#[test]
+fn test_load_palette_image() {
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_palette_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    // 黒と白の 2 ピクセルなら、真ん中は灰色
+    write_image(filename, &[0, 0, 0, 255, 255, 255], (2, 1)).unwrap();
+    let gradient = load_palette_image(filename);
+    std::fs::remove_file(filename).unwrap();
+    let gradient = gradient.unwrap();
+    assert_eq!(gradient.color(0.0), [0, 0, 0]);
+    assert_eq!(gradient.color(0.5), [128, 128, 128]);
+    assert_eq!(gradient.color(1.0), [255, 255, 255]);
+
+    // 大きな画像は真ん中の行を比例して読む（上下の行の色は使わない）
+    let bounds = (1000, 3);
+    let mut pixels = vec![255u8; bounds.0 * bounds.1 * 3];
+    for x in 0..bounds.0 {
+        let offset = (bounds.0 + x) * 3;
+        pixels[offset..offset + 3].copy_from_slice(&[(x / 4) as u8, 0, 0]);
+    }
+    write_image(filename, &pixels, bounds).unwrap();
+    let gradient = load_palette_image(filename);
+    std::fs::remove_file(filename).unwrap();
+    let gradient = gradient.unwrap();
+    assert_eq!(gradient.color(0.0), [0, 0, 0]);
+    assert_eq!(gradient.color(1.0), [249, 0, 0]);
+
+    // 読めない画像はエラー
+    assert!(load_palette_image(filename).is_err());
+}
+
+/// 画像ファイルを RGB のバッファとして読み込み、大きさと一緒に返す（`--compare` の基準画像）
+///
+/// アルファやグレースケールの画像も RGB に直して読む。
+pub fn read_image(filename: &str) -> Result<(Vec<u8>, (usize, usize)), RenderError> {
+    let image = image::open(filename)?.into_rgb8();
+    let bounds = (image.width() as usize, image.height() as usize);
+    Ok((image.into_raw(), bounds))
+}
+
+#[test]
+fn test_read_image() {
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_read_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    let bounds = (3, 2);
+    let pixels: Vec<u8> = (0..18).map(|v| v * 10).collect();
+    write_image(filename, &pixels, bounds).unwrap();
+    let read = read_image(filename);
+    std::fs::remove_file(filename).unwrap();
+    assert_eq!(read.unwrap(), (pixels, bounds));
+
+    assert!(read_image(filename).is_err());
+}
+
+/// `write_image_with_metadata` で PNG に埋め込んだ描画条件を読み戻す
+pub fn read_metadata(filename: &str) -> Result<ImageMetadata, RenderError> {
+    let error = |message: String| RenderError::Metadata(format!("{}: {}", filename, message));
+
+    let file = std::fs::File::open(filename).map_err(|err| error(err.to_string()))?;
+    let reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .map_err(|err| error(err.to_string()))?;
+    let chunks = &reader.info().uncompressed_latin1_text;
+
+    let text = |keyword: &str| {
+        chunks.iter()
+            .find(|chunk| chunk.keyword == keyword)
+            .map(|chunk| chunk.text.as_str())
+            .ok_or_else(|| error(format!("missing {}", keyword)))
+    };
+    let invalid = |keyword: &str, text: &str| error(format!("invalid {}: {}", keyword, text));
+    let complex = |keyword: &str| {
+        let value = text(keyword)?;
+        parse_complex(value).ok_or_else(|| invalid(keyword, value))
+    };
+
+    let bounds = text(KEY_BOUNDS)?;
+    let max_iter = text(KEY_MAX_ITER)?;
+    Ok(ImageMetadata {
+        bounds: parse_pair(bounds, 'x').ok_or_else(|| invalid(KEY_BOUNDS, bounds))?,
+        region: Region {
+            upper_left: complex(KEY_UPPER_LEFT)?,
+            lower_right: complex(KEY_LOWER_RIGHT)?,
+        },
+        max_iter: max_iter.parse().map_err(|_| invalid(KEY_MAX_ITER, max_iter))?,
+    })
+}
+
+#[test]
+fn test_tracing_spans() {
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    /// 作られたスパンの名前を順に記録する
+    struct SpanNames(Arc<Mutex<Vec<&'static str>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanNames {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _: &tracing::span::Id, _: Context<'_, S>) {
+            self.0.lock().unwrap().push(attrs.metadata().name());
+        }
+    }
+
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_tracing_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let bounds = (40, 30);
+        let pixels = compute_iterations(bounds, &Region::CLASSIC, 100).colorize(&Palette::Classic);
+        write_image(filename, &pixels, bounds).unwrap();
+    });
+    std::fs::remove_file(filename).unwrap();
+
+    // 反復回数の計算 → 色付け → 書き出しの順
+    assert_eq!(*names.lock().unwrap(), ["compute_iterations", "colorize", "write_image"]);
+}
+
+#[test]
+fn test_image_metadata_round_trip() {
+    let metadata = ImageMetadata {
+        bounds: (8, 6),
+        region: Region::from_corners(Complex::new(-0.75, 0.1), Complex::new(-0.7, 0.0625)).unwrap(),
+        max_iter: 500,
+    };
+    let pixels = compute_iterations(metadata.bounds, &metadata.region, metadata.max_iter)
+        .colorize(&Palette::Classic);
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_meta_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image_with_metadata(filename, &pixels, &metadata).unwrap();
+    let read = read_metadata(filename);
+    std::fs::remove_file(filename).unwrap();
+    assert_eq!(read.unwrap(), metadata);
+
+    // 描画条件を埋め込んでいない PNG は読めない
+    write_image(filename, &pixels, metadata.bounds).unwrap();
+    let err = read_metadata(filename).unwrap_err();
+    std::fs::remove_file(filename).unwrap();
+    assert!(matches!(err, RenderError::Metadata(_)), "{}", err);
+}
+
+#[test]
+fn test_write_image_gray16() {
+    // 8 ビットに収まらない値を含むサンプル
+    let bounds = (4, 2);
+    let samples: Vec<u16> = vec![0, 1, 255, 256, 1000, 4095, 40000, u16::MAX];
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_gray16_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image_gray16(filename, &samples, bounds).unwrap();
+    let read = image::open(filename).map(|image| image.into_luma16());
+    std::fs::remove_file(filename).unwrap();
+    let read = read.unwrap();
+    assert_eq!(read.dimensions(), (4, 2));
+    assert_eq!(read.into_raw(), samples);
+
+    // PNG 以外や長さの合わないバッファは書かない
+    assert!(write_image_gray16(&format!("{}.ppm", filename), &samples, bounds).is_err());
+    let err = write_image_gray16(filename, &samples[..7], bounds).unwrap_err();
+    assert!(matches!(err, RenderError::BufferMismatch { expected: 16, actual: 14 }));
+    assert!(!path.exists());
+
+    // 反復回数は 0..=65535 に線形に写る
+    let iters = IterBuffer { data: vec![0.0, 1.0, 200.0, 1000.0], bounds: (4, 1), max_iter: 1000 };
+    assert_eq!(iters.to_gray16(), [0, 66, 13107, 65535]);
+    let iters = IterBuffer { data: vec![0.0, 256.0, 65535.0], bounds: (3, 1), max_iter: 65535 };
+    assert_eq!(iters.to_gray16(), [0, 256, 65535]);
+}
+
+#[test]
+fn test_write_image_rgba() {
+    let bounds = (2, 2);
+    let pixels: Vec<u8> = vec![255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 10, 20, 30, 255];
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_rgba_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image_rgba(filename, &pixels, bounds).unwrap();
+    let read = image::open(filename).map(|image| image.into_rgba8());
+    std::fs::remove_file(filename).unwrap();
+    let read = read.unwrap();
+    assert_eq!(read.dimensions(), (2, 2));
+    assert_eq!(read.into_raw(), pixels);
+
+    // PNG 以外や長さの合わないバッファは書かない
+    assert!(write_image_rgba(&format!("{}.ppm", filename), &pixels, bounds).is_err());
+    let err = write_image_rgba(filename, &pixels[..12], bounds).unwrap_err();
+    assert!(matches!(err, RenderError::BufferMismatch { expected: 16, actual: 12 }));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_write_image_leaves_no_temp_file() {
+    let pixels = vec![0u8; 4 * 3 * 3];
+    let dir = std::env::temp_dir().join(format!("mandelbrot_color_atomic_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // 存在しないディレクトリには書けない
+    let missing = dir.join("no_such_dir").join("out.png");
+    let missing = missing.to_str().unwrap();
+    assert!(write_image(missing, &pixels, (4, 3)).is_err());
+    assert!(!std::path::Path::new(&format!("{}.tmp", missing)).exists());
+
+    // 一時ファイルは書けるが、リネーム先がディレクトリなので失敗する
+    let occupied = dir.join("occupied.png");
+    std::fs::create_dir_all(occupied.join("child")).unwrap();
+    let occupied = occupied.to_str().unwrap();
+    assert!(write_image(occupied, &pixels, (4, 3)).is_err());
+    assert!(!std::path::Path::new(&format!("{}.tmp", occupied)).exists());
+
+    // 成功すれば一時ファイルは残らない
+    let ok = dir.join("ok.png");
+    let ok = ok.to_str().unwrap();
+    write_image(ok, &pixels, (4, 3)).unwrap();
+    assert!(std::path::Path::new(ok).exists());
+    assert!(!std::path::Path::new(&format!("{}.tmp", ok)).exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_write_image_rejects_short_buffer() {
+    let bounds = (4, 3);
+    let pixels = vec![0u8; bounds.0 * bounds.1]; // グレースケールの長さ（RGB の 1/3）
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_short_{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    let err = write_image(filename, &pixels, bounds).unwrap_err();
+    assert!(matches!(err, RenderError::BufferMismatch { expected: 36, actual: 12 }));
+    let message = err.to_string();
+    assert!(message.contains("36") && message.contains("12"), "{}", message);
+
+    // 何も書き出さない（PPM でも同じ）
+    assert!(!path.exists());
+    assert!(!std::path::Path::new(&format!("{}.tmp", filename)).exists());
+    assert!(write_image(&format!("{}.ppm", filename), &pixels, bounds).is_err());
+}
+
+/// バイナリ PPM (P6) で書き出す（`image` クレートを通さず RGB をそのまま書く）
+fn write_ppm(file: &mut std::fs::File, pixels: &[u8], bounds: (usize, usize)) -> std::io::Result<()> {
+    use std::io::Write;
+
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+
+    let mut file = std::io::BufWriter::new(file);
+    write!(file, "P6\n{} {}\n255\n", bounds.0, bounds.1)?;
+    file.write_all(pixels)?;
+    file.flush()
+}
+
+#[test]
+fn test_write_ppm() {
+    let bounds = (3, 2);
+    let pixels: Vec<u8> = (0..18).map(|i| i * 10).collect();
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_test_{}.ppm", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image(filename, &pixels, bounds).unwrap();
+    let bytes = std::fs::read(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+
+    // ヘッダ "P6\n{w} {h}\n255\n" を読み戻す
+    let text = String::from_utf8_lossy(&bytes);
+    let mut fields = text.split_ascii_whitespace();
+    assert_eq!(fields.next(), Some("P6"));
+    assert_eq!(fields.next(), Some("3"));
+    assert_eq!(fields.next(), Some("2"));
+    assert_eq!(fields.next(), Some("255"));
+
+    let header_len = "P6\n3 2\n255\n".len();
+    assert_eq!(&bytes[..header_len], b"P6\n3 2\n255\n");
+    assert_eq!(&bytes[header_len..], &pixels[..]);
+}
+
+/// 反復回数ファイルの先頭 4 バイト
+const ITERATIONS_MAGIC: &[u8; 4] = b"MITR";
+
+/// 反復回数をそのままバイナリで保存する（色を付けずに外部のツールで扱うため）
+///
+/// 形式は先頭から `"MITR"`、幅・高さ（u32）、行優先の回数（u32）で、数値はすべてリトルエンディアン。
+/// `write_image` と同じく一時ファイルに書いてからリネームする。`read_iterations` で読み戻せる。
+pub fn write_iterations(filename: &str, iters: &[u32], bounds: (usize, usize)) -> Result<(), RenderError> {
+    use std::io::Write;
+
+    let expected = bounds.0 * bounds.1;
+    if iters.len() != expected {
+        return Err(RenderError::BufferMismatch { expected: expected * 4, actual: iters.len() * 4 });
+    }
+    let dimension = |n: usize| {
+        u32::try_from(n).map_err(|_| RenderError::from(format!("image too large to save: {}", n)))
+    };
+    let (width, height) = (dimension(bounds.0)?, dimension(bounds.1)?);
+
+    Ok(write_atomically(filename, |file| -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(file);
+        file.write_all(ITERATIONS_MAGIC)?;
+        file.write_all(&width.to_le_bytes())?;
+        file.write_all(&height.to_le_bytes())?;
+        for &iter in iters {
+            file.write_all(&iter.to_le_bytes())?;
+        }
+        file.flush()
+    })?)
+}
+
+/// `write_iterations` で保存した反復回数と画像の大きさを読み戻す
+pub fn read_iterations(filename: &str) -> Result<(Vec<u32>, (usize, usize)), RenderError> {
+    let invalid = |message: &str| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", filename, message))
+    };
+
+    let bytes = std::fs::read(filename)?;
+    let (header, body) = bytes.split_at_checked(12).ok_or_else(|| invalid("header too short"))?;
+    if &header[..4] != ITERATIONS_MAGIC {
+        return Err(invalid("not an iteration file").into());
+    }
+    let word = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let bounds = (word(&header[4..8]) as usize, word(&header[8..12]) as usize);
+
+    if body.len() != bounds.0 * bounds.1 * 4 {
+        return Err(RenderError::BufferMismatch { expected: bounds.0 * bounds.1 * 4, actual: body.len() });
+    }
+    Ok((body.chunks_exact(4).map(word).collect(), bounds))
+}
+
+#[test]
+fn test_iterations_round_trip() {
+    let bounds = (7, 5);
+    let iters: Vec<u32> = compute_iterations(bounds, &Region::CLASSIC, 300)
+        .data
+        .iter()
+        .map(|&iter| iter as u32)
+        .collect();
+    assert!(iters.contains(&300) && iters.iter().any(|&iter| iter < 300));
+
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_iters_{}.dat", std::process::id()));
+    let filename = path.to_str().unwrap();
+    write_iterations(filename, &iters, bounds).unwrap();
+    let bytes = std::fs::read(filename).unwrap();
+    let read = read_iterations(filename);
+
+    // ヘッダは "MITR"、幅 7、高さ 5（リトルエンディアン）
+    assert_eq!(&bytes[..12], b"MITR\x07\0\0\0\x05\0\0\0");
+    assert_eq!(bytes.len(), 12 + iters.len() * 4);
+    assert_eq!(read.unwrap(), (iters.clone(), bounds));
+    assert!(!std::path::Path::new(&format!("{}.tmp", filename)).exists());
+
+    // 途中で切れたファイルや、長さの合わないバッファは受け付けない
+    std::fs::write(filename, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(read_iterations(filename), Err(RenderError::BufferMismatch { .. })));
+    std::fs::write(filename, b"MITR").unwrap();
+    assert!(read_iterations(filename).is_err());
+    std::fs::remove_file(filename).unwrap();
+    assert!(matches!(write_iterations(filename, &iters[1..], bounds),
+                     Err(RenderError::BufferMismatch { expected: 140, actual: 136 })));
+    assert!(!path.exists());
+}
+
+/// 境界のピクセル（`extract_boundary`）を SVG に書き出す
+///
+/// 1 ピクセルを 1 つの `<rect>` にして、複素平面の座標のまま置く。`viewBox` は `region` に
+/// 合わせるので、拡大しても輪郭がぼやけない。SVG の y 軸は下向きなので、虚部は符号を反転して置く。
+/// 書き出し方は `write_image` と同じ（一時ファイルからリネームする）。
+pub fn write_svg(
+    filename: &str,
+    bounds: (usize, usize),
+    region: &Region,
+    boundary: &[(usize, usize)],
+) -> Result<(), RenderError> {
+    use std::io::Write;
+
+    let (width, height) = (region.width(), region.height());
+    let (pixel_width, pixel_height) = (width / bounds.0 as f64, height / bounds.1 as f64);
+
+    Ok(write_atomically(filename, |file| -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(file);
+        writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(file, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+                 bounds.0, bounds.1, region.upper_left.re, -region.upper_left.im, width, height)?;
+        for &pixel in boundary {
+            let point = region.pixel_to_point(bounds, pixel);
+            writeln!(file, r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+                     point.re, -point.im, pixel_width, pixel_height)?;
+        }
+        writeln!(file, "</svg>")?;
+        file.flush()
+    })?)
+}
+
+#[test]
+fn test_write_svg() {
+    let bounds = (40, 30);
+    let region = Region::CLASSIC;
+    let boundary = extract_boundary(&compute_iterations(bounds, &region, 50));
+    assert!(!boundary.is_empty());
+    let path = std::env::temp_dir().join(format!("mandelbrot_color_boundary_{}.svg", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_svg(filename, bounds, &region, &boundary).unwrap();
+    let svg = std::fs::read_to_string(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+
+    // XML 宣言の後に svg 要素が 1 つだけあり、その中に境界のピクセルと同じ数の rect が閉じて並ぶ
+    let mut lines = svg.lines();
+    assert!(lines.next().unwrap().starts_with("<?xml"));
+    let root = lines.next().unwrap();
+    assert!(root.starts_with("<svg ") && root.ends_with('>') && !root.ends_with("/>"), "{}", root);
+    assert!(root.contains(r#"viewBox="-2.2 -1.2 3.2 2.4""#), "{}", root);
+    assert_eq!(svg.lines().last(), Some("</svg>"));
+    let rects: Vec<_> = svg.lines().filter(|line| line.starts_with("<rect ")).collect();
+    assert_eq!(rects.len(), boundary.len());
+    assert!(rects.iter().all(|rect| rect.ends_with("/>") && rect.matches('"').count() == 8));
+    assert_eq!(svg.lines().count(), boundary.len() + 3);
+    assert_eq!(svg.matches('<').count(), svg.matches('>').count());
+}
@@ -1,6 +1,6 @@
 #![allow(unused)]
 
-use num::Complex;
+use num_complex::Complex;
 
 /// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
 /// iterations to decide.
@@ -89,32 +89,120 @@ mod atomic_chunks_mut {
     pub struct AtomicChunksMut<'a, T> {
         slice: &'a [T],
         step: usize,
+        /// 続けて配るチャンクの番号の間隔（1 なら先頭から順番）
+        stride: usize,
+        /// これまでに配ったチャンクの数
         next: AtomicUsize
     }
 
     impl<'a, T> AtomicChunksMut<'a, T> {
+        /// `slice` を先頭から `step` 要素ずつのチャンクに分けて、先頭から順番に配る
+        ///
+        /// `step` がスライスより長ければ、スライス全体を 1 つのチャンクとして配る。
+        ///
+        /// # Panics
+        ///
+        /// `step` が 0 のとき（`next` が進まず、CAS のループが終わらなくなるため）。
         pub fn new(slice: &'a mut [T], step: usize) -> AtomicChunksMut<'a, T> {
+            AtomicChunksMut::new_interleaved(slice, step, 1)
+        }
+
+        /// チャンクを 0, stride, 2*stride, ..., 1, 1 + stride, ... の順に配る
+        ///
+        /// 続けて取ったチャンクが隣り合わないので、スレッドどうしが同じキャッシュラインを
+        /// 取り合いにくい。どの順でも、すべてのチャンクがちょうど 1 回ずつ配られる。
+        ///
+        /// # Panics
+        ///
+        /// `step` か `stride` が 0 のとき。
+        pub fn new_interleaved(slice: &'a mut [T], step: usize, stride: usize) -> AtomicChunksMut<'a, T> {
+            assert!(step > 0, "AtomicChunksMut: step must be at least 1");
+            assert!(stride > 0, "AtomicChunksMut: stride must be at least 1");
             AtomicChunksMut {
                 slice: slice,
                 step: step,
+                stride: stride,
                 next: AtomicUsize::new(0)
             }
         }
 
+        /// チャンクの総数（最後のチャンクは step より短いことがある）
+        fn chunks(&self) -> usize {
+            self.slice.len().div_ceil(self.step)
+        }
+
+        /// 番号 `index` のチャンクの範囲
+        fn range(&self, index: usize) -> std::ops::Range<usize> {
+            let start = index * self.step;
+            start..std::cmp::min(start + self.step, self.slice.len())
+        }
+
+        /// 番号を stride で割った余りが `lane` のチャンクの数
+        fn lane_len(&self, lane: usize) -> usize {
+            self.chunks().saturating_sub(lane).div_ceil(self.stride)
+        }
+
+        /// `ticket` 番目に配るチャンクの番号
+        fn index(&self, ticket: usize) -> usize {
+            let mut ticket = ticket;
+            for lane in 0..self.stride {
+                let len = self.lane_len(lane);
+                if ticket < len {
+                    return lane + ticket * self.stride;
+                }
+                ticket -= len;
+            }
+            unreachable!("ticket out of range")
+        }
+
+        /// 番号 `index` のチャンクを何番目に配るか（`index` の逆）
+        fn ticket(&self, index: usize) -> usize {
+            let lane = index % self.stride;
+            (0..lane).map(|l| self.lane_len(l)).sum::<usize>() + index / self.stride
+        }
+
         #[allow(mutable_transmutes)]
         unsafe fn next(&self) -> Option<(usize, &'a mut [T])> {
             loop {
                 let current = self.next.load(SeqCst);
-                assert!(current <= self.slice.len());
-                if current == self.slice.len() {
+                assert!(current <= self.chunks());
+                if current == self.chunks() {
                     return None;
                 }
-                let end = std::cmp::min(current + self.step, self.slice.len());
-                if self.next.compare_exchange(current, end, SeqCst, SeqCst).is_ok() {
-                    return Some((current / self.step, std::mem::transmute(&self.slice[current..end])));
+                if self.next.compare_exchange(current, current + 1, SeqCst, SeqCst).is_ok() {
+                    let index = self.index(current);
+                    let chunk = unsafe {
+                        std::mem::transmute::<&[T], &mut [T]>(&self.slice[self.range(index)])
+                    };
+                    return Some((index, chunk));
                 }
             }
         }
+
+        /// まだ誰にも渡していない要素の数（進捗表示用）
+        pub fn remaining(&self) -> usize {
+            let taken = self.next.load(SeqCst);
+            if taken == 0 {
+                return self.slice.len();
+            }
+            // 短いかもしれない最後のチャンクを配り終えていれば、その分を差し引く
+            let last = self.chunks() - 1;
+            let mut claimed = taken * self.step;
+            if self.ticket(last) < taken {
+                claimed -= self.step - self.range(last).len();
+            }
+            self.slice.len() - claimed
+        }
+
+        /// 先頭のチャンクから配り直す（同じスライスで 2 回目の描画をするとき）
+        ///
+        /// # Safety
+        ///
+        /// これまでに渡したチャンクと同じ範囲をもう一度 `&mut` で渡すので、
+        /// 呼び出す側はそれらのチャンクをすべて使い終えていなければならない。
+        pub unsafe fn reset(&self) {
+            self.next.store(0, SeqCst);
+        }
     }
 
     impl<'a, 'b, T> Iterator for &'b AtomicChunksMut<'a, T> {
@@ -134,6 +222,46 @@ fn test_ait() {
     assert_eq!((&c).iter().map(|&(_, ref s)| s[0]).collect::<Vec<_>>(), vec![0,3,6,9]);
 }
 
+#[test]
+#[should_panic(expected = "step must be at least 1")]
+fn test_ait_zero_step() {
+    let mut v = [0,1,2];
+    AtomicChunksMut::new(&mut v[..], 0);
+}
+
+#[test]
+fn test_ait_step_longer_than_slice() {
+    let mut v = [0,1,2,3,4];
+    let c : Vec<_> = (&AtomicChunksMut::new(&mut v[..], 8)).collect();
+
+    assert_eq!(c.len(), 1);
+    assert_eq!(c[0].0, 0);
+    assert_eq!(c[0].1, &[0,1,2,3,4]);
+}
+
+#[test]
+fn test_ait_remaining_and_reset() {
+    let mut v = vec![0,1,2,3,4,5,6,7,8,9,10];
+    let it = AtomicChunksMut::new(&mut v[..], 3);
+    assert_eq!(it.remaining(), 11);
+
+    // チャンクを取るたびに減り、最後の短いチャンクで 0 になる
+    let mut remaining = vec![];
+    for (_, chunk) in &it {
+        chunk[0] += 100;
+        remaining.push(it.remaining());
+    }
+    assert_eq!(remaining, vec![8, 5, 2, 0]);
+    assert!((&it).next().is_none());
+
+    // reset すると最初から同じ順番で配り直す
+    unsafe { it.reset(); }
+    assert_eq!(it.remaining(), 11);
+    let again: Vec<_> = (&it).map(|(i, chunk)| (i, chunk[0])).collect();
+    assert_eq!(again, vec![(0, 100), (1, 103), (2, 106), (3, 109)]);
+    assert_eq!(it.remaining(), 0);
+}
+
 #[test]
 fn stress_test_ait() {
     use crossbeam::scope;
@@ -160,3 +288,56 @@ fn stress_test_ait() {
         }
     }).unwrap();
 }
+
+#[test]
+fn test_ait_interleaved() {
+    let mut v : Vec<usize> = (0..11).collect();
+    let c : Vec<_> = (&AtomicChunksMut::new_interleaved(&mut v[..], 2, 3)).collect();
+
+    // 6 つのチャンクを 0, 3, 1, 4, 2, 5 の順に配る（番号は元の並びのまま）
+    assert_eq!(c.iter().map(|&(i, _)| i).collect::<Vec<_>>(), vec![0,3,1,4,2,5]);
+    assert_eq!(c.iter().map(|(_, s)| s.to_vec()).collect::<Vec<_>>(),
+               vec![vec![0,1], vec![6,7], vec![2,3], vec![8,9], vec![4,5], vec![10]]);
+
+    // stride 1 は new と同じ
+    let mut w : Vec<usize> = (0..11).collect();
+    let d : Vec<_> = (&AtomicChunksMut::new_interleaved(&mut w[..], 3, 1)).map(|(i, _)| i).collect();
+    assert_eq!(d, vec![0,1,2,3]);
+
+    // 最後の短いチャンクが途中で配られても remaining は正しい
+    let mut u : Vec<usize> = (0..11).collect();
+    let it = AtomicChunksMut::new_interleaved(&mut u[..], 2, 3);
+    let remaining : Vec<_> = (&it).map(|_| it.remaining()).collect();
+    assert_eq!(remaining, vec![9, 7, 5, 3, 1, 0]);
+}
+
+#[test]
+fn stress_test_ait_interleaved() {
+    use crossbeam::scope;
+    let mut v : Vec<usize> = (0..10000).collect();
+    let it = AtomicChunksMut::new_interleaved(&mut v[..], 3, 7);
+
+    scope(|scope| {
+        let mut threads = vec![];
+        for _ in 0..10 {
+            threads.push(scope.spawn(|_| {
+                let mut v = vec![];
+                for (i, chunk) in &it {
+                    assert_eq!(chunk[0], i * 3);
+                    v.extend_from_slice(chunk);
+                }
+                v
+            }));
+        }
+
+        // すべての要素がちょうど 1 回ずつ、どれかのスレッドに渡る
+        let mut seen = vec![false; 10000];
+        for thread in threads {
+            for element in thread.join().unwrap() {
+                assert!(!seen[element]);
+                seen[element] = true;
+            }
+        }
+        assert!(seen.iter().all(|&s| s));
+    }).unwrap();
+}
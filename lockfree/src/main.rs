@@ -1,19 +1,22 @@
 #![allow(unused)]
 
-use num::Complex;
+use num_complex::Complex;
+
+/// Radius of the circle that `escape_time` checks `z` against.
+const DEFAULT_BAILOUT: f64 = 2.0;
 
 /// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
 /// iterations to decide.
 ///
 /// If `c` is not a member, return `Some(i)`, where `i` is the number of
-/// iterations it took for `c` to leave the circle of radius two centered on the
-/// origin. If `c` seems to be a member (more precisely, if we reached the
-/// iteration limit without being able to prove that `c` is not a member),
-/// return `None`.
+/// iterations it took for `c` to leave the circle of radius `DEFAULT_BAILOUT`
+/// (two) centered on the origin. If `c` seems to be a member (more precisely,
+/// if we reached the iteration limit without being able to prove that `c` is
+/// not a member), return `None`.
 fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
+        if z.norm_sqr() > DEFAULT_BAILOUT * DEFAULT_BAILOUT {
             return Some(i);
         }
         z = z * z + c;
@@ -57,6 +60,7 @@ fn test_parse_pair() {
 
 /// Parse a pair of floating-point numbers separated by a comma as a complex
 /// number.
+#[allow(clippy::manual_map)]
 fn parse_complex(s: &str) -> Option<Complex<f64>> {
     match parse_pair(s, ',') {
         Some((re, im)) => Some(Complex { re, im }),
@@ -142,45 +146,188 @@ fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize))
     Ok(())
 }
 
-{                                           // 元々は　mod atomic_chunks_mut {
-    use std;
-    use std::sync::atomic::AtomicUsize;
-    use std::sync::atomic::Ordering::*;
+use mandelbrot_lockfree::AtomicChunksMut;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 描画を途中で止めても、`--resume` で続きから描けるようにするチェックポイントファイル
+const CHECKPOINT_FILE: &str = "mandelbrot.png.checkpoint";
 
-    pub struct AtomicChunksMut<'a, T> {
-        slice: &'a [T],
-        step: usize,
-        next: AtomicUsize
+/// チェックポイントの先頭に書くヘッダ（別の画像のチェックポイントを読まないように）
+///
+/// ヘッダに続けて、描き終えた帯ごとに「帯の番号（u64 リトルエンディアン）+ 帯のピクセル」を追記していく。
+fn checkpoint_header(bounds: (usize, usize), rows_per_band: usize) -> Vec<u8> {
+    let mut header = b"MBCKPT01".to_vec();
+    for n in [bounds.0, bounds.1, rows_per_band] {
+        header.extend_from_slice(&(n as u64).to_le_bytes());
     }
+    header
+}
 
-    impl<'a, T> AtomicChunksMut<'a, T> {
-        pub fn new(slice: &'a mut [T], step: usize) -> AtomicChunksMut<'a, T> {
-            AtomicChunksMut {
-                slice: slice,
-                step: step,
-                next: AtomicUsize::new(0)
-            }
+/// チェックポイントを追記用に開き、帯ごとの「描画済み」フラグを返す
+///
+/// `resume` のときは記録済みの帯を `pixels` に書き戻す。書き込み途中で止まった最後の帯は捨て、
+/// その手前から追記を続ける。`resume` でない（またはファイルがない）ときは作り直す。
+fn open_checkpoint(path: &str,
+                   bounds: (usize, usize),
+                   rows_per_band: usize,
+                   pixels: &mut [u8],
+                   resume: bool)
+    -> io::Result<(Mutex<File>, Vec<bool>)>
+{
+    let header = checkpoint_header(bounds, rows_per_band);
+    let band_len = rows_per_band * bounds.0;
+    let mut done = vec![false; bounds.1.div_ceil(rows_per_band)];
+
+    if !resume || !std::path::Path::new(path).exists() {
+        let mut file = File::create(path)?;
+        file.write_all(&header)?;
+        return Ok((Mutex::new(file), done));
+    }
+
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(&header) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{} was made for a different image", path)));
+    }
+    let mut valid = header.len();
+    while let Some(index) = bytes.get(valid..valid + 8) {
+        let index = u64::from_le_bytes(index.try_into().unwrap()) as usize;
+        if index >= done.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("{}: band {} out of range", path, index)));
         }
+        let start = index * band_len;
+        let end = std::cmp::min(start + band_len, pixels.len());
+        let Some(band) = bytes.get(valid + 8..valid + 8 + end - start) else {
+            break;
+        };
+        pixels[start..end].copy_from_slice(band);
+        done[index] = true;
+        valid += 8 + band.len();
+    }
 
-        #[allow(mutable_transmutes)]
-        unsafe fn next(&self) -> Option<(usize, &'a mut [T])> {
-            loop {
-                let current = self.next.load(SeqCst);
-                assert!(current <= self.slice.len());
-                if current == self.slice.len() {
-                    return None;
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(valid as u64)?;
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok((Mutex::new(file), done))
+}
+
+/// 画像を `rows_per_band` 行ずつの帯に分け、`threads` 本のスレッドで帯を取り合って描画する
+///
+/// `done` が立っている帯は描かずに飛ばす。`checkpoint` があれば、帯を描き終えるたびに追記する。
+/// 戻り値は実際に描いた帯の数。
+#[allow(clippy::too_many_arguments)]
+fn render_bands(pixels: &mut [u8],
+                bounds: (usize, usize),
+                upper_left: Complex<f64>,
+                lower_right: Complex<f64>,
+                rows_per_band: usize,
+                threads: usize,
+                done: &[bool],
+                checkpoint: Option<&Mutex<File>>)
+    -> io::Result<usize>
+{
+    let rendered = AtomicUsize::new(0);
+    let bands = AtomicChunksMut::new(pixels, rows_per_band * bounds.0);
+
+    crossbeam::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|_| -> io::Result<()> {
+            for (i, band) in &bands {
+                if done[i] {
+                    continue;
                 }
-                let end = std::cmp::min(current + self.step, self.slice.len());
-                if self.next.compare_exchange(current, end, SeqCst, SeqCst).is_ok() {
-                    return Some((current / self.step, std::mem::transmute(&self.slice[current..end])));
+                let top = i * rows_per_band;
+                let height = band.len() / bounds.0;
+                let band_bounds = (bounds.0, height);
+                let band_upper_left = pixel_to_point(bounds, (0, top),
+                                                     upper_left, lower_right);
+                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height),
+                                                      upper_left, lower_right);
+                render(band, band_bounds, band_upper_left, band_lower_right);
+                rendered.fetch_add(1, Ordering::SeqCst);
+
+                if let Some(file) = checkpoint {
+                    // 番号とピクセルを 1 度に書き、帯の途中で他のスレッドの記録が割り込まないようにする
+                    let mut record = (i as u64).to_le_bytes().to_vec();
+                    record.extend_from_slice(band);
+                    file.lock().unwrap().write_all(&record)?;
                 }
             }
-        }
+            Ok(())
+        })).collect();
+
+        workers.into_iter().try_for_each(|worker| worker.join().unwrap())
+    }).unwrap()?;
+
+    Ok(rendered.into_inner())
+}
+
+#[test]
+fn test_resume_from_checkpoint() {
+    let bounds: (usize, usize) = (120, 80);
+    let upper_left = Complex::new(-2.2, 1.2);
+    let lower_right = Complex::new(1.0, -1.2);
+    let rows_per_band = 3;
+    let band_count = bounds.1.div_ceil(rows_per_band);
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot_lockfree_resume_{}.checkpoint", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    // 1 回で描いたもの
+    let mut full = vec![0; bounds.0 * bounds.1];
+    let rendered = render_bands(&mut full, bounds, upper_left, lower_right, rows_per_band, 4,
+                                &vec![false; band_count], None).unwrap();
+    assert_eq!(rendered, band_count);
+
+    // チェックポイントを取りながら描いてから、記録を半分の帯まで切り詰める
+    let mut first = vec![0; bounds.0 * bounds.1];
+    let (file, done) = open_checkpoint(path, bounds, rows_per_band, &mut first, false).unwrap();
+    render_bands(&mut first, bounds, upper_left, lower_right, rows_per_band, 4, &done, Some(&file))
+        .unwrap();
+    drop(file);
+
+    let bytes = std::fs::read(path).unwrap();
+    let mut half = checkpoint_header(bounds, rows_per_band).len();
+    for _ in 0..band_count / 2 {
+        let index = u64::from_le_bytes(bytes[half..half + 8].try_into().unwrap()) as usize;
+        let rows = std::cmp::min(rows_per_band, bounds.1 - index * rows_per_band);
+        half += 8 + rows * bounds.0;
     }
+    // 次の帯を書いている途中で止まった状態にする
+    File::options().write(true).open(path).unwrap().set_len(half as u64 + 10).unwrap();
 
-    impl<'a, 'b, T> Iterator for &'b AtomicChunksMut<'a, T> {
-        type Item = (usize, &'a mut [T]);
-        fn next(&mut self) -> Option<Self::Item> { unsafe { (*self).next() } }
+    // 再開すると、残りの帯だけを描いて同じ画像になる
+    let mut resumed = vec![0; bounds.0 * bounds.1];
+    let (file, done) = open_checkpoint(path, bounds, rows_per_band, &mut resumed, true).unwrap();
+    assert_eq!(done.iter().filter(|&&d| d).count(), band_count / 2);
+    let rendered = render_bands(&mut resumed, bounds, upper_left, lower_right, rows_per_band, 4,
+                                &done, Some(&file)).unwrap();
+    drop(file);
+    assert_eq!(rendered, band_count - band_count / 2);
+    assert!(resumed == full);
+
+    // 再開後に描いた帯も記録されているので、もう一度再開すると何も描かない
+    let mut again = vec![0; bounds.0 * bounds.1];
+    let (_, done) = open_checkpoint(path, bounds, rows_per_band, &mut again, true).unwrap();
+    assert!(done.iter().all(|&d| d));
+    assert!(again == full);
+
+    // 大きさの違う画像のチェックポイントは読まない
+    let mut other = vec![0; 60 * 40];
+    assert!(open_checkpoint(path, (60, 40), rows_per_band, &mut other, true).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+/// `--resume` を解釈する（ほかの引数は受け付けない）
+fn parse_resume(args: &[String]) -> Result<bool, String> {
+    match args.get(1).map(String::as_str) {
+        None => Ok(false),
+        Some("--resume") if args.len() == 2 => Ok(true),
+        Some(_) => Err(format!("unexpected arguments: {}", args[1..].join(" "))),
     }
 }
 
@@ -203,6 +350,13 @@ fn main() {
     let lower_right = parse_complex(&args[4])
         .expect("error parsing lower right corner point");
 */
+    let args: Vec<String> = std::env::args().collect();
+    let resume = parse_resume(&args).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        eprintln!("Usage: {} [--resume]", args[0]);
+        std::process::exit(1);
+    });
+
     let start = std::time::Instant::now(); // ★ 計測開始
     let bounds = (1200, 800);
     let upper_left = Complex::new(-2.2, 1.2);
@@ -212,29 +366,24 @@ fn main() {
     let threads = 8;
     let rows_per_band = bounds.1 / 400 + 1;
 
-    {
-        let bands = AtomicChunksMut::new(&mut pixels, rows_per_band * bounds.0);
-        crossbeam::scope(|scope| {
-            for _ in 0..threads {
-                scope.spawn(|_| {
-                    for (i, band) in &bands {
-                        let top = i * rows_per_band;
-                        let height = band.len() / bounds.0;
-                        let band_bounds = (bounds.0, height);
-                        let band_upper_left = pixel_to_point(bounds, (0, top),
-                                                             upper_left, lower_right);
-                        let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height),
-                                                              upper_left, lower_right);
-                        render(band, band_bounds, band_upper_left, band_lower_right);
-                    }
-                });
-            }
-        });
+    let (checkpoint, done) =
+        open_checkpoint(CHECKPOINT_FILE, bounds, rows_per_band, &mut pixels, resume)
+            .expect("error opening checkpoint file");
+    let skipped = done.iter().filter(|&&d| d).count();
+    if skipped > 0 {
+        println!("{} 本の帯は描画済みなので、続きから描きます", skipped);
     }
+    render_bands(&mut pixels, bounds, upper_left, lower_right, rows_per_band, threads,
+                 &done, Some(&checkpoint))
+        .expect("error writing checkpoint file");
 
     //    write_image(&args[1], &pixels, bounds)
     write_image("mandelbrot.png", &pixels, bounds)
         .expect("error writing PNG file");
+    // 画像を書き終えたらチェックポイントはいらない
+    drop(checkpoint);
+    let _ = std::fs::remove_file(CHECKPOINT_FILE);
+
     let elapsed = start.elapsed(); // ★ 経過時間 
     println!( "mandelbrot.png を生成しました！\n処理時間: {:.3} 秒", elapsed.as_secs_f64() );
 }
@@ -1,12 +1,16 @@
 #![allow(unused)]
 
-use num::Complex;
+use num_complex::Complex;
 use std::time::Instant;
+
+/// 発散半径（|z| がこれを超えたら c は集合に属さない）
+const DEFAULT_BAILOUT: f64 = 2.0;
+
 /*
 c がマンデルブロ集合に属するかどうかを、最大 limit 回の反復で判定する。
 
 c が集合の要素でない場合は Some(i) を返す。
-ここで i は、原点を中心とする半径 2 の円から c が外に出るまでに必要だった反復回数である。
+ここで i は、原点を中心とする半径 DEFAULT_BAILOUT（= 2）の円から c が外に出るまでに必要だった反復回数である。
 
 もし c が集合の要素であるように見える場合（より正確には、c が集合に属さないと証明できないまま
 反復回数の上限に達した場合）は、None を返す。
@@ -14,7 +18,7 @@ c が集合の要素でない場合は Some(i) を返す。
 fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
+        if z.norm_sqr() > DEFAULT_BAILOUT * DEFAULT_BAILOUT {
             return Some(i);
         }
         z = z * z + c;
@@ -59,6 +63,7 @@ fn test_parse_pair() {
 }
 
 // カンマで区切られた 2 つの浮動小数点数をパースして、複素数として解釈する。
+#[allow(clippy::manual_map)]
 fn parse_complex(s: &str) -> Option<Complex<f64>> {
     match parse_pair(s, ',') {
         Some((re, im)) => Some(Complex { re, im }),
@@ -73,15 +78,74 @@ fn test_parse_complex() {
     assert_eq!(parse_complex(",-0.0625"), None);
 }
 
+use std::num::NonZeroUsize;
+
+/*
+出力画像の幅と高さ（ピクセル数）。どちらも 0 にはならない。
+
+`(usize, usize)` のままだと、`0x100` のような大きさで pixel_to_point が 0 で割ったり、
+空のバッファを確保したりしてしまうので、作るときに確かめておく。
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bounds {
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+}
+
+impl Bounds {
+    // 幅か高さが 0 ならエラーを返す。
+    fn new(width: usize, height: usize) -> Result<Bounds, String> {
+        match (NonZeroUsize::new(width), NonZeroUsize::new(height)) {
+            (Some(width), Some(height)) => Ok(Bounds { width, height }),
+            _ => Err(format!("image dimensions must not be zero: {}x{}", width, height)),
+        }
+    }
+
+    // `"1000x750"` のような文字列を parse_pair でパースして作る。
+    fn parse(s: &str) -> Result<Bounds, String> {
+        let (width, height) = parse_pair(s, 'x')
+            .ok_or_else(|| format!("error parsing image dimensions: {}", s))?;
+        Bounds::new(width, height)
+    }
+
+    fn width(self) -> usize {
+        self.width.get()
+    }
+
+    fn height(self) -> usize {
+        self.height.get()
+    }
+
+    // (幅, 高さ) のペア（画像の書き出しに使う）
+    fn pair(self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+}
+
+#[test]
+fn test_bounds() {
+    assert!(Bounds::new(0, 100).is_err());
+    assert!(Bounds::new(100, 0).is_err());
+    assert!(Bounds::parse("0x100").is_err());
+    assert!(Bounds::parse("100by100").is_err());
+
+    let bounds = Bounds::parse("1000x750").unwrap();
+    assert_eq!(bounds.pair(), (1000, 750));
+    assert_eq!(Bounds::new(1000, 750), Ok(bounds));
+    let text = format!("{}x{}", bounds.width(), bounds.height());
+    assert_eq!(parse_pair::<usize>(&text, 'x'), Some(bounds.pair()));
+    assert_eq!(Bounds::parse(&text), Ok(bounds));
+}
+
 /*
 出力画像のあるピクセルの行と列から、複素平面上の対応する点を返す。
 
-bounds は画像の幅と高さ（ピクセル数）を表すペア。
+bounds は画像の幅と高さ（ピクセル数）。
 pixel は画像内の特定のピクセルを示す (列, 行) のペア。
 upper_left と lower_right は、画像がカバーする複素平面上の領域を示す 2 点である。
 */
 
-fn pixel_to_point(bounds: (usize, usize),
+fn pixel_to_point(bounds: Bounds,
                   pixel: (usize, usize),
                   upper_left: Complex<f64>,
                   lower_right: Complex<f64>)
@@ -90,8 +154,8 @@ fn pixel_to_point(bounds: (usize, usize),
     let (width, height) = (lower_right.re - upper_left.re,
                            upper_left.im - lower_right.im);
     Complex {
-        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
+        re: upper_left.re + pixel.0 as f64 * width  / bounds.width() as f64,
+        im: upper_left.im - pixel.1 as f64 * height / bounds.height() as f64
         // Why subtraction here? pixel.1 increases as we go down,
         // but the imaginary component increases as we go up.
     }
@@ -99,53 +163,436 @@ fn pixel_to_point(bounds: (usize, usize),
 
 #[test]
 fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100, 200), (25, 175),
+    assert_eq!(pixel_to_point(Bounds::new(100, 200).unwrap(), (25, 175),
                               Complex { re: -1.0, im:  1.0 },
                               Complex { re:  1.0, im: -1.0 }),
                Complex { re: -0.5, im: -0.75 });
 }
 
+#[test]
+fn test_complex_is_num_complex() {
+    // color（mandelbrot_color）と同じ num_complex::Complex<f64> をそのまま渡せる
+    let upper_left: num_complex::Complex<f64> = num_complex::Complex::new(-2.2, 1.2);
+    let lower_right: num_complex::Complex<f64> = num_complex::Complex::new(1.0, -1.2);
+    let point = pixel_to_point(Bounds::new(1200, 800).unwrap(), (0, 0), upper_left, lower_right);
+    assert_eq!(point, upper_left);
+    assert_eq!(escape_time(point, 255), Some(1));
+    assert_eq!(escape_time(num_complex::Complex::new(-1.0, 0.0), 255), None);
+}
+
 /*
 マンデルブロ集合のある矩形領域を、ピクセルバッファへ描画する。
 
 bounds 引数は、1 バイトにつき 1 つのグレースケール値を持つピクセルバッファで pixels の幅と高さを表す。
 upper_left と lower_right は、ピクセルバッファの左上および右下の角に対応する複素平面上の点を指定する。
+invert が true なら濃淡を反転し、集合の内部を白、すぐに発散した点ほど暗く描く（印刷向け）。
+solid_guess が true なら、すべてのピクセルを計算する代わりに guess_counts で反復回数を推測する。
 */
 fn render(pixels: &mut [u8],
-          bounds: (usize, usize),
+          bounds: Bounds,
           upper_left: Complex<f64>,
-          lower_right: Complex<f64>)
+          lower_right: Complex<f64>,
+          limit: usize,
+          invert: bool,
+          solid_guess: bool)
 {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(pixels.len() == bounds.width() * bounds.height());
 
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row),
-                                       upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
-                match escape_time(point, 255) {
-                    None => 0,
-                    Some(count) => 255 - count as u8
-                };
+    let count = |column, row| {
+        escape_time(pixel_to_point(bounds, (column, row), upper_left, lower_right), limit)
+    };
+    let paint = |count: Option<usize>| {
+        let value = match count {
+            None => 0,
+            Some(count) => shade(count, limit)
+        };
+        if invert { 255 - value } else { value }
+    };
+
+    if solid_guess {
+        for (pixel, count) in pixels.iter_mut().zip(guess_counts(bounds, &count)) {
+            *pixel = paint(count);
+        }
+        return;
+    }
+
+    for row in 0..bounds.height() {
+        for column in 0..bounds.width() {
+            pixels[row * bounds.width() + column] = paint(count(column, row));
+        }
+    }
+}
+
+/*
+solid guessing で画像全体の反復回数を求める（--solid-guess）。
+
+count(column, row) はそのピクセルの反復回数を計算する関数で、1 つのピクセルにつき高々 1 回しか呼ばない。
+長方形の周囲のピクセルだけを計算し、周囲がすべて同じ反復回数なら中も同じとみなして計算せずに埋める。
+違えば長方形を 4 つに分けて同じことを繰り返す。集合の内部のような大きな一様な部分はほとんど計算しない。
+
+集合の内部（None）は穴のない領域なので、周囲がすべて内部なら中も内部になる。
+ただし、発散する点の周囲が同じ回数でも中に細い模様が入り込んでいることがあるので、
+総当たり（--no-solid-guess）の結果と違うことがある。
+*/
+fn guess_counts(bounds: Bounds, count: &dyn Fn(usize, usize) -> Option<usize>) -> Vec<Option<usize>> {
+    // 外側の None はまだ決まっていないピクセル
+    let mut counts = vec![None; bounds.width() * bounds.height()];
+    guess_rect(&mut counts, bounds.width(), (0, 0, bounds.width(), bounds.height()), count);
+    counts.into_iter()
+        .map(|count| count.expect("every pixel is computed or guessed"))
+        .collect()
+}
+
+// counts（1 行 stride ピクセル）の (left, top) から width×height の長方形を埋める。
+fn guess_rect(counts: &mut [Option<Option<usize>>],
+              stride: usize,
+              (left, top, width, height): (usize, usize, usize, usize),
+              count: &dyn Fn(usize, usize) -> Option<usize>)
+{
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (right, bottom) = (left + width - 1, top + height - 1);
+
+    // 周囲を計算する（隣の長方形と共有する辺は計算済みのものを使う）
+    let mut edge = None;
+    let mut uniform = true;
+    for row in top..=bottom {
+        // 上下の辺は全部、それ以外の行は左右の端だけ
+        let step = if row == top || row == bottom { 1 } else { (right - left).max(1) };
+        for column in (left..=right).step_by(step) {
+            let value = *counts[row * stride + column].get_or_insert_with(|| count(column, row));
+            match edge {
+                None => edge = Some(value),
+                Some(first) => uniform &= first == value,
+            }
         }
     }
+
+    if uniform {
+        for row in top + 1..bottom {
+            for column in left + 1..right {
+                counts[row * stride + column] = edge;
+            }
+        }
+    } else if width > 2 && height > 2 {
+        let (half_width, half_height) = (width / 2, height / 2);
+        for rect in [(left, top, half_width, half_height),
+                     (left + half_width, top, width - half_width, half_height),
+                     (left, top + half_height, half_width, height - half_height),
+                     (left + half_width, top + half_height, width - half_width, height - half_height)] {
+            guess_rect(counts, stride, rect, count);
+        }
+    }
+    // どちらかが 2 ピクセル以下なら、全部が周囲なのですでに計算してある
+}
+
+#[test]
+fn test_solid_guess() {
+    use std::cell::Cell;
+
+    // 主カージオイドの内側の範囲（4 辺ともカージオイドの内側にあり、カージオイドには穴がないので中も内側）。
+    // 全体が集合の内部なので、推測して塗りつぶしても必ず総当たりと同じになる
+    let bounds = Bounds::new(120, 80).unwrap();
+    let upper_left = Complex::new(-0.6, 0.4);
+    let lower_right = Complex::new(0.2, -0.4);
+    let mut brute = vec![1; bounds.width() * bounds.height()];
+    let mut guessed = vec![1; bounds.width() * bounds.height()];
+    render(&mut brute, bounds, upper_left, lower_right, 255, false, false);
+    render(&mut guessed, bounds, upper_left, lower_right, 255, false, true);
+    assert!(brute.iter().all(|&v| v == 0));
+    assert!(guessed == brute);
+
+    // 計算するのは画像の周囲の点だけ
+    let calls = Cell::new(0);
+    let counts = guess_counts(bounds, &|column, row| {
+        calls.set(calls.get() + 1);
+        escape_time(pixel_to_point(bounds, (column, row), upper_left, lower_right), 255)
+    });
+    assert!(counts.iter().all(Option::is_none));
+    assert_eq!(calls.get(), 2 * bounds.width() + 2 * (bounds.height() - 2));
+
+    // 外側を含む全体図でも、この大きさでは総当たりと同じになり、計算する点は減る
+    let upper_left = Complex::new(-2.2, 1.2);
+    let lower_right = Complex::new(1.0, -1.2);
+    render(&mut brute, bounds, upper_left, lower_right, 255, false, false);
+    render(&mut guessed, bounds, upper_left, lower_right, 255, false, true);
+    assert!(guessed == brute);
+
+    calls.set(0);
+    guess_counts(bounds, &|column, row| {
+        calls.set(calls.get() + 1);
+        escape_time(pixel_to_point(bounds, (column, row), upper_left, lower_right), 255)
+    });
+    assert!(calls.get() < bounds.width() * bounds.height());
+}
+
+#[test]
+fn test_render_invert() {
+    let bounds = Bounds::new(60, 40).unwrap();
+    let upper_left = Complex::new(-2.2, 1.2);
+    let lower_right = Complex::new(1.0, -1.2);
+    let mut normal = vec![0; bounds.width() * bounds.height()];
+    let mut inverted = vec![0; bounds.width() * bounds.height()];
+
+    // 上限を 255 より大きくしても、反転は縮めた後の濃淡に対してかかる
+    render(&mut normal, bounds, upper_left, lower_right, 1000, false, false);
+    render(&mut inverted, bounds, upper_left, lower_right, 1000, true, false);
+    for (n, i) in normal.iter().zip(&inverted) {
+        assert_eq!(*i, 255 - *n);
+    }
+    assert!(inverted.contains(&255));
 }
 
+/*
+発散までの反復回数 count（0..limit）を灰色の濃さに変換する。
+
+すぐに発散した点（count = 0）ほど明るく 255 に、上限 limit 近くまで発散しなかった点ほど暗くなる。
+color（mandelbrot_color）が反復回数を max_iter で割った t = count / max_iter でパレットを引くのと
+同じく、t を求めてから round(255 * (1 - t)) にするので、同じ反復回数・上限なら両方の濃淡がそろう。
+`255 - count as u8` のように 1 バイトに切り詰めないので、limit が 255 を超えても折り返さない。
+limit = 255 なら 255 - count と同じ。
+*/
+fn shade(count: usize, limit: usize) -> u8 {
+    let t = count.min(limit) as f64 / limit as f64;
+    (255.0 * (1.0 - t)).round() as u8
+}
+
+#[test]
+fn test_shade() {
+    assert_eq!(shade(0, 1000), 255);
+    assert!(shade(999, 1000) <= 1);
+
+    // 反復回数が増えるほど暗くなり、途中で折り返さない
+    for count in 1..1000 {
+        assert!(shade(count, 1000) <= shade(count - 1, 1000), "count = {}", count);
+    }
+    assert!(shade(300, 1000) > shade(700, 1000));
+
+    // limit = 255 では従来の 255 - count と同じ
+    for count in 0..255 {
+        assert_eq!(shade(count, 255), 255 - count as u8);
+    }
+}
+
+#[test]
+fn test_shade_normalized() {
+    // color と同じく t = count / max_iter で正規化し、round(255 * (1 - t)) になる
+    for (count, max_iter) in [(0, 1000), (1, 1000), (2, 1000), (500, 1000), (998, 1000), (1, 3), (2, 3),
+                              (100, 256), (7, 64), (37, 500)] {
+        let t = count as f64 / max_iter as f64;
+        assert_eq!(shade(count, max_iter), (255.0 * (1.0 - t)).round() as u8, "{} / {}", count, max_iter);
+    }
+    // 切り捨てではなく四捨五入（255 * 2/3 = 170, 255 * 1/3 = 85, 255 * 0.998 = 254.49）
+    assert_eq!(shade(1, 3), 170);
+    assert_eq!(shade(2, 3), 85);
+    assert_eq!(shade(2, 1000), 254);
+    assert_eq!(shade(1, 1000), 255);
+}
 
 /// 全面変更
-use image::{ImageBuffer, Luma, ImageError};
+use image::{ImageBuffer, ImageFormat, Luma, ImageError};
 
+/*
+ピクセルバッファを filename に保存する。拡張子が `.pgm` ならバイナリ PGM (P5)、それ以外は PNG。
+
+まず `{filename}.tmp` に書き出し、成功したときだけ filename にリネームするので、
+途中で失敗しても書きかけのファイルが filename に残ることはない。
+*/
 fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize))
     -> Result<(), ImageError>
 {
+    let temp = format!("{}.tmp", filename);
+
+    let result = write_image_as(&temp, filename, pixels, bounds)
+        .and_then(|()| Ok(std::fs::rename(&temp, filename)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+// path に書き出す。形式は filename の拡張子で決める。
+fn write_image_as(path: &str, filename: &str, pixels: &[u8], bounds: (usize, usize))
+    -> Result<(), ImageError>
+{
+    if filename.ends_with(".pgm") {
+        write_pgm(path, pixels, bounds)?;
+        return Ok(());
+    }
+
     let buffer: ImageBuffer<Luma<u8>, _> =
         ImageBuffer::from_raw(bounds.0 as u32, bounds.1 as u32, pixels.to_vec())
             .expect("buffer size mismatch");
 
-    buffer.save(filename)?;
+    buffer.save_with_format(path, ImageFormat::from_path(filename)?)?;
     Ok(())
 }
 
+#[test]
+fn test_write_image_leaves_no_temp_file() {
+    let pixels = vec![0; 4 * 3];
+    let dir = std::env::temp_dir()
+        .join(format!("mandelbrot_gray_atomic_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // 存在しないディレクトリには書けない
+    let missing = dir.join("no_such_dir").join("out.png");
+    let missing = missing.to_str().unwrap();
+    assert!(write_image(missing, &pixels, (4, 3)).is_err());
+    assert!(!std::path::Path::new(&format!("{}.tmp", missing)).exists());
+
+    // 一時ファイルは書けるが、リネーム先がディレクトリなので失敗する
+    let occupied = dir.join("occupied.png");
+    std::fs::create_dir_all(occupied.join("child")).unwrap();
+    let occupied = occupied.to_str().unwrap();
+    assert!(write_image(occupied, &pixels, (4, 3)).is_err());
+    assert!(!std::path::Path::new(&format!("{}.tmp", occupied)).exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/*
+グレースケールのピクセルバッファをバイナリ PGM (P5) として保存する。
+ヘッダ `P5\n{w} {h}\n255\n` に続けて、1 ピクセル 1 バイトをそのまま書き出す。
+*/
+fn write_pgm(filename: &str, pixels: &[u8], bounds: (usize, usize))
+    -> std::io::Result<()>
+{
+    use std::io::Write;
+
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+    write!(file, "P5\n{} {}\n255\n", bounds.0, bounds.1)?;
+    file.write_all(pixels)?;
+    file.flush()
+}
+
+#[test]
+fn test_write_pgm() {
+    let bounds = (3, 2);
+    let pixels = vec![0, 50, 100, 150, 200, 250];
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot_gray_test_{}.pgm", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    write_image(filename, &pixels, bounds).unwrap();
+    let bytes = std::fs::read(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+
+    let header = b"P5\n3 2\n255\n";
+    assert_eq!(&bytes[..header.len()], header);
+    assert_eq!(&bytes[header.len()..], &pixels[..]);
+}
+
+/*
+メッセージをどこまで出すか。
+
+成功のメッセージや処理時間は標準エラーに出し、標準出力には何も書かない。
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Verbosity {
+    Quiet,   // --quiet: 何も出さない
+    Normal,  // 既定: 生成のメッセージだけ
+    Verbose, // --verbose: 処理時間も出す
+}
+
+// コマンドラインで指定できる設定
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Options {
+    verbosity: Verbosity,
+    invert: bool,    // --invert: 濃淡を反転する
+    max_iter: usize, // --max-iter N: 反復回数の上限（既定: 255。color の --max-iter と同じく濃淡をこれで正規化する）
+    solid_guess: bool, // --solid-guess: 長方形の周囲が一様なら中を計算せずに塗る（--no-solid-guess で総当たりに戻す）
+    bounds: Bounds,    // --size WxH: 出力画像の大きさ（既定: 1200x800）
+}
+
+// 反復回数の上限の既定値
+const DEFAULT_MAX_ITER: usize = 255;
+
+// コマンドライン引数（args[0] はプログラム名）から Options を決める。
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        verbosity: Verbosity::Normal,
+        invert: false,
+        max_iter: DEFAULT_MAX_ITER,
+        solid_guess: false,
+        bounds: Bounds::new(1200, 800).unwrap(),
+    };
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let requested = match arg.as_str() {
+            "--quiet" => Verbosity::Quiet,
+            "--verbose" => Verbosity::Verbose,
+            "--invert" => {
+                options.invert = true;
+                continue;
+            }
+            // 後に書いたほうが優先（総当たりと結果を比べるときに、もう一方を打ち消せる）
+            "--solid-guess" | "--no-solid-guess" => {
+                options.solid_guess = arg == "--solid-guess";
+                continue;
+            }
+            "--max-iter" => {
+                let value = rest.next().ok_or("--max-iter requires a value")?;
+                options.max_iter = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid iteration count: {}", value)),
+                };
+                continue;
+            }
+            "--size" => {
+                let value = rest.next().ok_or("--size requires a value")?;
+                options.bounds = Bounds::parse(value)?;
+                continue;
+            }
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        };
+        if options.verbosity != Verbosity::Normal && options.verbosity != requested {
+            return Err("--quiet and --verbose cannot be combined".to_string());
+        }
+        options.verbosity = requested;
+    }
+    Ok(options)
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let verbosity = |s: &[&str]| parse_args(&args(s)).map(|options| options.verbosity);
+
+    assert_eq!(verbosity(&["mandelbrot"]), Ok(Verbosity::Normal));
+    assert_eq!(verbosity(&["mandelbrot", "--quiet"]), Ok(Verbosity::Quiet));
+    assert_eq!(verbosity(&["mandelbrot", "--verbose"]), Ok(Verbosity::Verbose));
+    assert!(verbosity(&["mandelbrot", "--quiet", "--verbose"]).is_err());
+    assert!(verbosity(&["mandelbrot", "--loud"]).is_err());
+
+    assert!(!parse_args(&args(&["mandelbrot"])).unwrap().invert);
+    assert_eq!(parse_args(&args(&["mandelbrot", "--invert", "--quiet"])),
+               Ok(Options { verbosity: Verbosity::Quiet, invert: true, max_iter: DEFAULT_MAX_ITER,
+                            solid_guess: false, bounds: Bounds::new(1200, 800).unwrap() }));
+
+    assert_eq!(parse_args(&args(&["mandelbrot"])).unwrap().max_iter, 255);
+    assert_eq!(parse_args(&args(&["mandelbrot", "--max-iter", "1000", "--verbose"])),
+               Ok(Options { verbosity: Verbosity::Verbose, invert: false, max_iter: 1000,
+                            solid_guess: false, bounds: Bounds::new(1200, 800).unwrap() }));
+    assert!(parse_args(&args(&["mandelbrot", "--max-iter", "0"])).is_err());
+    assert!(parse_args(&args(&["mandelbrot", "--max-iter"])).is_err());
+
+    let solid_guess = |s: &[&str]| parse_args(&args(s)).map(|options| options.solid_guess);
+    assert_eq!(solid_guess(&["mandelbrot"]), Ok(false));
+    assert_eq!(solid_guess(&["mandelbrot", "--solid-guess"]), Ok(true));
+    assert_eq!(solid_guess(&["mandelbrot", "--solid-guess", "--no-solid-guess"]), Ok(false));
+    assert_eq!(solid_guess(&["mandelbrot", "--no-solid-guess", "--solid-guess"]), Ok(true));
+
+    let size = |s: &[&str]| parse_args(&args(s)).map(|options| options.bounds.pair());
+    assert_eq!(size(&["mandelbrot", "--size", "300x200"]), Ok((300, 200)));
+    assert!(size(&["mandelbrot", "--size", "0x100"]).is_err());
+    assert!(size(&["mandelbrot", "--size", "300"]).is_err());
+    assert!(size(&["mandelbrot", "--size"]).is_err());
+}
+
 fn main() {
     /*
     let args: Vec<String> = env::args().collect();
@@ -165,17 +612,33 @@ fn main() {
     let lower_right = parse_complex(&args[4])
         .expect("error parsing lower right corner point");
 */
+    let args: Vec<String> = std::env::args().collect();
+    let options = parse_args(&args).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        eprintln!("Usage: {} [--quiet | --verbose] [--invert] [--max-iter N] [--solid-guess | --no-solid-guess] \
+                   [--size WxH]",
+                  args[0]);
+        std::process::exit(1);
+    });
+    let verbosity = options.verbosity;
+
     let start = Instant::now(); // ★ 計測開始
-    let bounds = (1200, 800);
+    let bounds = options.bounds;
     let upper_left = Complex::new(-2.2, 1.2);
     let lower_right = Complex::new(1.0, -1.2);
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let mut pixels = vec![0; bounds.width() * bounds.height()];
 
-    render(&mut pixels, bounds, upper_left, lower_right);
+    render(&mut pixels, bounds, upper_left, lower_right, options.max_iter, options.invert,
+           options.solid_guess);
 //    write_image(&args[1], &pixels, bounds)
-    write_image("mandelbrot.png", &pixels, bounds)
+    write_image("mandelbrot.png", &pixels, bounds.pair())
         .expect("error writing PNG file");
-    let elapsed = start.elapsed(); // ★ 経過時間 
-    println!( "mandelbrot.png を生成しました！\n処理時間: {:.3} 秒", elapsed.as_secs_f64() );
+    let elapsed = start.elapsed(); // ★ 経過時間
+    if verbosity != Verbosity::Quiet {
+        eprintln!("mandelbrot.png を生成しました！");
+    }
+    if verbosity == Verbosity::Verbose {
+        eprintln!("処理時間: {:.3} 秒", elapsed.as_secs_f64());
+    }
 }
 